@@ -0,0 +1,2 @@
+-- Add private flag to links_main, private links are hidden from the recent feed
+ALTER TABLE links_main ADD COLUMN private BOOLEAN NOT NULL DEFAULT false;
@@ -0,0 +1,2 @@
+-- Add admin flag to users_main
+ALTER TABLE users_main ADD COLUMN is_admin BOOLEAN NOT NULL DEFAULT false;
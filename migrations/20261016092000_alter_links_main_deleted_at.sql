@@ -0,0 +1,2 @@
+-- Add soft-delete timestamp to links_main, deleted links answer 410 until cleaned up
+ALTER TABLE links_main ADD COLUMN deleted_at TIMESTAMPTZ;
@@ -0,0 +1,5 @@
+-- Aliases of removed links, kept from being claimed again until the grace period ends
+CREATE TABLE retired_aliases (
+    alias TEXT PRIMARY KEY,
+    retired_until TIMESTAMPTZ NOT NULL
+);
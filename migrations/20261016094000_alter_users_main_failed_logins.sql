@@ -0,0 +1,4 @@
+-- Track failed authentication attempts per user, reset on successful login
+ALTER TABLE users_main
+ADD COLUMN failed_logins INTEGER NOT NULL DEFAULT 0,
+ADD COLUMN last_failed_at TIMESTAMPTZ;
@@ -0,0 +1,4 @@
+-- Add per-link expiry to links_main, links without either fall back to the idle expiry
+ALTER TABLE links_main
+ADD COLUMN expires_at TIMESTAMPTZ,
+ADD COLUMN never_expires BOOLEAN NOT NULL DEFAULT false;
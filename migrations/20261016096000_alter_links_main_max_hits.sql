@@ -0,0 +1,4 @@
+-- Add a hit limit to links_main, hit_count tracks flushed hits towards it
+ALTER TABLE links_main
+ADD COLUMN max_hits BIGINT CHECK (max_hits > 0),
+ADD COLUMN hit_count BIGINT NOT NULL DEFAULT 0;
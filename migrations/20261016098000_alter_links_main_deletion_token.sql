@@ -0,0 +1,3 @@
+-- Add the hashed token letting anonymous creators delete their links
+ALTER TABLE links_main
+ADD COLUMN deletion_token_hash TEXT;
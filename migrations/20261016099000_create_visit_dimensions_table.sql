@@ -0,0 +1,9 @@
+-- Daily visit counts of a link by coarse visit properties, nothing identifying a visitor
+CREATE TABLE visit_dimensions (
+    day DATE NOT NULL,
+    link_id BIGINT NOT NULL REFERENCES links_main(id) ON DELETE CASCADE,
+    dimension TEXT NOT NULL CHECK (dimension IN ('referrer', 'language')),
+    value TEXT NOT NULL,
+    hits BIGINT NOT NULL,
+    PRIMARY KEY (link_id, day, dimension, value)
+);
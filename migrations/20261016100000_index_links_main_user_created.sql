@@ -0,0 +1,2 @@
+-- Pages of a user's links are read newest first by (created_at, id)
+CREATE INDEX links_main_user_created_idx ON links_main (user_id, created_at DESC, id DESC);
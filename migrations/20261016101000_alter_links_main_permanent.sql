@@ -0,0 +1,3 @@
+-- Let links answer with a permanent redirect, browsers may cache it and skip counting visits
+ALTER TABLE links_main
+ADD COLUMN permanent BOOLEAN NOT NULL DEFAULT false;
@@ -0,0 +1,2 @@
+-- Links are listed in the public recent feed only when shortened with public set
+ALTER TABLE links_main ADD COLUMN is_public BOOLEAN NOT NULL DEFAULT false;
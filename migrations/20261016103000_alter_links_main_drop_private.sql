@@ -0,0 +1,3 @@
+-- Visibility is kept in is_public alone, links marked private are never listed
+UPDATE links_main SET is_public = false WHERE private;
+ALTER TABLE links_main DROP COLUMN private;
@@ -0,0 +1,3 @@
+-- Sessions are looked up by the SHA-256 of their id, so reading the table doesn't hand out live sessions
+UPDATE sessions SET id = rtrim(encode(sha256(convert_to(id, 'UTF8')), 'base64'), '=');
+ALTER TABLE sessions RENAME COLUMN id TO id_hash;
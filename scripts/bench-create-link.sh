@@ -0,0 +1,55 @@
+#!/bin/bash
+# Compare the ways of writing a generated link with pgbench
+#
+# Usage: DATABASE_URL=postgres://... scripts/bench-create-link.sh [clients] [seconds]
+# Runs against a scratch table, the links table itself is left alone
+set -euo pipefail
+
+CLIENTS=${1:-16}
+SECONDS_PER_RUN=${2:-10}
+BLOCK=32
+WORK=$(mktemp -d)
+trap 'rm -rf "$WORK"; psql "$DATABASE_URL" -qc "DROP TABLE IF EXISTS bench_links"' EXIT
+
+# Aliases are made up from the id, Sqids encoding happens in the app
+cat >"$WORK/insert_update.sql" <<'SQL'
+BEGIN;
+INSERT INTO bench_links (url) VALUES ('https://example.com') RETURNING id \gset
+UPDATE bench_links SET alias = 'a' || :id WHERE id = :id;
+COMMIT;
+SQL
+
+cat >"$WORK/nextval_insert.sql" <<'SQL'
+SELECT nextval('bench_links_id_seq') AS id \gset
+INSERT INTO bench_links (id, alias, url) VALUES (:id, 'a' || :id, 'https://example.com');
+SQL
+
+# One reservation per block of inserts, ids come from a range no other run touches
+cat >"$WORK/reserve.sql" <<SQL
+SELECT nextval('bench_links_id_seq') FROM generate_series(1, $BLOCK);
+SQL
+cat >"$WORK/reserved_insert.sql" <<'SQL'
+\set id random(1000000000, 9000000000000)
+INSERT INTO bench_links (id, alias, url) VALUES (:id, 'a' || :id, 'https://example.com')
+ON CONFLICT DO NOTHING;
+SQL
+
+run() {
+    psql "$DATABASE_URL" -q <<'SQL'
+SET client_min_messages TO warning;
+DROP TABLE IF EXISTS bench_links;
+CREATE TABLE bench_links (
+    id BIGSERIAL PRIMARY KEY,
+    alias TEXT UNIQUE,
+    url TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+SQL
+    echo "== $1"
+    pgbench -n -c "$CLIENTS" -j "$CLIENTS" -T "$SECONDS_PER_RUN" "${@:2}" "$DATABASE_URL" |
+        grep -E '^(latency average|tps)'
+}
+
+run "insert + update in a transaction" -f "$WORK/insert_update.sql"
+run "nextval + insert" -f "$WORK/nextval_insert.sql"
+run "reserved block + insert" -f "$WORK/reserve.sql@1" -f "$WORK/reserved_insert.sql@$BLOCK"
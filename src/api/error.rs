@@ -1,58 +1,212 @@
+use std::{borrow::Cow, time::Duration};
+
 use axum::{
     Json,
-    http::StatusCode,
+    extract::rejection::{FormRejection, JsonRejection},
+    http::{HeaderValue, StatusCode, header},
     response::{IntoResponse, Response},
 };
 use const_format::formatcp;
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    api::session::SessionError,
-    domain::{Alias, AliasParseError, CredentialsError, UrlParseError, UserName, UserPassword},
+    api::{
+        request_id,
+        session::{ClearSid, SessionError},
+    },
+    domain::{
+        Alias, AliasParseError, CredentialsError, PasswordPolicyError, UrlParseError, UserName,
+    },
     services::{LinkServiceError, ServiceError},
 };
 
+/// `WWW-Authenticate` scheme of password-protected links, answered through the unlock endpoint
+const LINK_PASSWORD_CHALLENGE: &str = "LinkPassword";
+
 pub struct ApiError {
     status_code: StatusCode,
-    reason: &'static str,
+    /// Stable machine-readable code, clients may key translations by it
+    code: &'static str,
+    reason: Cow<'static, str>,
+    clear_session: bool,
+    /// `WWW-Authenticate` challenge of a 401
+    challenge: Option<&'static str>,
+    /// Seconds until a 429 is lifted
+    retry_after: Option<u64>,
 }
 
 #[derive(Deserialize, Serialize)]
-struct ApiErrorBody(&'static str);
+struct ApiErrorBody {
+    code: Cow<'static, str>,
+    /// Human readable, may be reworded at any time
+    message: Cow<'static, str>,
+    /// Id of the request to find its log lines by
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    request_id: Option<String>,
+}
 
 impl ApiError {
-    pub fn public(status_code: StatusCode, reason: &'static str) -> Self {
+    pub fn public(
+        status_code: StatusCode,
+        code: &'static str,
+        reason: impl Into<Cow<'static, str>>,
+    ) -> Self {
         Self {
             status_code,
-            reason,
+            code,
+            reason: reason.into(),
+            clear_session: false,
+            challenge: None,
+            retry_after: None,
         }
     }
 
     pub fn not_found() -> Self {
+        Self::public(StatusCode::NOT_FOUND, "not_found", "Not found")
+    }
+
+    pub fn bad_request() -> Self {
+        Self::public(StatusCode::BAD_REQUEST, "bad_request", "Invalid request")
+    }
+
+    /// The route needs a signed in user
+    pub fn unauthorized() -> Self {
+        Self::public(StatusCode::UNAUTHORIZED, "unauthorized", "Please log in")
+    }
+
+    pub fn forbidden() -> Self {
+        Self::public(StatusCode::FORBIDDEN, "forbidden", "Forbidden")
+    }
+
+    pub fn method_not_allowed() -> Self {
+        Self::public(
+            StatusCode::METHOD_NOT_ALLOWED,
+            "method_not_allowed",
+            "Method not allowed",
+        )
+    }
+
+    pub fn internal() -> Self {
+        Self::public(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "internal",
+            "Internal server error",
+        )
+    }
+
+    /// The session is gone, respond with 401 and have the session middleware drop the cookie
+    pub fn session_ended() -> Self {
         Self {
-            status_code: StatusCode::NOT_FOUND,
-            reason: "Not found",
+            clear_session: true,
+            ..Self::public(
+                StatusCode::UNAUTHORIZED,
+                "session_ended",
+                "Please log in again",
+            )
         }
     }
 
-    pub fn bad_request() -> Self {
+    /// The link is protected and no password was given
+    pub fn password_required() -> Self {
         Self {
-            status_code: StatusCode::BAD_REQUEST,
-            reason: "Invalid request",
+            challenge: Some(LINK_PASSWORD_CHALLENGE),
+            ..Self::public(
+                StatusCode::UNAUTHORIZED,
+                "password_required",
+                "This link is password protected",
+            )
         }
     }
 
-    pub fn internal() -> Self {
+    /// The password given for a protected link doesn't match
+    pub fn wrong_password() -> Self {
         Self {
-            status_code: StatusCode::INTERNAL_SERVER_ERROR,
-            reason: "Internal server error",
+            challenge: Some(LINK_PASSWORD_CHALLENGE),
+            ..Self::public(StatusCode::UNAUTHORIZED, "wrong_password", "Wrong password")
         }
     }
+
+    /// Respond with 429 and a `Retry-After` of at least a second
+    pub fn too_many_requests(
+        code: &'static str,
+        reason: &'static str,
+        retry_after: Duration,
+    ) -> Self {
+        Self {
+            retry_after: Some(retry_after.as_secs_f64().ceil().max(1.0) as u64),
+            ..Self::public(StatusCode::TOO_MANY_REQUESTS, code, reason)
+        }
+    }
+
+    /// Public reason, for reporting errors of individual items
+    pub fn into_reason(self) -> Cow<'static, str> {
+        self.reason
+    }
 }
 
 impl IntoResponse for ApiError {
     fn into_response(self) -> Response {
-        (self.status_code, Json(ApiErrorBody(self.reason))).into_response()
+        let body = ApiErrorBody {
+            code: Cow::Borrowed(self.code),
+            message: self.reason,
+            request_id: request_id::current(),
+        };
+        let mut res = (self.status_code, Json(body)).into_response();
+        if self.clear_session {
+            res.extensions_mut().insert(ClearSid);
+        }
+        if let Some(challenge) = self.challenge {
+            res.headers_mut().insert(
+                header::WWW_AUTHENTICATE,
+                HeaderValue::from_static(challenge),
+            );
+        }
+        if let Some(secs) = self.retry_after {
+            res.headers_mut()
+                .insert(header::RETRY_AFTER, HeaderValue::from(secs));
+        }
+        res
+    }
+}
+
+/// Paths served by another router, answered before the web UI fallback could take them
+pub async fn not_found() -> ApiError {
+    ApiError::not_found()
+}
+
+/// Fallback for routes hit with an unsupported method, axum fills in the `Allow` header
+pub async fn method_not_allowed() -> ApiError {
+    ApiError::method_not_allowed()
+}
+
+/// Code of a request body that couldn't be read, the rejection keeps axum's status
+fn body_rejection_code(status: StatusCode) -> &'static str {
+    match status {
+        StatusCode::UNSUPPORTED_MEDIA_TYPE => "unsupported_media_type",
+        StatusCode::PAYLOAD_TOO_LARGE => "body_too_large",
+        _ => "unreadable_body",
+    }
+}
+
+impl From<JsonRejection> for ApiError {
+    fn from(rejection: JsonRejection) -> Self {
+        let code = match &rejection {
+            JsonRejection::JsonSyntaxError(_) => "malformed_body",
+            JsonRejection::JsonDataError(_) => "invalid_body",
+            _ => body_rejection_code(rejection.status()),
+        };
+        Self::public(rejection.status(), code, rejection.body_text())
+    }
+}
+
+impl From<FormRejection> for ApiError {
+    fn from(rejection: FormRejection) -> Self {
+        let code = match &rejection {
+            FormRejection::FailedToDeserializeForm(_)
+            | FormRejection::FailedToDeserializeFormBody(_) => "invalid_body",
+            _ => body_rejection_code(rejection.status()),
+        };
+        Self::public(rejection.status(), code, rejection.body_text())
     }
 }
 
@@ -60,6 +214,11 @@ impl From<ServiceError> for ApiError {
     fn from(error: ServiceError) -> Self {
         match error {
             ServiceError::LinkServiceError(err) => err.into(),
+            ServiceError::AuthError => Self::public(
+                StatusCode::UNAUTHORIZED,
+                "invalid_credentials",
+                "Invalid username or password",
+            ),
             _ => {
                 // propagated internal errors will be logged here
                 tracing::error!(error = %error, "internal error: ");
@@ -72,39 +231,67 @@ impl From<ServiceError> for ApiError {
 impl From<LinkServiceError> for ApiError {
     fn from(error: LinkServiceError) -> Self {
         match error {
-            LinkServiceError::AlreadyExists => {
-                Self::public(StatusCode::CONFLICT, "This alias already exists")
-            }
+            LinkServiceError::AlreadyExists => Self::public(
+                StatusCode::CONFLICT,
+                "alias_taken",
+                "This alias already exists",
+            ),
             LinkServiceError::NotFound => Self::not_found(),
+            LinkServiceError::Forbidden => Self::forbidden(),
+            LinkServiceError::InvalidToken => Self::public(
+                StatusCode::UNAUTHORIZED,
+                "invalid_deletion_token",
+                "Invalid deletion token",
+            ),
+            LinkServiceError::Permanent => Self::public(
+                StatusCode::CONFLICT,
+                "link_permanent",
+                "Permanent links can't be repointed",
+            ),
         }
     }
 }
 
 impl From<SessionError> for ApiError {
-    fn from(_error: SessionError) -> Self {
-        // TODO: expired session errors might be relevant to user
-        Self::internal()
+    fn from(error: SessionError) -> Self {
+        match error {
+            SessionError::NotExists | SessionError::Expired => Self::session_ended(),
+            SessionError::Storage(e) => {
+                tracing::error!(error = %e, "session storage error");
+                Self::internal()
+            }
+        }
     }
 }
 
 impl From<UrlParseError> for ApiError {
     fn from(error: UrlParseError) -> Self {
         match error {
-            UrlParseError::ContainsUserinfo => {
-                Self::public(StatusCode::BAD_REQUEST, "URL contains credentials")
-            }
-            UrlParseError::WrongScheme(_) => {
-                Self::public(StatusCode::BAD_REQUEST, "This URL scheme is not supported")
-            }
-            UrlParseError::BlockedHost(_) => {
-                Self::public(StatusCode::BAD_REQUEST, "This host is not allowed")
-            }
-            UrlParseError::EmptyHost => {
-                Self::public(StatusCode::BAD_REQUEST, "This URL is incomplete")
-            }
-            UrlParseError::Invalid(_) => {
-                Self::public(StatusCode::BAD_REQUEST, "This URL is invalid")
-            }
+            UrlParseError::ContainsUserinfo => Self::public(
+                StatusCode::BAD_REQUEST,
+                "url_has_credentials",
+                "URL contains credentials",
+            ),
+            UrlParseError::WrongScheme { allowed, .. } => Self::public(
+                StatusCode::BAD_REQUEST,
+                "url_scheme_not_allowed",
+                format!("URL scheme must be one of: {}", allowed.join(", ")),
+            ),
+            UrlParseError::BlockedHost(_) => Self::public(
+                StatusCode::BAD_REQUEST,
+                "url_host_not_allowed",
+                "This host is not allowed",
+            ),
+            UrlParseError::EmptyHost => Self::public(
+                StatusCode::BAD_REQUEST,
+                "url_invalid",
+                "This URL is incomplete",
+            ),
+            UrlParseError::Invalid(_) => Self::public(
+                StatusCode::BAD_REQUEST,
+                "url_invalid",
+                "This URL is invalid",
+            ),
         }
     }
 }
@@ -114,6 +301,7 @@ impl From<AliasParseError> for ApiError {
         match error {
             AliasParseError::TooShort => Self::public(
                 StatusCode::BAD_REQUEST,
+                "alias_too_short",
                 formatcp!(
                     "Chosen link must be at least {} characters",
                     Alias::MIN_ALIAS_LENGTH
@@ -121,6 +309,7 @@ impl From<AliasParseError> for ApiError {
             ),
             AliasParseError::TooLong => Self::public(
                 StatusCode::BAD_REQUEST,
+                "alias_too_long",
                 formatcp!(
                     "Chosen link cannot contain more than {} characters",
                     Alias::MAX_ALIAS_LENGTH
@@ -128,8 +317,14 @@ impl From<AliasParseError> for ApiError {
             ),
             AliasParseError::InvalidCharacters => Self::public(
                 StatusCode::BAD_REQUEST,
+                "alias_invalid",
                 "Chosen link contains invalid characters",
             ),
+            AliasParseError::Reserved => Self::public(
+                StatusCode::BAD_REQUEST,
+                "alias_reserved",
+                "Chosen link is reserved",
+            ),
         }
     }
 }
@@ -139,10 +334,12 @@ impl From<CredentialsError> for ApiError {
         match error {
             CredentialsError::UsernameInvalidChars => ApiError::public(
                 StatusCode::BAD_REQUEST,
+                "username_invalid",
                 "Username contains invalid characters",
             ),
             CredentialsError::UsernameTooShort => ApiError::public(
                 StatusCode::BAD_REQUEST,
+                "username_too_short",
                 formatcp!(
                     "Username must be at least {} characters",
                     UserName::MIN_USERNAME_LENGTH
@@ -150,29 +347,63 @@ impl From<CredentialsError> for ApiError {
             ),
             CredentialsError::UsernameTooLong => ApiError::public(
                 StatusCode::BAD_REQUEST,
+                "username_too_long",
                 formatcp!(
                     "Username cannot be longer than {} characters",
                     UserName::MAX_USERNAME_LENGTH
                 ),
             ),
-            CredentialsError::PasswordInvalidChars => ApiError::public(
+            CredentialsError::Password(err) => err.into(),
+            CredentialsError::PasswordHashUnsupported => ApiError::public(
                 StatusCode::BAD_REQUEST,
-                "Password contains invalid characters",
+                "password_hash_unsupported",
+                "Password hash format is not supported",
             ),
-            CredentialsError::PasswordTooShort => ApiError::public(
-                StatusCode::BAD_REQUEST,
-                formatcp!(
-                    "Password must contain at least {} characters",
-                    UserPassword::MIN_PASSWORD_LENGTH
-                ),
+        }
+    }
+}
+
+impl From<PasswordPolicyError> for ApiError {
+    fn from(error: PasswordPolicyError) -> Self {
+        let (code, reason) = match error {
+            PasswordPolicyError::TooShort(min) => (
+                "password_too_short",
+                format!("Password must contain at least {min} characters"),
             ),
-            CredentialsError::PasswordTooLong => ApiError::public(
-                StatusCode::BAD_REQUEST,
-                formatcp!(
-                    "Password cannot be longer than {} characters",
-                    UserPassword::MAX_PASSWORD_LENGTH
-                ),
+            PasswordPolicyError::TooLong(max) => (
+                "password_too_long",
+                format!("Password cannot be longer than {max} characters"),
+            ),
+            PasswordPolicyError::InvalidChars => (
+                "password_invalid",
+                "Password contains invalid characters".into(),
+            ),
+            PasswordPolicyError::MissingLetter => (
+                "password_missing_letter",
+                "Password must contain a letter".into(),
+            ),
+            PasswordPolicyError::MissingDigit => (
+                "password_missing_digit",
+                "Password must contain a digit".into(),
             ),
+        };
+        ApiError::public(StatusCode::BAD_REQUEST, code, reason)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn ended_session_clears_cookie() {
+        for error in [SessionError::NotExists, SessionError::Expired] {
+            let res = ApiError::from(error).into_response();
+            assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+            assert!(res.extensions().get::<ClearSid>().is_some());
         }
+
+        let res = ApiError::not_found().into_response();
+        assert!(res.extensions().get::<ClearSid>().is_none());
     }
 }
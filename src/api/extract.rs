@@ -1,15 +1,21 @@
 use axum::{
-    extract::FromRequestParts,
-    http::{StatusCode, request::Parts},
-    response::{IntoResponse, Response},
+    Form, Json,
+    extract::{FromRequest, FromRequestParts, Path, Request},
+    http::{header, request::Parts},
 };
 
-use crate::{api::session::SessionId, app::AppState};
+use serde::de::DeserializeOwned;
+
+use crate::{
+    api::{error::ApiError, session::SessionId},
+    app::AppState,
+    domain::Alias,
+};
 
 pub struct RequireUser(pub SessionId);
 
 impl FromRequestParts<AppState> for RequireUser {
-    type Rejection = Response;
+    type Rejection = ApiError;
 
     async fn from_request_parts(parts: &mut Parts, _: &AppState) -> Result<Self, Self::Rejection> {
         parts
@@ -17,7 +23,7 @@ impl FromRequestParts<AppState> for RequireUser {
             .get::<SessionId>()
             .cloned()
             .map(RequireUser)
-            .ok_or_else(|| StatusCode::UNAUTHORIZED.into_response())
+            .ok_or_else(ApiError::unauthorized)
     }
 }
 
@@ -32,3 +38,87 @@ impl FromRequestParts<AppState> for MaybeUser {
         ))
     }
 }
+
+/// Requires a session of an admin user, responds with 403 to other users
+pub struct RequireAdmin;
+
+impl FromRequestParts<AppState> for RequireAdmin {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        app: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        let RequireUser(session_id) = RequireUser::from_request_parts(parts, app).await?;
+
+        let session = app.sessions.get_session_data(&session_id).await?;
+
+        if !session.is_admin {
+            return Err(ApiError::forbidden());
+        }
+
+        Ok(RequireAdmin)
+    }
+}
+
+/// Alias taken from the `{alias}` path segment
+///
+/// Aliases that can't be valid, e.g. over-long ones, can't name an existing link and are rejected with 404.
+pub struct AliasPath(pub Alias);
+
+impl<S: Send + Sync> FromRequestParts<S> for AliasPath {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let Path(alias) = Path::<String>::from_request_parts(parts, state)
+            .await
+            .map_err(|_| ApiError::not_found())?;
+
+        Alias::try_from(alias)
+            .map(AliasPath)
+            .map_err(|_| ApiError::not_found())
+    }
+}
+
+/// JSON body, unreadable ones are rejected with an [`ApiError`] like any other failure
+pub struct JsonBody<T>(pub T);
+
+impl<T, S> FromRequest<S> for JsonBody<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let Json(value) = Json::<T>::from_request(req, state).await?;
+        Ok(JsonBody(value))
+    }
+}
+
+/// Body deserialized from JSON or, if the content type says so, from a urlencoded form
+pub struct JsonOrForm<T>(pub T);
+
+impl<T, S> FromRequest<S> for JsonOrForm<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let is_form = req
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.starts_with("application/x-www-form-urlencoded"));
+
+        if is_form {
+            let Form(value) = Form::<T>::from_request(req, state).await?;
+            Ok(JsonOrForm(value))
+        } else {
+            let Json(value) = Json::<T>::from_request(req, state).await?;
+            Ok(JsonOrForm(value))
+        }
+    }
+}
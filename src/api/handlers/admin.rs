@@ -0,0 +1,156 @@
+use axum::{
+    Json,
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+use crate::{
+    api::{
+        error::ApiError,
+        extract::{AliasPath, JsonBody, RequireAdmin},
+    },
+    app::{AppState, usage_metrics::CategoryUsage},
+    domain::{ImportedPasswordHash, UserId, UserName},
+    services::{self, UserFilter, UserSummary},
+    tasks::maintenance::MaintenanceTask,
+};
+
+const DEFAULT_PAGE_SIZE: i64 = 50;
+const MAX_PAGE_SIZE: i64 = 200;
+
+#[derive(Deserialize)]
+pub struct ListUsersQuery {
+    limit: Option<i64>,
+    /// Id of the last user on the previous page
+    cursor: Option<UserId>,
+    prefix: Option<String>,
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    created_after: Option<OffsetDateTime>,
+}
+
+#[derive(Serialize)]
+pub struct ListUsersResponse {
+    users: Vec<UserSummary>,
+    next_cursor: Option<UserId>,
+}
+
+pub async fn list_users(
+    _: RequireAdmin,
+    State(app): State<AppState>,
+    Query(query): Query<ListUsersQuery>,
+) -> Result<Response, ApiError> {
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .clamp(1, MAX_PAGE_SIZE);
+
+    let filter = UserFilter {
+        username_prefix: query.prefix.as_deref(),
+        created_after: query.created_after,
+    };
+
+    let users = services::admin_list_users(&filter, query.cursor, limit, &app.pool).await?;
+
+    // A full page might have more users after it
+    let next_cursor = match users.last() {
+        Some(last) if users.len() as i64 == limit => Some(last.id),
+        _ => None,
+    };
+
+    Ok((
+        StatusCode::OK,
+        Json(ListUsersResponse { users, next_cursor }),
+    )
+        .into_response())
+}
+
+#[derive(Deserialize)]
+pub struct ImportUserRequest {
+    username: String,
+    password_hash: String,
+}
+
+#[derive(Serialize)]
+pub struct ImportUserResponse {
+    id: UserId,
+    username: String,
+}
+
+pub async fn import_user(
+    _: RequireAdmin,
+    State(app): State<AppState>,
+    JsonBody(ImportUserRequest {
+        username,
+        password_hash,
+    }): JsonBody<ImportUserRequest>,
+) -> Result<Response, ApiError> {
+    let username: UserName = username.try_into()?;
+    let password_hash: ImportedPasswordHash = password_hash.try_into()?;
+
+    let Some(user) = services::import_user(username, password_hash, &app.pool).await? else {
+        return Err(ApiError::public(
+            StatusCode::CONFLICT,
+            "user_exists",
+            "User already exists",
+        ));
+    };
+
+    Ok((
+        StatusCode::CREATED,
+        Json(ImportUserResponse {
+            id: user.id(),
+            username: user.name().to_string(),
+        }),
+    )
+        .into_response())
+}
+
+pub async fn get_link(
+    _: RequireAdmin,
+    State(app): State<AppState>,
+    AliasPath(alias): AliasPath,
+) -> Result<Response, ApiError> {
+    let link = services::admin_get_link(&alias, &app.pool)
+        .await?
+        .ok_or_else(ApiError::not_found)?;
+
+    Ok((StatusCode::OK, Json(link)).into_response())
+}
+
+#[derive(Serialize)]
+pub struct UsageResponse {
+    categories: Vec<CategoryUsage>,
+}
+
+pub async fn usage_summary(_: RequireAdmin, State(app): State<AppState>) -> Response {
+    let categories = app.usage_metrics.usage_summary();
+
+    (StatusCode::OK, Json(UsageResponse { categories })).into_response()
+}
+
+/// Run a maintenance task now instead of waiting for its schedule
+pub async fn run_maintenance(
+    _: RequireAdmin,
+    State(app): State<AppState>,
+    Path(task_name): Path<String>,
+) -> Result<Response, ApiError> {
+    let task = MaintenanceTask::from_name(&task_name).ok_or_else(ApiError::not_found)?;
+
+    let Some(result) = app.maintenance.try_run(task, &app).await else {
+        return Err(ApiError::public(
+            StatusCode::CONFLICT,
+            "task_running",
+            "Task is already running",
+        ));
+    };
+
+    let summary = result.map_err(|e| {
+        tracing::error!(error = %e, task = task.name(), "maintenance task failed");
+        ApiError::internal()
+    })?;
+
+    Ok((StatusCode::OK, Json(summary)).into_response())
+}
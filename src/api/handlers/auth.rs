@@ -9,7 +9,10 @@ use cookie::{Cookie, SameSite};
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    api::{error::ApiError, extract::RequireUser},
+    api::{
+        error::ApiError,
+        extract::{JsonOrForm, RequireUser},
+    },
     app::{AppState, usage_metrics::Category},
     domain::{UserName, UserPassword},
     services,
@@ -32,12 +35,12 @@ impl IntoResponse for AuthResponse {
     }
 }
 
-fn build_cookie_header(sid: &str) -> HeaderValue {
+fn build_cookie_header(sid: &str, secure: bool) -> HeaderValue {
     let cookie = Cookie::build(("sid", sid))
         .path("/")
         .http_only(true)
         .same_site(SameSite::Lax)
-        .secure(false); // no https for now
+        .secure(secure);
 
     HeaderValue::from_str(&cookie.to_string()).expect("Could not build a cookie")
 }
@@ -47,7 +50,7 @@ pub async fn authenticate_session(
     State(app): State<AppState>,
 ) -> Result<Response<Body>, ApiError> {
     app.usage_metrics.log(Category::AuthenticateSession);
-    let session = app.sessions.get_session_data(&session_id)?;
+    let session = app.sessions.get_session_data(&session_id).await?;
 
     Ok(AuthResponse {
         username: session.username.clone(),
@@ -57,52 +60,62 @@ pub async fn authenticate_session(
 
 pub async fn authenticate_user(
     State(app): State<AppState>,
-    Json(AuthRequest { username, password }): Json<AuthRequest>,
+    JsonOrForm(AuthRequest { username, password }): JsonOrForm<AuthRequest>,
 ) -> Result<Response<Body>, ApiError> {
     app.usage_metrics.log(Category::AuthenticateUser);
 
     let username: UserName = username.try_into()?;
-    let password: UserPassword = password.try_into()?;
+    let password = UserPassword::existing(password, &app.config.account_password_policy)?;
 
     let user = services::authenticate_user(username, password, &app.hasher, &app.pool).await?;
 
-    let session_id = app.sessions.new_session(&user);
+    let session_id = app.sessions.new_session(&user).await?;
 
     let mut response = AuthResponse {
         username: user.name().to_string(),
     }
     .into_response();
-    response
-        .headers_mut()
-        .append(header::SET_COOKIE, build_cookie_header(session_id.as_str()));
+    response.headers_mut().append(
+        header::SET_COOKIE,
+        build_cookie_header(session_id.as_str(), app.config.secure_cookies),
+    );
 
     Ok(response)
 }
 
 pub async fn create_user(
     State(app): State<AppState>,
-    Json(AuthRequest { username, password }): Json<AuthRequest>,
+    JsonOrForm(AuthRequest { username, password }): JsonOrForm<AuthRequest>,
 ) -> Result<Response<Body>, ApiError> {
     let username: UserName = username.try_into()?;
-    let password: UserPassword = password.try_into()?;
-
-    let Some(user) = services::create_user(username, password, &app.hasher, &app.pool).await?
+    let password = UserPassword::new(password, &app.config.account_password_policy)?;
+
+    let Some(user) = services::create_user(
+        username,
+        password,
+        app.config.first_user_admin,
+        &app.hasher,
+        &app.pool,
+    )
+    .await?
     else {
         return Err(ApiError::public(
             StatusCode::BAD_REQUEST,
+            "user_exists",
             "User already exists",
         ));
     };
 
-    let session_id = app.sessions.new_session(&user);
+    let session_id = app.sessions.new_session(&user).await?;
 
     let mut response = AuthResponse {
         username: user.name().to_string(),
     }
     .into_response();
-    response
-        .headers_mut()
-        .append(header::SET_COOKIE, build_cookie_header(session_id.as_str()));
+    response.headers_mut().append(
+        header::SET_COOKIE,
+        build_cookie_header(session_id.as_str(), app.config.secure_cookies),
+    );
 
     Ok(response)
 }
@@ -1,50 +1,105 @@
+use std::time::Instant;
+
 use argon2::{PasswordHash, PasswordVerifier};
 use axum::{
     Json,
-    extract::{Path, State},
-    http::StatusCode,
+    extract::{RawQuery, State},
+    http::{HeaderMap, StatusCode, header},
     response::{IntoResponse, Redirect, Response},
 };
+use const_format::formatcp;
 use serde::{Deserialize, Serialize};
 use time::{Duration, OffsetDateTime};
 
 use crate::{
-    api::{error::ApiError, extract::MaybeUser},
-    app::{AppState, CachedLink, usage_metrics::Category},
-    domain::{Alias, Url},
-    services,
+    api::{
+        error::ApiError,
+        extract::{AliasPath, JsonBody, MaybeUser},
+    },
+    app::{
+        AppState, CachedLink,
+        idempotency::{IdempotencyClaim, ShortenOutcome},
+        usage_metrics::Category,
+    },
+    domain::{Alias, Url, UserId},
+    services::{self, LinkOptions},
+    tasks::link_metrics::{LimitedHit, VisitDimension},
 };
 
-// TODO: settings
-pub const EXPIRY_DAYS: i64 = 30;
+use super::interstitial;
+
 pub const UNLOCK_PATH: &str = "unlock";
+/// Upper bound of URLs shortened in a single batch request
+pub const MAX_BATCH_SIZE: usize = 500;
+/// Upper bound of a requested link lifetime
+pub const MAX_EXPIRY_DAYS: i64 = 3650;
 
 #[derive(Serialize, Deserialize)]
 pub struct ShortenRequest {
     pub url: String,
     pub name: Option<String>,
     pub password: Option<String>,
+    /// List the link in the public recent feed
+    #[serde(default)]
+    pub public: bool,
+    /// Days until the link expires regardless of use, 0 means it never expires
+    pub expires_in_days: Option<i64>,
+    /// Number of visits after which the link stops resolving
+    pub max_hits: Option<i64>,
+    /// Redirect with 308 for stable links, browsers may cache it and skip counting visits
+    ///
+    /// Cached redirects can't be taken back, so permanent links have no expiry or visit limit
+    /// and can't be repointed later
+    #[serde(default)]
+    pub permanent: bool,
+    /// Return the user's existing link to the same URL instead of creating one
+    ///
+    /// Only for signed in users asking for a generated alias without a password, expiry, visit limit,
+    /// permanent redirect or public listing
+    #[serde(default)]
+    pub dedup: bool,
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct ShortenResponse {
     pub alias: String,
+    /// Full URL of the link under the configured base URL
+    #[serde(default)]
+    pub short_url: String,
+    /// Only for links created without an account, required to delete them
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deletion_token: Option<String>,
 }
 
 impl IntoResponse for ShortenResponse {
     fn into_response(self) -> Response {
-        (StatusCode::CREATED, Json(self)).into_response()
+        let location = format!("/r/{}", self.alias);
+        (
+            StatusCode::CREATED,
+            [(header::LOCATION, location)],
+            Json(self),
+        )
+            .into_response()
     }
 }
 
-async fn fetch_link(alias: &Alias, app: &AppState) -> Result<CachedLink, ApiError> {
+pub(super) async fn fetch_link(alias: &Alias, app: &AppState) -> Result<CachedLink, ApiError> {
+    // Definitely missing aliases don't need a cache entry nor a DB query
+    if !app.alias_filter.might_contain(alias.as_str()) {
+        app.diag.alias_filter_reject();
+        return Err(ApiError::not_found());
+    }
+
     let link_opt = if let Some(link) = app.cache.get(alias).await {
         app.diag.cache_hit();
         link
     } else {
         app.diag.cache_miss();
         app.cache
-            .try_get_with_by_ref(alias, services::query_url_by_alias(alias, &app.pool))
+            .try_get_with(
+                alias,
+                services::query_url_by_alias(alias, app.metrics.limits_epoch(), &app.pool),
+            )
             .await
             .map_err(|e| {
                 tracing::error!(error = %e, "failed to query the url");
@@ -52,39 +107,206 @@ async fn fetch_link(alias: &Alias, app: &AppState) -> Result<CachedLink, ApiErro
             })?
     };
 
-    let link = link_opt.ok_or_else(ApiError::not_found)?;
+    let Some(link) = link_opt else {
+        // Aliases of removed links stay gone for a while
+        let retired = services::is_alias_retired(alias, &app.pool)
+            .await
+            .map_err(|e| {
+                tracing::error!(error = %e, "failed to query retired aliases");
+                ApiError::internal()
+            })?;
+        if retired {
+            return Err(ApiError::public(
+                StatusCode::GONE,
+                "link_deleted",
+                "The link has been deleted",
+            ));
+        }
+        return Err(ApiError::not_found());
+    };
+
+    if link.deleted {
+        return Err(ApiError::public(
+            StatusCode::GONE,
+            "link_deleted",
+            "The link has been deleted",
+        ));
+    }
+
+    let now = OffsetDateTime::now_utc();
+    let expired = match link.expires_at {
+        _ if link.never_expires => false,
+        Some(expires_at) => expires_at <= now,
+        // Links without an explicit expiry expire after being idle
+        None => {
+            let idle_days = Duration::days(app.config.link_idle_days.into());
+            link.last_seen < now.date().saturating_sub(idle_days)
+        }
+    };
+    if expired {
+        app.expired_links.push(link.id);
+        return Err(ApiError::public(
+            StatusCode::GONE,
+            "link_expired",
+            "The link has expired",
+        ));
+    }
 
-    let today = OffsetDateTime::now_utc().date();
-    if link.last_seen < today.saturating_sub(Duration::days(EXPIRY_DAYS)) {
-        return Err(ApiError::public(StatusCode::GONE, "The link has expired"));
+    if let Some(max_hits) = link.max_hits {
+        if app.metrics.limited_hits(link.id, link.hit_count) >= max_hits {
+            return Err(hits_exhausted());
+        }
     }
 
     Ok(link)
 }
 
+fn hits_exhausted() -> ApiError {
+    ApiError::public(
+        StatusCode::GONE,
+        "link_exhausted",
+        "The link has reached its visit limit",
+    )
+}
+
+/// Count a visit, the limit is checked again since concurrent visits may have used it up
+///
+/// A visit counts only once it resolves to the target, for protected links that is a successful unlock
+async fn record_hit(alias: &Alias, link: &CachedLink, app: &AppState) -> Result<(), ApiError> {
+    let mut reloaded;
+    let mut link = link;
+    loop {
+        let Some(max_hits) = link.max_hits else {
+            app.metrics.record_hit(link.id);
+            return Ok(());
+        };
+
+        match app.metrics.try_record_limited_hit(
+            link.id,
+            link.hit_count,
+            link.limits_epoch,
+            max_hits,
+        ) {
+            LimitedHit::Recorded => return Ok(()),
+            LimitedHit::Exhausted => return Err(hits_exhausted()),
+            // Hits were flushed since the link was cached, count on from the persisted `hit_count`
+            LimitedHit::Stale => {
+                app.cache.invalidate_local(alias).await;
+                reloaded = fetch_link(alias, app).await?;
+                link = &reloaded;
+            }
+        }
+    }
+}
+
+/// Count the visit by referrer host and language, if enabled
+fn record_visit_dimensions(link: &CachedLink, app: &AppState, headers: &HeaderMap) {
+    if !app.config.collect_referrers {
+        return;
+    }
+    if let Some(host) = referrer_host(headers) {
+        app.metrics
+            .record_dimension(link.id, VisitDimension::Referrer, host);
+    }
+    if let Some(language) = primary_language(headers) {
+        app.metrics
+            .record_dimension(link.id, VisitDimension::Language, language);
+    }
+}
+
+/// Host of the referring page, its path and query may identify the visitor
+fn referrer_host(headers: &HeaderMap) -> Option<String> {
+    let referrer = headers.get(header::REFERER)?.to_str().ok()?;
+    let url = url::Url::parse(referrer).ok()?;
+    let host = url.host_str()?;
+    (host.len() <= 253).then(|| host.to_ascii_lowercase())
+}
+
+/// Primary subtag of the first listed language, e.g. `en` for `en-US,en;q=0.9`
+fn primary_language(headers: &HeaderMap) -> Option<String> {
+    let accept = headers.get(header::ACCEPT_LANGUAGE)?.to_str().ok()?;
+    let tag = accept.split([',', ';']).next()?.trim();
+    let primary = tag.split('-').next()?;
+    let valid =
+        (2..=3).contains(&primary.len()) && primary.chars().all(|c| c.is_ascii_alphabetic());
+    valid.then(|| primary.to_ascii_lowercase())
+}
+
+/// Query parameters of our own, never forwarded to the target
+const RESERVED_QUERY_PARAMS: &[&str] = &["password", interstitial::CONFIRM_PARAM];
+
+/// Append query parameters of a visit to the target, after the ones it already has
+///
+/// Parameters are decoded and encoded again, so they aren't encoded twice
+fn forward_query(target: &str, query: &str) -> String {
+    let Ok(mut url) = url::Url::parse(target) else {
+        return target.to_string();
+    };
+
+    let mut pairs = url::form_urlencoded::parse(query.as_bytes())
+        .filter(|(key, _)| !RESERVED_QUERY_PARAMS.contains(&key.as_ref()))
+        .peekable();
+    if pairs.peek().is_none() {
+        return target.to_string();
+    }
+
+    url.query_pairs_mut().extend_pairs(pairs);
+    url.into()
+}
+
+/// Unlock view of the link, on the web UI host when redirects are served on their own port
+fn unlock_location(alias: &Alias, app: &AppState) -> String {
+    let path = format!("/{UNLOCK_PATH}/{}", alias.as_str());
+    match &app.config.web_base_url {
+        Some(web_base_url) => format!("{web_base_url}{path}"),
+        None => path,
+    }
+}
+
 pub async fn redirect(
     State(app): State<AppState>,
-    Path(alias): Path<String>,
-) -> Result<Redirect, ApiError> {
-    let alias: Alias = alias.try_into()?;
+    AliasPath(alias): AliasPath,
+    RawQuery(query): RawQuery,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    app.usage_metrics.log(Category::Redirect);
+
     let link = fetch_link(&alias, &app).await?;
 
-    // Redirect to unlock view if the link is protected
+    // Redirect to unlock view if the link is protected, the hit is counted on unlock
     if link.password_hash.is_some() {
-        return Ok(Redirect::temporary(&format!(
-            "/{UNLOCK_PATH}/{}",
-            alias.as_str()
-        )));
+        return Ok(Redirect::temporary(&unlock_location(&alias, &app)).into_response());
+    }
+
+    let target = match query.as_deref().filter(|_| app.config.forward_query_params) {
+        Some(query) => forward_query(&link.url, query),
+        None => link.url.clone(),
+    };
+
+    // Show the target first if enabled, the hit is counted on the confirmed visit
+    if app.config.interstitial && !interstitial::is_confirmed(query.as_deref()) {
+        return Ok(interstitial::preview(
+            &alias,
+            &target,
+            query.as_deref(),
+            &headers,
+        ));
     }
 
     // Update metrics
-    app.metrics.record_hit(link.id);
+    record_hit(&alias, &link, &app).await?;
+    record_visit_dimensions(&link, &app, &headers);
 
-    Ok(Redirect::temporary(&link.url))
+    if link.permanent {
+        Ok(Redirect::permanent(&target).into_response())
+    } else {
+        Ok(Redirect::temporary(&target).into_response())
+    }
 }
 
 #[derive(Deserialize)]
 pub struct UnlockRequest {
+    #[serde(default)]
     pub password: String,
 }
 
@@ -101,17 +323,29 @@ impl IntoResponse for UnlockResponse {
 
 pub async fn redirect_unlock(
     State(app): State<AppState>,
-    Path(alias): Path<String>,
-    Json(UnlockRequest { password }): Json<UnlockRequest>,
+    AliasPath(alias): AliasPath,
+    JsonBody(UnlockRequest { password }): JsonBody<UnlockRequest>,
 ) -> Result<UnlockResponse, ApiError> {
-    let alias: Alias = alias.try_into()?;
     let link = fetch_link(&alias, &app).await?;
 
-    let Some(password_hash) = link.password_hash else {
+    let Some(password_hash) = &link.password_hash else {
         return Err(ApiError::bad_request());
     };
+    if password.is_empty() {
+        return Err(ApiError::password_required());
+    }
 
-    let parsed_hash = PasswordHash::new(&password_hash).map_err(|e| {
+    // Guessing is throttled per link, whoever makes the attempts
+    let now = Instant::now();
+    if let Err(retry_after) = app.unlock_limiter.check(link.id, now) {
+        return Err(ApiError::too_many_requests(
+            "too_many_attempts",
+            "Too many wrong passwords, try again later",
+            retry_after,
+        ));
+    }
+
+    let parsed_hash = PasswordHash::new(password_hash).map_err(|e| {
         tracing::debug!(error = %e, "password hash parse error");
         ApiError::internal()
     })?;
@@ -121,53 +355,226 @@ pub async fn redirect_unlock(
         .verify_password(password.as_bytes(), &parsed_hash)
         .is_err()
     {
-        return Err(ApiError::public(StatusCode::UNAUTHORIZED, "Wrong password"));
+        app.unlock_limiter.record_failure(link.id, now);
+        return Err(ApiError::wrong_password());
     }
+    app.unlock_limiter.reset(link.id);
 
     // Update metrics
-    app.metrics.record_hit(link.id);
+    record_hit(&alias, &link, &app).await?;
 
     Ok(UnlockResponse { url: link.url })
 }
 
+/// Header making retries of a shorten request return the link of the first one
+///
+/// Only honored for signed in users, anonymous clients can't be told apart and must not
+/// be handed each other's links and deletion tokens
+const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+const MAX_IDEMPOTENCY_KEY_LENGTH: usize = 255;
+
+/// Idempotency key of the request scoped by the user, anonymous requests have none
+fn idempotency_key(
+    headers: &HeaderMap,
+    user_id: Option<UserId>,
+) -> Result<Option<String>, ApiError> {
+    let (Some(user_id), Some(value)) = (user_id, headers.get(IDEMPOTENCY_KEY_HEADER)) else {
+        return Ok(None);
+    };
+
+    let key = value
+        .to_str()
+        .ok()
+        .filter(|key| (1..=MAX_IDEMPOTENCY_KEY_LENGTH).contains(&key.len()))
+        .ok_or_else(|| {
+            ApiError::public(
+                StatusCode::BAD_REQUEST,
+                "idempotency_key_invalid",
+                formatcp!(
+                    "Idempotency key must be 1 to {MAX_IDEMPOTENCY_KEY_LENGTH} visible characters"
+                ),
+            )
+        })?;
+
+    Ok(Some(format!("user:{user_id}:{key}")))
+}
+
 pub async fn shorten(
     MaybeUser(session_id_opt): MaybeUser,
     State(app): State<AppState>,
-    Json(ShortenRequest {
-        url,
-        name,
-        password,
-    }): Json<ShortenRequest>,
+    headers: HeaderMap,
+    JsonBody(request): JsonBody<ShortenRequest>,
 ) -> Result<ShortenResponse, ApiError> {
     app.usage_metrics.log(Category::Shorten);
 
-    let url: Url = url.try_into()?;
-
     let mut user_id = None;
 
     if let Some(session_id) = session_id_opt {
-        let session = app.sessions.get_session_data(&session_id)?;
+        let session = app.sessions.get_session_data(&session_id).await?;
         user_id = Some(session.user_id);
     }
 
-    let password_ref = password.as_deref();
+    let Some(key) = idempotency_key(&headers, user_id)? else {
+        return create_short_link(request, user_id, &app).await;
+    };
+
+    match app.idempotency.claim(&key) {
+        IdempotencyClaim::New => {}
+        IdempotencyClaim::InFlight => {
+            return Err(ApiError::public(
+                StatusCode::CONFLICT,
+                "idempotency_key_in_use",
+                "A request with this idempotency key is in progress",
+            ));
+        }
+        IdempotencyClaim::Full => {
+            return Err(ApiError::public(
+                StatusCode::SERVICE_UNAVAILABLE,
+                "idempotency_unavailable",
+                "Too many idempotency keys are in use, try again later",
+            ));
+        }
+        // Replay the link of the first request
+        IdempotencyClaim::Done(outcome) => {
+            if outcome.url != request.url {
+                return Err(ApiError::public(
+                    StatusCode::UNPROCESSABLE_ENTITY,
+                    "idempotency_key_reused",
+                    "Idempotency key was already used for another URL",
+                ));
+            }
+            return Ok(ShortenResponse {
+                short_url: app.config.short_url(&outcome.alias),
+                alias: outcome.alias,
+                deletion_token: None,
+            });
+        }
+    }
+
+    let url = request.url.clone();
+    let result = create_short_link(request, user_id, &app).await;
+    match &result {
+        Ok(response) => app.idempotency.complete(
+            &key,
+            ShortenOutcome {
+                url,
+                alias: response.alias.clone(),
+            },
+        ),
+        Err(_) => app.idempotency.release(&key),
+    }
+    result
+}
+
+async fn create_short_link(
+    ShortenRequest {
+        url,
+        name,
+        password,
+        public,
+        expires_in_days,
+        max_hits,
+        permanent,
+        dedup,
+    }: ShortenRequest,
+    user_id: Option<UserId>,
+    app: &AppState,
+) -> Result<ShortenResponse, ApiError> {
+    let url = Url::parse_with_policy(url, &app.config.url_policy)?;
+
+    // Empty password means the link is not protected
+    let password_ref = password.as_deref().filter(|p| !p.is_empty());
+    if let Some(password) = password_ref {
+        app.config.link_password_policy.check(password)?;
+    }
+
+    let (expires_at, never_expires) = match expires_in_days {
+        None => (None, false),
+        Some(0) => (None, true),
+        Some(days @ 1..=MAX_EXPIRY_DAYS) => (
+            Some(OffsetDateTime::now_utc() + Duration::days(days)),
+            false,
+        ),
+        Some(_) => {
+            return Err(ApiError::public(
+                StatusCode::BAD_REQUEST,
+                "expiry_invalid",
+                formatcp!("Expiry must be between 0 and {MAX_EXPIRY_DAYS} days"),
+            ));
+        }
+    };
+
+    if max_hits.is_some_and(|max_hits| max_hits < 1) {
+        return Err(ApiError::public(
+            StatusCode::BAD_REQUEST,
+            "max_hits_invalid",
+            "Visit limit must be at least 1",
+        ));
+    }
+
+    if permanent && (expires_at.is_some() || max_hits.is_some()) {
+        return Err(ApiError::public(
+            StatusCode::BAD_REQUEST,
+            "permanent_conflict",
+            "Permanent links can't expire or have a visit limit",
+        ));
+    }
+
+    // Links with options of their own are always new
+    let plain = name.is_none()
+        && password_ref.is_none()
+        && expires_in_days.is_none()
+        && max_hits.is_none()
+        && !permanent
+        && !public;
+    let existing = match user_id {
+        Some(user_id) if dedup && plain => {
+            services::find_user_link_by_url(&user_id, &url, app.config.link_idle_days, &app.pool)
+                .await?
+        }
+        _ => None,
+    };
+    if let Some(alias) = existing {
+        return Ok(ShortenResponse {
+            short_url: app.config.short_url(&alias),
+            alias,
+            deletion_token: None,
+        });
+    }
+
+    // Links of users are deleted through their account
+    let deletion_token = user_id.is_none().then(services::generate_deletion_token);
+
+    let options = LinkOptions {
+        user_id,
+        password: password_ref,
+        public,
+        expires_at,
+        never_expires,
+        max_hits,
+        deletion_token: deletion_token.as_deref(),
+        permanent,
+    };
 
     match name {
         // If request contains an alias, validate and save it
         Some(alias_str) => {
             let alias: Alias = alias_str.try_into()?;
+            app.config.check_alias(&alias)?;
 
-            let result = services::create_link_with_alias(
-                &url,
-                &alias,
-                &app.pool,
-                user_id,
-                password_ref,
-                &app.hasher,
-            )
-            .await?;
+            let result =
+                services::create_link_with_alias(&url, &alias, &app.pool, &options, &app.hasher)
+                    .await?;
+
+            app.alias_filter.insert(&result);
+            // Instances may have cached the alias as missing
+            app.cache.invalidate(&alias).await;
 
-            Ok(ShortenResponse { alias: result })
+            Ok(ShortenResponse {
+                short_url: app.config.short_url(&result),
+                alias: result,
+                deletion_token,
+            })
         }
 
         // If request does not contain an alias, generate a new one
@@ -175,22 +582,144 @@ pub async fn shorten(
             let alias = services::create_link(
                 &url,
                 &app.sqids,
+                &app.link_ids,
                 &app.pool,
-                user_id,
-                password_ref,
+                &options,
                 &app.hasher,
             )
             .await?;
 
-            Ok(ShortenResponse { alias })
+            app.alias_filter.insert(&alias);
+            app.check_sqids_capacity(&alias);
+
+            Ok(ShortenResponse {
+                short_url: app.config.short_url(&alias),
+                alias,
+                deletion_token,
+            })
         }
     }
 }
 
-pub async fn recently_added_links(State(app): State<AppState>) -> Result<Response, ApiError> {
+#[derive(Deserialize)]
+pub struct RemoveLinkRequest {
+    pub token: String,
+}
+
+pub async fn remove_link(
+    State(app): State<AppState>,
+    AliasPath(alias): AliasPath,
+    JsonBody(RemoveLinkRequest { token }): JsonBody<RemoveLinkRequest>,
+) -> Result<Response, ApiError> {
+    services::remove_link_with_token(&alias, &token, &app.pool).await?;
+
+    // Drop the cached entry so the link answers 410 right away
+    app.cache.invalidate(&alias).await;
+
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+#[derive(Deserialize)]
+pub struct BatchShortenRequest {
+    pub urls: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum BatchShortenItem {
+    Created {
+        url: String,
+        alias: String,
+        /// Only for links created without an account, required to delete them
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        deletion_token: Option<String>,
+    },
+    Failed {
+        url: String,
+        error: String,
+    },
+}
+
+pub async fn shorten_batch(
+    MaybeUser(session_id_opt): MaybeUser,
+    State(app): State<AppState>,
+    JsonBody(BatchShortenRequest { urls }): JsonBody<BatchShortenRequest>,
+) -> Result<Response, ApiError> {
+    app.usage_metrics.log(Category::Shorten);
+
+    if urls.len() > MAX_BATCH_SIZE {
+        return Err(ApiError::public(
+            StatusCode::BAD_REQUEST,
+            "batch_too_large",
+            formatcp!("Batch cannot contain more than {MAX_BATCH_SIZE} URLs"),
+        ));
+    }
+
+    let user_id = match session_id_opt {
+        Some(session_id) => Some(app.sessions.get_session_data(&session_id).await?.user_id),
+        None => None,
+    };
+
+    let parsed: Vec<Result<Url, ApiError>> = urls
+        .iter()
+        .map(|url| {
+            Url::parse_with_policy(url.clone(), &app.config.url_policy).map_err(ApiError::from)
+        })
+        .collect();
+    let valid: Vec<Url> = parsed
+        .iter()
+        .filter_map(|r| r.as_ref().ok())
+        .cloned()
+        .collect();
+
+    let links = services::create_links_batch(&valid, user_id, &app.sqids, &app.pool).await?;
+
+    for link in &links {
+        app.alias_filter.insert(&link.alias);
+    }
+    if let Some(link) = links.last() {
+        app.check_sqids_capacity(&link.alias);
+    }
+
+    // Valid URLs take the links in order, there must be exactly one for each
+    let mut created = links.into_iter();
+    let items: Option<Vec<BatchShortenItem>> = urls
+        .into_iter()
+        .zip(parsed)
+        .map(|(url, result)| match result {
+            Ok(_) => created.next().map(|link| BatchShortenItem::Created {
+                url,
+                alias: link.alias,
+                deletion_token: link.deletion_token,
+            }),
+            Err(e) => Some(BatchShortenItem::Failed {
+                url,
+                error: e.into_reason().into_owned(),
+            }),
+        })
+        .collect();
+    let items = items.filter(|_| created.next().is_none()).ok_or_else(|| {
+        tracing::error!("batch insert created a different number of links than valid URLs");
+        ApiError::internal()
+    })?;
+
+    Ok((StatusCode::OK, Json(items)).into_response())
+}
+
+/// Latest links of the signed in user, or the latest public links for anyone else
+pub async fn recently_added_links(
+    MaybeUser(session_id_opt): MaybeUser,
+    State(app): State<AppState>,
+) -> Result<Response, ApiError> {
     app.usage_metrics.log(Category::RecentlyAdded);
 
-    let links = services::recently_added_links(10, &app.pool).await?;
+    let links = match session_id_opt {
+        Some(session_id) => {
+            let session = app.sessions.get_session_data(&session_id).await?;
+            services::recently_added_user_links(&session.user_id, 10, &app.pool).await?
+        }
+        None => services::recently_added_links(10, &app.pool).await?,
+    };
 
     Ok((StatusCode::OK, Json(links)).into_response())
 }
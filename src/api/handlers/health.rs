@@ -0,0 +1,43 @@
+use std::time::Duration;
+
+use axum::{Json, extract::State, http::StatusCode};
+use serde::Serialize;
+use tokio::time::timeout;
+
+use crate::{api::error::ApiError, app::AppState};
+
+/// Readiness fails if the database doesn't answer within this time
+const READY_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Serialize)]
+pub struct HealthResponse {
+    pub status: &'static str,
+}
+
+/// Liveness probe, the process is up if it can answer
+pub async fn health() -> Json<HealthResponse> {
+    Json(HealthResponse { status: "ok" })
+}
+
+/// Readiness probe, the app can serve requests only while the database is reachable
+pub async fn ready(State(app): State<AppState>) -> Result<Json<HealthResponse>, ApiError> {
+    let unavailable = || {
+        ApiError::public(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "database_unavailable",
+            "Database is unreachable",
+        )
+    };
+
+    match timeout(READY_TIMEOUT, sqlx::query("SELECT 1").execute(&app.pool)).await {
+        Ok(Ok(_)) => Ok(Json(HealthResponse { status: "ok" })),
+        Ok(Err(e)) => {
+            tracing::warn!(error = %e, "readiness check failed");
+            Err(unavailable())
+        }
+        Err(_) => {
+            tracing::warn!("readiness check timed out");
+            Err(unavailable())
+        }
+    }
+}
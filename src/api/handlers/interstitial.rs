@@ -0,0 +1,118 @@
+use axum::{
+    Json,
+    http::{HeaderMap, StatusCode, header},
+    response::{Html, IntoResponse, Response},
+};
+use serde::Serialize;
+
+use crate::domain::Alias;
+
+/// Query parameter confirming the visit of a previewed link
+pub(super) const CONFIRM_PARAM: &str = "go";
+
+#[derive(Serialize)]
+pub struct PreviewResponse {
+    pub target: String,
+}
+
+/// Whether the visitor confirmed the navigation with `?go=1`
+pub(super) fn is_confirmed(query: Option<&str>) -> bool {
+    let Some(query) = query else {
+        return false;
+    };
+    url::form_urlencoded::parse(query.as_bytes())
+        .any(|(key, value)| key == CONFIRM_PARAM && value == "1")
+}
+
+/// Page naming the target before going there, JSON for clients asking for it
+pub(super) fn preview(
+    alias: &Alias,
+    target: &str,
+    query: Option<&str>,
+    headers: &HeaderMap,
+) -> Response {
+    let wants_json = headers
+        .get(header::ACCEPT)
+        .and_then(|accept| accept.to_str().ok())
+        .is_some_and(|accept| accept.contains("application/json"));
+
+    let response = if wants_json {
+        Json(PreviewResponse {
+            target: target.to_string(),
+        })
+        .into_response()
+    } else {
+        // Parameters of the visit are kept for the confirmed one
+        let confirm_url = match query.filter(|query| !query.is_empty()) {
+            Some(query) => format!("/r/{}?{query}&{CONFIRM_PARAM}=1", alias.as_str()),
+            None => format!("/r/{}?{CONFIRM_PARAM}=1", alias.as_str()),
+        };
+        Html(render_page(target, &confirm_url)).into_response()
+    };
+
+    // Previews must not stand in for the confirmation on a later visit
+    (
+        StatusCode::OK,
+        [(header::CACHE_CONTROL, "no-store")],
+        response,
+    )
+        .into_response()
+}
+
+fn render_page(target: &str, confirm_url: &str) -> String {
+    let target = escape_html(target);
+    let confirm_url = escape_html(confirm_url);
+    format!(
+        r#"<!doctype html>
+<html lang="en">
+<head>
+  <meta charset="UTF-8" />
+  <meta name="viewport" content="width=device-width, initial-scale=1.0" />
+  <meta name="robots" content="noindex" />
+  <title>Leaving url-shorten</title>
+</head>
+<body>
+  <p>You are being redirected to</p>
+  <p><code>{target}</code></p>
+  <p><a href="{confirm_url}" rel="noreferrer">Continue</a></p>
+</body>
+</html>
+"#
+    )
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn confirmation_needs_go_param() {
+        assert!(is_confirmed(Some("go=1")));
+        assert!(is_confirmed(Some("utm_source=mail&go=1")));
+        assert!(!is_confirmed(Some("go=0")));
+        assert!(!is_confirmed(Some("utm_source=go")));
+        assert!(!is_confirmed(None));
+    }
+
+    #[test]
+    fn target_is_escaped() {
+        let page = render_page("https://example.com/?a=1&b=\"<script>", "/r/abcdef?go=1");
+        assert!(page.contains("https://example.com/?a=1&amp;b=&quot;&lt;script&gt;"));
+        assert!(!page.contains("<script>"));
+    }
+}
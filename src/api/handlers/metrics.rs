@@ -0,0 +1,147 @@
+use std::fmt::{Display, Write};
+
+use axum::{
+    extract::State,
+    http::header::CONTENT_TYPE,
+    response::{IntoResponse, Response},
+};
+
+use crate::app::{AppState, usage_metrics::Category};
+
+const CONTENT_TYPE_TEXT: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// Writer of the Prometheus text exposition format
+#[derive(Default)]
+struct Encoder {
+    out: String,
+}
+
+impl Encoder {
+    fn family(&mut self, name: &str, kind: &str, help: &str) -> &mut Self {
+        // Writing into a String can't fail
+        let _ = writeln!(self.out, "# HELP {name} {help}");
+        let _ = writeln!(self.out, "# TYPE {name} {kind}");
+        self
+    }
+
+    fn sample(&mut self, name: &str, labels: &[(&str, &str)], value: impl Display) -> &mut Self {
+        self.out.push_str(name);
+        if !labels.is_empty() {
+            let labels: Vec<String> = labels
+                .iter()
+                .map(|(key, value)| format!("{key}=\"{value}\""))
+                .collect();
+            let _ = write!(self.out, "{{{}}}", labels.join(","));
+        }
+        let _ = writeln!(self.out, " {value}");
+        self
+    }
+}
+
+/// Prometheus scrape target, only routed when metrics are enabled
+pub async fn metrics(State(app): State<AppState>) -> Response {
+    let mut enc = Encoder::default();
+
+    enc.family(
+        "url_shorten_requests_total",
+        "counter",
+        "Requests handled, by usage category",
+    );
+    for cat in Category::ALL {
+        enc.sample(
+            "url_shorten_requests_total",
+            &[("category", cat.as_str())],
+            app.usage_metrics.total_usage_in(cat),
+        );
+    }
+
+    let (cache_hits, cache_misses, alias_filter_rejects, _) = app.diag.snapshot();
+    let lookups = cache_hits + cache_misses;
+    let hit_ratio = if lookups == 0 {
+        0.0
+    } else {
+        cache_hits as f64 / lookups as f64
+    };
+    enc.family(
+        "url_shorten_cache_hits_total",
+        "counter",
+        "Alias lookups answered by the link cache",
+    )
+    .sample("url_shorten_cache_hits_total", &[], cache_hits)
+    .family(
+        "url_shorten_cache_misses_total",
+        "counter",
+        "Alias lookups that went to the database",
+    )
+    .sample("url_shorten_cache_misses_total", &[], cache_misses)
+    .family(
+        "url_shorten_cache_hit_ratio",
+        "gauge",
+        "Share of alias lookups answered by the link cache",
+    )
+    .sample("url_shorten_cache_hit_ratio", &[], hit_ratio)
+    .family(
+        "url_shorten_alias_filter_rejects_total",
+        "counter",
+        "Lookups of missing aliases rejected by the alias filter",
+    )
+    .sample(
+        "url_shorten_alias_filter_rejects_total",
+        &[],
+        alias_filter_rejects,
+    );
+
+    let size = app.pool.size();
+    let idle = app.pool.num_idle() as u32;
+    enc.family(
+        "url_shorten_db_pool_connections",
+        "gauge",
+        "Open database connections, by state",
+    )
+    .sample(
+        "url_shorten_db_pool_connections",
+        &[("state", "active")],
+        size.saturating_sub(idle),
+    )
+    .sample(
+        "url_shorten_db_pool_connections",
+        &[("state", "idle")],
+        idle,
+    )
+    .family(
+        "url_shorten_db_pool_max_connections",
+        "gauge",
+        "Connection limit of the database pool",
+    )
+    .sample(
+        "url_shorten_db_pool_max_connections",
+        &[],
+        app.pool.options().get_max_connections(),
+    );
+
+    let (batches, entries, last_entries) = app.metrics.flushes().snapshot();
+    enc.family(
+        "url_shorten_hit_flush_batches_total",
+        "counter",
+        "Batches of link hits written to the database",
+    )
+    .sample("url_shorten_hit_flush_batches_total", &[], batches)
+    .family(
+        "url_shorten_hit_flush_entries_total",
+        "counter",
+        "Links updated by hit batches",
+    )
+    .sample("url_shorten_hit_flush_entries_total", &[], entries)
+    .family(
+        "url_shorten_hit_flush_last_batch_entries",
+        "gauge",
+        "Links updated by the latest hit batch",
+    )
+    .sample(
+        "url_shorten_hit_flush_last_batch_entries",
+        &[],
+        last_entries,
+    );
+
+    ([(CONTENT_TYPE, CONTENT_TYPE_TEXT)], enc.out).into_response()
+}
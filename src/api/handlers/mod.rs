@@ -1,10 +1,19 @@
+mod admin;
 mod auth;
 mod core;
+mod health;
+mod interstitial;
+mod metrics;
+mod qr;
 mod user;
 
+pub(crate) use admin::*;
 pub(crate) use auth::*;
 pub(crate) use core::*;
+pub(crate) use health::*;
+pub(crate) use metrics::*;
+pub(crate) use qr::*;
 pub(crate) use user::*;
 
 pub use core::ShortenResponse;
-pub use core::{EXPIRY_DAYS, UNLOCK_PATH};
+pub use core::UNLOCK_PATH;
@@ -0,0 +1,116 @@
+use axum::{
+    extract::{Query, State},
+    http::{StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use qrcode::{Color, QrCode, render::svg};
+use serde::Deserialize;
+
+use crate::{
+    api::{error::ApiError, extract::AliasPath},
+    app::AppState,
+};
+
+use super::{UNLOCK_PATH, core::fetch_link};
+
+const DEFAULT_SIZE: u32 = 256;
+const MIN_SIZE: u32 = 64;
+const MAX_SIZE: u32 = 2048;
+/// Light modules around the code required by scanners
+const QUIET_ZONE: u32 = 4;
+
+#[derive(Deserialize, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum QrFormat {
+    #[default]
+    Svg,
+    Png,
+}
+
+#[derive(Deserialize)]
+pub struct QrQuery {
+    #[serde(default)]
+    format: QrFormat,
+    /// Minimum width and height in pixels
+    size: Option<u32>,
+}
+
+pub async fn redirect_qr(
+    State(app): State<AppState>,
+    AliasPath(alias): AliasPath,
+    Query(QrQuery { format, size }): Query<QrQuery>,
+) -> Result<Response, ApiError> {
+    // Looked up only to 404 on missing links, rendering a code isn't a hit
+    let link = fetch_link(&alias, &app).await?;
+
+    // Protected links send scanners to the unlock view, never straight to the target
+    let short_url = if link.password_hash.is_some() {
+        format!("{}/{UNLOCK_PATH}/{}", app.config.web_url(), alias.as_str())
+    } else {
+        app.config.short_url(alias.as_str())
+    };
+
+    let code = QrCode::new(short_url.as_bytes()).map_err(|e| {
+        tracing::error!(error = %e, "failed to encode QR code");
+        ApiError::internal()
+    })?;
+
+    let size = size.unwrap_or(DEFAULT_SIZE).clamp(MIN_SIZE, MAX_SIZE);
+
+    let response = match format {
+        QrFormat::Svg => {
+            let image = code
+                .render::<svg::Color>()
+                .min_dimensions(size, size)
+                .quiet_zone(true)
+                .build();
+            (
+                StatusCode::OK,
+                [(header::CONTENT_TYPE, "image/svg+xml")],
+                image,
+            )
+                .into_response()
+        }
+        QrFormat::Png => {
+            let image = render_png(&code, size).map_err(|e| {
+                tracing::error!(error = %e, "failed to encode QR code png");
+                ApiError::internal()
+            })?;
+            (StatusCode::OK, [(header::CONTENT_TYPE, "image/png")], image).into_response()
+        }
+    };
+
+    Ok(response)
+}
+
+/// Render the code as a grayscale PNG at least `size` pixels wide
+fn render_png(code: &QrCode, size: u32) -> Result<Vec<u8>, png::EncodingError> {
+    let modules = code.width() as u32;
+    let total = modules + 2 * QUIET_ZONE;
+    let scale = size.div_ceil(total);
+    let dimension = total * scale;
+
+    let colors = code.to_colors();
+    let mut pixels = vec![u8::MAX; (dimension * dimension) as usize];
+    for (idx, color) in colors.iter().enumerate() {
+        if *color != Color::Dark {
+            continue;
+        }
+        let x0 = (idx as u32 % modules + QUIET_ZONE) * scale;
+        let y0 = (idx as u32 / modules + QUIET_ZONE) * scale;
+        for y in y0..y0 + scale {
+            let row = (y * dimension) as usize;
+            pixels[row + x0 as usize..row + (x0 + scale) as usize].fill(0);
+        }
+    }
+
+    let mut image = Vec::new();
+    let mut encoder = png::Encoder::new(&mut image, dimension, dimension);
+    encoder.set_color(png::ColorType::Grayscale);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&pixels)?;
+    writer.finish()?;
+
+    Ok(image)
+}
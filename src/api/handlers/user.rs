@@ -1,47 +1,401 @@
 use axum::{
     Json,
-    extract::{Path, State},
-    http::StatusCode,
+    body::Body,
+    extract::{Query, State},
+    http::{StatusCode, header},
     response::{IntoResponse, Response},
 };
+use const_format::formatcp;
+use futures_util::stream;
+use serde::{Deserialize, Serialize};
 
 use crate::{
-    api::{error::ApiError, extract::RequireUser, session::ClearSid},
-    app::AppState,
-    domain::Alias,
-    services::{self, query_links_by_user_id},
+    api::{
+        error::ApiError,
+        extract::{AliasPath, JsonBody, RequireUser},
+        handlers::{BatchShortenItem, MAX_BATCH_SIZE},
+        session::ClearSid,
+    },
+    app::{AppState, usage_metrics::Category},
+    domain::{Alias, Url, UserId},
+    services::{self, ExportLink, LinkCursor, LinkOptions, ServiceError, query_links_by_user_id},
 };
 
+/// Links per page of the user's list unless requested otherwise
+const DEFAULT_PAGE_SIZE: i64 = 50;
+const MAX_PAGE_SIZE: i64 = 200;
+
+#[derive(Deserialize)]
+pub struct ListLinksQuery {
+    pub limit: Option<i64>,
+    pub cursor: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct SearchLinksQuery {
+    pub q: String,
+    pub limit: Option<i64>,
+    pub cursor: Option<String>,
+}
+
+fn page_params(
+    limit: Option<i64>,
+    cursor: Option<String>,
+) -> Result<(i64, Option<LinkCursor>), ApiError> {
+    let limit = limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+    let cursor = cursor
+        .map(|cursor| cursor.parse::<LinkCursor>())
+        .transpose()
+        .map_err(|_| {
+            ApiError::public(StatusCode::BAD_REQUEST, "cursor_invalid", "Invalid cursor")
+        })?;
+    Ok((limit, cursor))
+}
+
 pub async fn list_user_links(
     RequireUser(session_id): RequireUser,
     State(app): State<AppState>,
+    Query(ListLinksQuery { limit, cursor }): Query<ListLinksQuery>,
 ) -> Result<Response, ApiError> {
-    let session = app.sessions.get_session_data(&session_id)?;
-    let links = query_links_by_user_id(&session.user_id, &app.pool).await?;
+    let (limit, cursor) = page_params(limit, cursor)?;
 
-    Ok((StatusCode::OK, Json(links)).into_response())
+    let session = app.sessions.get_session_data(&session_id).await?;
+    let page = query_links_by_user_id(&session.user_id, None, limit, cursor, &app.pool).await?;
+
+    Ok((StatusCode::OK, Json(page)).into_response())
 }
 
-pub async fn remove_user_link(
+/// Find user's links by alias prefix or URL substring, paginated like the list
+pub async fn search_user_links(
     RequireUser(session_id): RequireUser,
     State(app): State<AppState>,
-    Path(alias): Path<String>,
+    Query(SearchLinksQuery { q, limit, cursor }): Query<SearchLinksQuery>,
 ) -> Result<Response, ApiError> {
-    let alias: Alias = alias.try_into()?;
+    let (limit, cursor) = page_params(limit, cursor)?;
+
+    let session = app.sessions.get_session_data(&session_id).await?;
+    let page =
+        query_links_by_user_id(&session.user_id, Some(q.trim()), limit, cursor, &app.pool).await?;
 
-    let session = app.sessions.get_session_data(&session_id)?;
+    Ok((StatusCode::OK, Json(page)).into_response())
+}
+
+pub async fn remove_user_link(
+    RequireUser(session_id): RequireUser,
+    State(app): State<AppState>,
+    AliasPath(alias): AliasPath,
+) -> Result<Response, ApiError> {
+    let session = app.sessions.get_session_data(&session_id).await?;
     services::remove_user_link(&session.user_id, &alias, &app.pool).await?;
 
+    // Drop the cached entry so the link answers 410 right away
+    app.cache.invalidate(&alias).await;
+
     Ok(StatusCode::NO_CONTENT.into_response())
 }
 
+#[derive(Deserialize)]
+pub struct UpdateLinkRequest {
+    pub url: String,
+}
+
+pub async fn update_user_link(
+    RequireUser(session_id): RequireUser,
+    State(app): State<AppState>,
+    AliasPath(alias): AliasPath,
+    JsonBody(UpdateLinkRequest { url }): JsonBody<UpdateLinkRequest>,
+) -> Result<Response, ApiError> {
+    let url = Url::parse_with_policy(url, &app.config.url_policy)?;
+
+    let session = app.sessions.get_session_data(&session_id).await?;
+    let link = services::update_user_link(&session.user_id, &alias, &url, &app.pool).await?;
+
+    // Drop the cached entry so redirects pick up the new destination
+    app.cache.invalidate(&alias).await;
+
+    Ok((StatusCode::OK, Json(link)).into_response())
+}
+
+#[derive(Deserialize)]
+pub struct UpdateLinkPasswordRequest {
+    /// New password, a missing or empty one removes the protection
+    pub password: Option<String>,
+}
+
+pub async fn update_user_link_password(
+    RequireUser(session_id): RequireUser,
+    State(app): State<AppState>,
+    AliasPath(alias): AliasPath,
+    JsonBody(UpdateLinkPasswordRequest { password }): JsonBody<UpdateLinkPasswordRequest>,
+) -> Result<Response, ApiError> {
+    let password = password.as_deref().filter(|p| !p.is_empty());
+    if let Some(password) = password {
+        app.config.link_password_policy.check(password)?;
+    }
+
+    let session = app.sessions.get_session_data(&session_id).await?;
+    services::update_link_password(&session.user_id, &alias, password, &app.hasher, &app.pool)
+        .await?;
+
+    // Drop the cached entry so redirects pick up the new protection right away
+    app.cache.invalidate(&alias).await;
+
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+pub async fn user_link_stats(
+    RequireUser(session_id): RequireUser,
+    State(app): State<AppState>,
+    AliasPath(alias): AliasPath,
+) -> Result<Response, ApiError> {
+    let session = app.sessions.get_session_data(&session_id).await?;
+    let stats = services::query_link_stats(&session.user_id, &alias, &app.pool).await?;
+
+    Ok((StatusCode::OK, Json(stats)).into_response())
+}
+
+pub async fn touch_user_link(
+    RequireUser(session_id): RequireUser,
+    State(app): State<AppState>,
+    AliasPath(alias): AliasPath,
+) -> Result<Response, ApiError> {
+    let session = app.sessions.get_session_data(&session_id).await?;
+    services::touch_user_link(&session.user_id, &alias, &app.pool).await?;
+
+    // Drop the cached entry so the new last seen day is picked up
+    app.cache.invalidate(&alias).await;
+
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+#[derive(Serialize)]
+pub struct RotateResponse {
+    pub alias: String,
+}
+
+pub async fn rotate_user_link(
+    RequireUser(session_id): RequireUser,
+    State(app): State<AppState>,
+    AliasPath(alias): AliasPath,
+) -> Result<Response, ApiError> {
+    let session = app.sessions.get_session_data(&session_id).await?;
+    let new_alias = services::rotate_user_link(
+        &session.user_id,
+        &alias,
+        app.config.alias_grace_days,
+        &app.sqids,
+        &app.pool,
+    )
+    .await?;
+
+    app.alias_filter.insert(&new_alias);
+
+    // Both aliases may have cached lookups, the old one is retired now
+    app.cache.invalidate(&alias).await;
+    if let Ok(new_alias) = Alias::try_from(new_alias.clone()) {
+        app.cache.invalidate(&new_alias).await;
+    }
+
+    Ok((StatusCode::OK, Json(RotateResponse { alias: new_alias })).into_response())
+}
+
 pub async fn logout(
     RequireUser(session_id): RequireUser,
     State(app): State<AppState>,
 ) -> Result<Response, ApiError> {
-    app.sessions.close_session(&session_id);
+    app.sessions.close_session(&session_id).await?;
 
     let mut res = StatusCode::NO_CONTENT.into_response();
     res.extensions_mut().insert(ClearSid);
     Ok(res)
 }
+
+#[derive(Serialize)]
+pub struct LogoutAllResponse {
+    pub revoked: u64,
+}
+
+/// Close every session of the user, including the current one
+pub async fn logout_all(
+    RequireUser(session_id): RequireUser,
+    State(app): State<AppState>,
+) -> Result<Response, ApiError> {
+    let session = app.sessions.get_session_data(&session_id).await?;
+    let revoked = app.sessions.close_user_sessions(session.user_id).await?;
+
+    let mut res = (StatusCode::OK, Json(LogoutAllResponse { revoked })).into_response();
+    res.extensions_mut().insert(ClearSid);
+    Ok(res)
+}
+
+#[derive(Deserialize)]
+pub struct ImportItem {
+    pub url: String,
+    pub alias: Option<String>,
+    pub password: Option<String>,
+}
+
+/// Links fetched per query while exporting
+const EXPORT_PAGE_SIZE: i64 = 500;
+
+#[derive(Serialize)]
+struct ExportRow<'a> {
+    #[serde(flatten)]
+    link: &'a ExportLink,
+    short_url: String,
+}
+
+/// Stream all of the user's links as a single JSON document
+///
+/// Links are read page by page, so large accounts aren't buffered in memory
+pub async fn export_links(
+    RequireUser(session_id): RequireUser,
+    State(app): State<AppState>,
+) -> Result<Response, ApiError> {
+    let session = app.sessions.get_session_data(&session_id).await?;
+    let user_id = session.user_id;
+
+    // The state is the last exported id, 0 before the first page and None once done
+    let chunks = stream::unfold(Some(0), move |after_id| {
+        let app = app.clone();
+        async move {
+            let after_id = after_id?;
+            let page =
+                match services::query_export_page(user_id, after_id, EXPORT_PAGE_SIZE, &app.pool)
+                    .await
+                {
+                    Ok(page) => page,
+                    Err(e) => {
+                        tracing::error!(error = %e, "failed to export links");
+                        return Some((Err(e), None));
+                    }
+                };
+
+            let mut chunk = Vec::new();
+            if after_id == 0 {
+                chunk.extend_from_slice(br#"{"links":["#);
+            }
+            for (idx, link) in page.iter().enumerate() {
+                if after_id != 0 || idx > 0 {
+                    chunk.push(b',');
+                }
+                let row = ExportRow {
+                    link,
+                    short_url: app.config.short_url(&link.alias),
+                };
+                if let Err(e) = serde_json::to_writer(&mut chunk, &row) {
+                    return Some((Err(ServiceError::Other(e.into())), None));
+                }
+            }
+
+            let next = match page.last() {
+                Some(link) if page.len() as i64 == EXPORT_PAGE_SIZE => Some(link.id),
+                _ => {
+                    chunk.extend_from_slice(b"]}");
+                    None
+                }
+            };
+            Some((Ok(chunk), next))
+        }
+    });
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/json"),
+            (
+                header::CONTENT_DISPOSITION,
+                "attachment; filename=\"links.json\"",
+            ),
+        ],
+        Body::from_stream(chunks),
+    )
+        .into_response())
+}
+
+/// Recreate links exported from another shortener under the user's account
+///
+/// Rows are created one by one, so a taken alias fails only its own row
+pub async fn import_links(
+    RequireUser(session_id): RequireUser,
+    State(app): State<AppState>,
+    JsonBody(items): JsonBody<Vec<ImportItem>>,
+) -> Result<Response, ApiError> {
+    app.usage_metrics.log(Category::Shorten);
+
+    if items.len() > MAX_BATCH_SIZE {
+        return Err(ApiError::public(
+            StatusCode::BAD_REQUEST,
+            "batch_too_large",
+            formatcp!("Import cannot contain more than {MAX_BATCH_SIZE} links"),
+        ));
+    }
+
+    let session = app.sessions.get_session_data(&session_id).await?;
+
+    let mut results = Vec::with_capacity(items.len());
+    for item in items {
+        let url = item.url.clone();
+        results.push(match import_link(item, session.user_id, &app).await {
+            Ok(alias) => BatchShortenItem::Created {
+                url,
+                alias,
+                deletion_token: None,
+            },
+            Err(e) => BatchShortenItem::Failed {
+                url,
+                error: e.into_reason().into_owned(),
+            },
+        });
+    }
+
+    Ok((StatusCode::OK, Json(results)).into_response())
+}
+
+async fn import_link(
+    item: ImportItem,
+    user_id: UserId,
+    app: &AppState,
+) -> Result<String, ApiError> {
+    let url = Url::parse_with_policy(item.url, &app.config.url_policy)?;
+
+    // Empty password means the link is not protected
+    let password = item.password.as_deref().filter(|p| !p.is_empty());
+    if let Some(password) = password {
+        app.config.link_password_policy.check(password)?;
+    }
+
+    let options = LinkOptions {
+        user_id: Some(user_id),
+        password,
+        ..LinkOptions::default()
+    };
+
+    match item.alias {
+        Some(alias) => {
+            let alias: Alias = alias.try_into()?;
+            app.config.check_alias(&alias)?;
+
+            let created =
+                services::create_link_with_alias(&url, &alias, &app.pool, &options, &app.hasher)
+                    .await?;
+            app.alias_filter.insert(&created);
+            // Instances may have cached the alias as missing
+            app.cache.invalidate(&alias).await;
+            Ok(created)
+        }
+        None => {
+            let alias = services::create_link(
+                &url,
+                &app.sqids,
+                &app.link_ids,
+                &app.pool,
+                &options,
+                &app.hasher,
+            )
+            .await?;
+            app.alias_filter.insert(&alias);
+            app.check_sqids_capacity(&alias);
+            Ok(alias)
+        }
+    }
+}
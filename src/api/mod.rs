@@ -1,8 +1,13 @@
 mod error;
 mod extract;
 pub mod handlers;
+mod rate_limit;
+mod request_id;
 mod router;
 mod session;
+mod unlock_limit;
 
-pub use router::build_router;
-pub use session::Sessions;
+pub use rate_limit::RateLimiter;
+pub use router::{build_api_router, build_redirect_router, build_router};
+pub use session::{MemorySessions, PgSessions, Sessions};
+pub use unlock_limit::UnlockLimiter;
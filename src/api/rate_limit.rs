@@ -0,0 +1,237 @@
+use std::{
+    net::{IpAddr, Ipv6Addr, SocketAddr},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use axum::{
+    body::Body,
+    extract::{ConnectInfo, State},
+    http::{HeaderMap, Request},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use dashmap::DashMap;
+
+use crate::{
+    api::error::ApiError,
+    app::{AppState, usage_metrics::Category},
+};
+
+/// Buckets tracked before refilled ones are dropped
+const MAX_TRACKED: usize = 100_000;
+/// Shortest time between sweeps of a full table, each one walks every bucket
+const SWEEP_INTERVAL: Duration = Duration::from_secs(1);
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// Token bucket per client IP, or per /64 prefix for IPv6 clients
+pub struct RateLimiter {
+    /// Tokens refilled per second
+    rate: f64,
+    burst: f64,
+    buckets: DashMap<IpAddr, Bucket>,
+    max_tracked: usize,
+    last_sweep: Mutex<Option<Instant>>,
+}
+
+impl RateLimiter {
+    /// `per_minute` of 0 disables limiting
+    pub fn new(per_minute: u32, burst: u32) -> Self {
+        Self {
+            rate: f64::from(per_minute) / 60.0,
+            burst: f64::from(burst.max(1)),
+            buckets: DashMap::new(),
+            max_tracked: MAX_TRACKED,
+            last_sweep: Mutex::new(None),
+        }
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.rate > 0.0
+    }
+
+    /// Take a token for the client, returns how long to wait if there is none
+    fn check(&self, ip: IpAddr, now: Instant) -> Result<(), Duration> {
+        let key = bucket_key(ip);
+        if self.buckets.len() >= self.max_tracked && !self.buckets.contains_key(&key) {
+            self.sweep(now);
+            // Every tracked client is still being limited, new ones wait for buckets to refill
+            if self.buckets.len() >= self.max_tracked {
+                return Err(SWEEP_INTERVAL);
+            }
+        }
+
+        let mut bucket = self.buckets.entry(key).or_insert(Bucket {
+            tokens: self.burst,
+            updated: now,
+        });
+
+        let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.rate).min(self.burst);
+        bucket.updated = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) / self.rate))
+        }
+    }
+
+    /// Drop buckets that refilled, they are the same as new ones
+    ///
+    /// Runs at most once per [`SWEEP_INTERVAL`], a table full of limited clients stays full meanwhile
+    fn sweep(&self, now: Instant) {
+        {
+            let mut last_sweep = self.last_sweep.lock().unwrap();
+            if last_sweep.is_some_and(|at| now.saturating_duration_since(at) < SWEEP_INTERVAL) {
+                return;
+            }
+            *last_sweep = Some(now);
+        }
+
+        self.buckets.retain(|_, bucket| {
+            let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+            bucket.tokens + elapsed * self.rate < self.burst
+        });
+    }
+}
+
+/// IPv6 clients usually hold a whole /64, so they share a bucket per prefix
+fn bucket_key(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V4(_) => ip,
+        IpAddr::V6(v6) => match v6.to_ipv4_mapped() {
+            Some(v4) => IpAddr::V4(v4),
+            None => IpAddr::V6(Ipv6Addr::from(u128::from(v6) & (u128::MAX << 64))),
+        },
+    }
+}
+
+/// Client IP, taken from `X-Forwarded-For` only behind a trusted proxy
+fn client_ip(headers: &HeaderMap, peer: Option<SocketAddr>, trust_proxy: bool) -> Option<IpAddr> {
+    if trust_proxy {
+        let forwarded = headers
+            .get("x-forwarded-for")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.split(',').next())
+            .and_then(|ip| ip.trim().parse().ok());
+        if forwarded.is_some() {
+            return forwarded;
+        }
+    }
+    peer.map(|addr| addr.ip())
+}
+
+pub async fn rate_limit_mw(
+    State(app): State<AppState>,
+    req: Request<Body>,
+    next: Next,
+) -> Response {
+    let limiter = &app.rate_limiter;
+    if !limiter.is_enabled() {
+        return next.run(req).await;
+    }
+
+    let peer = req
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| *addr);
+    // Requests without a known client aren't limited
+    let Some(ip) = client_ip(req.headers(), peer, app.config.trust_proxy) else {
+        return next.run(req).await;
+    };
+
+    if let Err(retry_after) = limiter.check(ip, Instant::now()) {
+        app.usage_metrics.log(Category::Throttled);
+
+        return ApiError::too_many_requests("too_many_requests", "Too many requests", retry_after)
+            .into_response();
+    }
+
+    next.run(req).await
+}
+
+#[cfg(test)]
+mod test {
+    use axum::http::HeaderValue;
+
+    use super::*;
+
+    #[test]
+    fn bucket_refills_over_time() {
+        let limiter = RateLimiter::new(60, 2);
+        let ip: IpAddr = [127, 0, 0, 1].into();
+        let start = Instant::now();
+
+        assert!(limiter.check(ip, start).is_ok());
+        assert!(limiter.check(ip, start).is_ok());
+        let retry_after = limiter.check(ip, start).unwrap_err();
+        assert!(retry_after <= Duration::from_secs(1));
+
+        // Other clients have their own bucket
+        assert!(limiter.check([127, 0, 0, 2].into(), start).is_ok());
+
+        assert!(limiter.check(ip, start + Duration::from_secs(1)).is_ok());
+        assert!(limiter.check(ip, start + Duration::from_secs(1)).is_err());
+    }
+
+    #[test]
+    fn ipv6_limited_by_prefix() {
+        let limiter = RateLimiter::new(60, 1);
+        let start = Instant::now();
+
+        let check = |ip: &str| limiter.check(ip.parse().unwrap(), start);
+
+        assert!(check("2001:db8::1").is_ok());
+        assert!(check("2001:db8::2").is_err(), "Same /64 shares the bucket");
+        assert!(check("2001:db8:0:1::1").is_ok());
+        assert!(check("::ffff:10.0.0.1").is_ok());
+        assert!(
+            check("10.0.0.1").is_err(),
+            "Mapped addresses are the IPv4 client"
+        );
+    }
+
+    #[test]
+    fn full_table_refuses_new_clients() {
+        let mut limiter = RateLimiter::new(60, 1);
+        limiter.max_tracked = 2;
+        let start = Instant::now();
+
+        // Both clients are held to the rate, neither bucket can be dropped
+        assert!(limiter.check([10, 0, 0, 1].into(), start).is_ok());
+        assert!(limiter.check([10, 0, 0, 2].into(), start).is_ok());
+        assert!(limiter.check([10, 0, 0, 3].into(), start).is_err());
+        assert_eq!(limiter.buckets.len(), 2);
+
+        // Tracked clients are still served
+        let later = start + Duration::from_secs(1);
+        assert!(limiter.check([10, 0, 0, 1].into(), later).is_ok());
+
+        // Refilled buckets make room on a later sweep
+        let much_later = start + Duration::from_secs(5);
+        assert!(limiter.check([10, 0, 0, 3].into(), much_later).is_ok());
+    }
+
+    #[test]
+    fn forwarded_for_needs_trusted_proxy() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "x-forwarded-for",
+            HeaderValue::from_static("10.0.0.1, 10.0.0.2"),
+        );
+        let peer: SocketAddr = ([192, 168, 0, 1], 4000).into();
+
+        assert_eq!(
+            client_ip(&headers, Some(peer), true),
+            Some([10, 0, 0, 1].into())
+        );
+        assert_eq!(client_ip(&headers, Some(peer), false), Some(peer.ip()));
+        assert_eq!(client_ip(&headers, None, false), None);
+    }
+}
@@ -1,46 +1,150 @@
 use axum::{
     Router,
-    middleware::from_fn_with_state,
-    routing::{delete, get, post},
+    middleware::{from_fn, from_fn_with_state},
+    routing::{any, delete, get, patch, post, put},
+};
+use tower_http::{
+    compression::CompressionLayer,
+    services::{ServeDir, ServeFile},
 };
-use tower_http::services::{ServeDir, ServeFile};
 
 use crate::{
-    api::{handlers, session},
+    api::{error, handlers, rate_limit, request_id, session},
     app::AppState,
 };
 
 const DIST_DIR: &str = "web/dist";
 
+/// Router serving both the redirect path and the API with web UI assets
 pub fn build_router(state: AppState) -> Router {
+    let api = api_routes(&state)
+        .layer(compression(&state))
+        .merge(redirect_routes())
+        .merge(health_routes())
+        .merge(metrics_routes(&state))
+        .method_not_allowed_fallback(error::method_not_allowed)
+        .with_state(state.clone())
+        .layer(from_fn_with_state(state, session::session_manager_mw)) // must follow all routes
+        .layer(from_fn(request_id::request_id_mw)); // outermost, so session errors get an id too
+
+    with_assets(api)
+}
+
+/// Router serving only `/r/{alias}`, for running redirects on a separate port
+pub fn build_redirect_router(state: AppState) -> Router {
+    redirect_routes()
+        .merge(health_routes())
+        .method_not_allowed_fallback(error::method_not_allowed)
+        .with_state(state)
+        .layer(from_fn(request_id::request_id_mw))
+}
+
+/// Router serving `/api/*` with web UI assets, without the redirect path
+pub fn build_api_router(state: AppState) -> Router {
+    let api = api_routes(&state)
+        .layer(compression(&state))
+        // `/r/*` lives on the redirect port, it must not fall through to the web UI
+        .route("/r/{*path}", any(error::not_found))
+        .merge(health_routes())
+        .merge(metrics_routes(&state))
+        .method_not_allowed_fallback(error::method_not_allowed)
+        .with_state(state.clone())
+        .layer(from_fn_with_state(state, session::session_manager_mw)) // must follow all routes
+        .layer(from_fn(request_id::request_id_mw)); // outermost, so session errors get an id too
+
+    with_assets(api)
+}
+
+fn redirect_routes() -> Router<AppState> {
+    Router::new()
+        .route("/r/{alias}", get(handlers::redirect))
+        .route("/r/{alias}/qr", get(handlers::redirect_qr))
+}
+
+/// Liveness and readiness probes, served by every router
+fn health_routes() -> Router<AppState> {
+    Router::new()
+        .route("/health", get(handlers::health))
+        .route("/ready", get(handlers::ready))
+}
+
+/// Prometheus scrape target, absent unless enabled
+fn metrics_routes(state: &AppState) -> Router<AppState> {
+    if !state.config.metrics_enabled {
+        return Router::new();
+    }
+
+    Router::new().route("/metrics", get(handlers::metrics))
+}
+
+fn api_routes(state: &AppState) -> Router<AppState> {
+    let throttled = || from_fn_with_state(state.clone(), rate_limit::rate_limit_mw);
+
     // user API (auth required)
     let user_api = Router::new()
         .route("/list", get(handlers::list_user_links))
         .route("/link/{alias}", delete(handlers::remove_user_link))
         .route("/logout", post(handlers::logout));
 
-    // auth management API
+    // link management API (auth required, except deleting with a token)
+    let links_api = Router::new()
+        .route("/", get(handlers::search_user_links))
+        .route(
+            "/{alias}",
+            put(handlers::update_user_link).delete(handlers::remove_link),
+        )
+        .route(
+            "/{alias}/password",
+            patch(handlers::update_user_link_password),
+        )
+        .route("/{alias}/stats", get(handlers::user_link_stats))
+        .route("/{alias}/touch", post(handlers::touch_user_link))
+        .route("/{alias}/rotate", post(handlers::rotate_user_link));
+
+    // admin API (admin required)
+    let admin_api = Router::new()
+        .route("/users", get(handlers::list_users))
+        .route("/users/import", post(handlers::import_user))
+        .route("/links/{alias}", get(handlers::get_link))
+        .route("/usage", get(handlers::usage_summary))
+        .route("/maintenance/{task}", post(handlers::run_maintenance));
+
+    // auth management API, credential checks are rate limited
     let auth_api = Router::new()
-        .route("/me", get(handlers::authenticate_session))
         .route("/login", post(handlers::authenticate_user))
-        .route("/register", post(handlers::create_user));
+        .route("/register", post(handlers::create_user))
+        .route_layer(throttled())
+        .route("/me", get(handlers::authenticate_session));
+
+    // link creation API (rate limited)
+    let shorten_api = Router::new()
+        .route("/shorten", post(handlers::shorten))
+        .route("/shorten/batch", post(handlers::shorten_batch))
+        .route("/import", post(handlers::import_links))
+        .route_layer(throttled());
 
     // core API functions
     let core_api = Router::new()
         .nest("/auth", auth_api)
         .nest("/user", user_api)
-        .route("/shorten", post(handlers::shorten))
+        .nest("/links", links_api)
+        .nest("/admin", admin_api)
+        .merge(shorten_api)
+        .route("/logout-all", post(handlers::logout_all))
         .route("/recent", get(handlers::recently_added_links))
+        .route("/export", get(handlers::export_links))
         .route("/unlock/{alias}", post(handlers::redirect_unlock));
 
-    // assemble everything
-    let api = Router::new()
-        .nest("/api", core_api)
-        .route("/r/{alias}", get(handlers::redirect))
-        .with_state(state.clone())
-        .layer(from_fn_with_state(state, session::session_manager_mw)); // must be last
+    Router::new().nest("/api", core_api)
+}
+
+/// Compression for API responses only, redirects have no body worth compressing
+fn compression(state: &AppState) -> CompressionLayer {
+    let enabled = state.config.compression;
+    CompressionLayer::new().gzip(enabled).br(enabled)
+}
 
-    // merge with assets
+fn with_assets(router: Router) -> Router {
     let serve = ServeDir::new(DIST_DIR).fallback(ServeFile::new(format!("{DIST_DIR}/index.html")));
-    Router::new().merge(api).fallback_service(serve)
+    Router::new().merge(router).fallback_service(serve)
 }
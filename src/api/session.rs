@@ -10,33 +10,63 @@ use axum::{
 };
 use base64::Engine;
 use cookie::Cookie;
-use dashmap::DashMap;
 use rand_core::{OsRng, RngCore};
+use time::{Duration, OffsetDateTime};
 
 use crate::{
     app::AppState,
     domain::{User, UserId},
 };
 
+mod memory;
+mod postgres;
+
+pub use memory::MemorySessions;
+pub use postgres::PgSessions;
+
 pub enum SessionError {
     NotExists,
     Expired,
+    Storage(sqlx::Error),
 }
 
 pub struct SessionData {
     pub user_id: UserId,
     pub username: String,
+    pub is_admin: bool,
+    pub created_at: OffsetDateTime,
+    pub expires_at: OffsetDateTime,
 }
 
-#[derive(Clone)]
-pub struct Sessions {
-    inner: Arc<DashMap<SessionId, Arc<SessionData>>>,
+impl SessionData {
+    fn new(user: &User, ttl: Duration) -> Self {
+        let created_at = OffsetDateTime::now_utc();
+        Self {
+            user_id: user.id(),
+            username: user.name().to_string(),
+            is_admin: user.is_admin(),
+            created_at,
+            expires_at: created_at + ttl,
+        }
+    }
+
+    fn is_expired(&self, now: OffsetDateTime) -> bool {
+        self.expires_at <= now
+    }
 }
 
 #[derive(PartialEq, Eq, Hash, Clone)]
 pub struct SessionId(String);
 
 impl SessionId {
+    fn generate() -> Self {
+        use base64::engine::general_purpose::URL_SAFE_NO_PAD as Base64;
+
+        let mut bytes = [0u8; 32];
+        OsRng.fill_bytes(&mut bytes);
+        Self(Base64.encode(bytes))
+    }
+
     pub fn as_str(&self) -> &str {
         &self.0
     }
@@ -48,54 +78,87 @@ impl Borrow<str> for SessionId {
     }
 }
 
-impl Sessions {
-    pub fn new_session(&self, user: &User) -> SessionId {
-        use base64::engine::general_purpose::URL_SAFE_NO_PAD as Base64;
+/// Backend keeping login sessions
+pub trait SessionStore {
+    fn new_session(
+        &self,
+        user: &User,
+    ) -> impl Future<Output = Result<SessionId, SessionError>> + Send;
 
-        let mut bytes = [0u8; 32];
-        OsRng.fill_bytes(&mut bytes);
-        let session_id = SessionId(Base64.encode(bytes));
+    /// Expired sessions are removed and reported as `SessionError::Expired`
+    fn get_session_data(
+        &self,
+        session_id: &SessionId,
+    ) -> impl Future<Output = Result<Arc<SessionData>, SessionError>> + Send;
+
+    fn close_session(
+        &self,
+        session_id: &SessionId,
+    ) -> impl Future<Output = Result<bool, SessionError>> + Send;
+
+    /// Close every session of the user, returns how many were closed
+    fn close_user_sessions(
+        &self,
+        user_id: UserId,
+    ) -> impl Future<Output = Result<u64, SessionError>> + Send;
 
-        self.inner
-            .insert(session_id.clone(), Arc::new(SessionData::new(user)));
+    /// Remove expired sessions, returns how many were removed
+    fn sweep_expired(&self) -> impl Future<Output = Result<u64, SessionError>> + Send;
+}
+
+/// Sessions of the configured backend
+#[derive(Clone)]
+pub enum Sessions {
+    /// Lost on restart and not shared between instances
+    Memory(MemorySessions),
+    Postgres(PgSessions),
+}
 
-        session_id
+impl Sessions {
+    pub const DEFAULT_TTL: Duration = Duration::days(7);
+
+    pub async fn new_session(&self, user: &User) -> Result<SessionId, SessionError> {
+        match self {
+            Self::Memory(store) => store.new_session(user).await,
+            Self::Postgres(store) => store.new_session(user).await,
+        }
     }
 
-    pub fn get_session_data(
+    pub async fn get_session_data(
         &self,
         session_id: &SessionId,
     ) -> Result<Arc<SessionData>, SessionError> {
-        if let Some(session) = self.inner.get(session_id) {
-            Ok(session.value().clone())
-        } else {
-            Err(SessionError::NotExists)
+        match self {
+            Self::Memory(store) => store.get_session_data(session_id).await,
+            Self::Postgres(store) => store.get_session_data(session_id).await,
         }
     }
 
-    pub fn close_session(&self, session_id: &SessionId) -> bool {
-        self.inner.remove(session_id).is_some()
+    pub async fn close_session(&self, session_id: &SessionId) -> Result<bool, SessionError> {
+        match self {
+            Self::Memory(store) => store.close_session(session_id).await,
+            Self::Postgres(store) => store.close_session(session_id).await,
+        }
     }
 
-    fn is_active(&self, session_id: &str) -> bool {
-        self.inner.contains_key(session_id)
+    pub async fn close_user_sessions(&self, user_id: UserId) -> Result<u64, SessionError> {
+        match self {
+            Self::Memory(store) => store.close_user_sessions(user_id).await,
+            Self::Postgres(store) => store.close_user_sessions(user_id).await,
+        }
     }
-}
 
-impl Default for Sessions {
-    fn default() -> Self {
-        Self {
-            inner: Arc::new(DashMap::new()),
+    pub async fn sweep_expired(&self) -> Result<u64, SessionError> {
+        match self {
+            Self::Memory(store) => store.sweep_expired().await,
+            Self::Postgres(store) => store.sweep_expired().await,
         }
     }
 }
 
-impl SessionData {
-    fn new(user: &User) -> Self {
-        Self {
-            user_id: user.id(),
-            username: user.name().to_string(),
-        }
+impl Default for Sessions {
+    fn default() -> Self {
+        Self::Memory(MemorySessions::new(Self::DEFAULT_TTL))
     }
 }
 
@@ -121,10 +184,16 @@ pub async fn session_manager_mw(
     let mut clear = false;
 
     if let Some(sid) = parse_session_id(req.headers()) {
-        if app.sessions.is_active(&sid) {
-            req.extensions_mut().insert(SessionId(sid));
-        } else {
-            clear = true;
+        let session_id = SessionId(sid);
+        match app.sessions.get_session_data(&session_id).await {
+            Ok(_) => {
+                req.extensions_mut().insert(session_id);
+            }
+            Err(SessionError::Storage(e)) => {
+                // Keep the cookie, the session may still be valid once storage is back
+                tracing::error!(error = %e, "failed to look up the session");
+            }
+            Err(_) => clear = true,
         }
     }
 
@@ -135,10 +204,13 @@ pub async fn session_manager_mw(
     }
 
     if clear {
-        res.headers_mut().append(
-            header::SET_COOKIE,
-            HeaderValue::from_static("sid=; Max-Age=0; Path=/; HttpOnly; SameSite=Lax"),
-        );
+        let cookie = if app.config.secure_cookies {
+            "sid=; Max-Age=0; Path=/; HttpOnly; SameSite=Lax; Secure"
+        } else {
+            "sid=; Max-Age=0; Path=/; HttpOnly; SameSite=Lax"
+        };
+        res.headers_mut()
+            .append(header::SET_COOKIE, HeaderValue::from_static(cookie));
     }
 
     res
@@ -0,0 +1,121 @@
+use std::sync::Arc;
+
+use dashmap::DashMap;
+use time::{Duration, OffsetDateTime};
+
+use crate::{
+    api::session::{SessionData, SessionError, SessionId, SessionStore},
+    domain::{User, UserId},
+};
+
+/// Sessions kept in process memory
+#[derive(Clone)]
+pub struct MemorySessions {
+    inner: Arc<DashMap<SessionId, Arc<SessionData>>>,
+    /// Lifetime of a session from login
+    ttl: Duration,
+}
+
+impl MemorySessions {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            inner: Arc::new(DashMap::new()),
+            ttl,
+        }
+    }
+}
+
+impl SessionStore for MemorySessions {
+    async fn new_session(&self, user: &User) -> Result<SessionId, SessionError> {
+        let session_id = SessionId::generate();
+
+        self.inner.insert(
+            session_id.clone(),
+            Arc::new(SessionData::new(user, self.ttl)),
+        );
+
+        Ok(session_id)
+    }
+
+    async fn get_session_data(
+        &self,
+        session_id: &SessionId,
+    ) -> Result<Arc<SessionData>, SessionError> {
+        let Some(session) = self.inner.get(session_id).map(|s| s.value().clone()) else {
+            return Err(SessionError::NotExists);
+        };
+
+        if session.is_expired(OffsetDateTime::now_utc()) {
+            self.inner.remove(session_id);
+            return Err(SessionError::Expired);
+        }
+
+        Ok(session)
+    }
+
+    async fn close_session(&self, session_id: &SessionId) -> Result<bool, SessionError> {
+        Ok(self.inner.remove(session_id).is_some())
+    }
+
+    async fn close_user_sessions(&self, user_id: UserId) -> Result<u64, SessionError> {
+        let before = self.inner.len();
+        self.inner.retain(|_, session| session.user_id != user_id);
+        Ok(before.saturating_sub(self.inner.len()) as u64)
+    }
+
+    async fn sweep_expired(&self) -> Result<u64, SessionError> {
+        let now = OffsetDateTime::now_utc();
+        let before = self.inner.len();
+        self.inner.retain(|_, session| !session.is_expired(now));
+        Ok(before.saturating_sub(self.inner.len()) as u64)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::domain::UserName;
+
+    fn user() -> User {
+        let Ok(name) = UserName::try_from("tester".to_string()) else {
+            panic!("Invalid test username");
+        };
+        User::new(1, name, false)
+    }
+
+    #[tokio::test]
+    async fn expired_session_is_removed() {
+        let sessions = MemorySessions::new(Duration::ZERO);
+        let Ok(session_id) = sessions.new_session(&user()).await else {
+            panic!("Failed to create a session");
+        };
+
+        assert!(matches!(
+            sessions.get_session_data(&session_id).await,
+            Err(SessionError::Expired)
+        ));
+        assert!(matches!(
+            sessions.get_session_data(&session_id).await,
+            Err(SessionError::NotExists)
+        ));
+    }
+
+    #[tokio::test]
+    async fn sweep_keeps_live_sessions() {
+        let sessions = MemorySessions::new(Duration::ZERO);
+        for _ in 0..2 {
+            assert!(sessions.new_session(&user()).await.is_ok());
+        }
+
+        let live = MemorySessions {
+            ttl: Duration::hours(1),
+            ..sessions.clone()
+        };
+        let Ok(session_id) = live.new_session(&user()).await else {
+            panic!("Failed to create a session");
+        };
+
+        assert!(matches!(sessions.sweep_expired().await, Ok(2)));
+        assert!(sessions.get_session_data(&session_id).await.is_ok());
+    }
+}
@@ -0,0 +1,120 @@
+use std::sync::Arc;
+
+use base64::Engine;
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use time::{Duration, OffsetDateTime};
+
+use crate::{
+    api::session::{SessionData, SessionError, SessionId, SessionStore},
+    domain::{User, UserId},
+};
+
+/// Sessions kept in the database, they survive restarts and are shared between instances
+#[derive(Clone)]
+pub struct PgSessions {
+    pool: PgPool,
+    /// Lifetime of a session from login
+    ttl: Duration,
+}
+
+impl PgSessions {
+    pub fn new(pool: PgPool, ttl: Duration) -> Self {
+        Self { pool, ttl }
+    }
+}
+
+/// Session ids are random 256-bit values, a fast digest is enough to keep them out of the table
+fn hash_session_id(session_id: &SessionId) -> String {
+    use base64::engine::general_purpose::STANDARD_NO_PAD as Base64;
+
+    Base64.encode(Sha256::digest(session_id.as_str().as_bytes()))
+}
+
+impl SessionStore for PgSessions {
+    async fn new_session(&self, user: &User) -> Result<SessionId, SessionError> {
+        let session_id = SessionId::generate();
+        let data = SessionData::new(user, self.ttl);
+
+        sqlx::query!(
+            r#"
+            INSERT INTO sessions (id_hash, user_id, created_at, expires_at)
+            VALUES ($1, $2, $3, $4)
+            "#,
+            hash_session_id(&session_id),
+            data.user_id,
+            data.created_at,
+            data.expires_at,
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(SessionError::Storage)?;
+
+        Ok(session_id)
+    }
+
+    async fn get_session_data(
+        &self,
+        session_id: &SessionId,
+    ) -> Result<Arc<SessionData>, SessionError> {
+        // User details are read fresh, an admin flag change applies to open sessions
+        let rec = sqlx::query!(
+            r#"
+            SELECT s.user_id, u.username, u.is_admin, s.created_at, s.expires_at
+            FROM sessions s
+            JOIN users_main u ON u.id = s.user_id
+            WHERE s.id_hash = $1
+            "#,
+            hash_session_id(session_id),
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(SessionError::Storage)?
+        .ok_or(SessionError::NotExists)?;
+
+        let session = SessionData {
+            user_id: rec.user_id,
+            username: rec.username,
+            is_admin: rec.is_admin,
+            created_at: rec.created_at,
+            expires_at: rec.expires_at,
+        };
+
+        if session.is_expired(OffsetDateTime::now_utc()) {
+            self.close_session(session_id).await?;
+            return Err(SessionError::Expired);
+        }
+
+        Ok(Arc::new(session))
+    }
+
+    async fn close_session(&self, session_id: &SessionId) -> Result<bool, SessionError> {
+        let result = sqlx::query!(
+            "DELETE FROM sessions WHERE id_hash = $1",
+            hash_session_id(session_id)
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(SessionError::Storage)?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn close_user_sessions(&self, user_id: UserId) -> Result<u64, SessionError> {
+        let result = sqlx::query!("DELETE FROM sessions WHERE user_id = $1", user_id)
+            .execute(&self.pool)
+            .await
+            .map_err(SessionError::Storage)?;
+
+        Ok(result.rows_affected())
+    }
+
+    async fn sweep_expired(&self) -> Result<u64, SessionError> {
+        let result = sqlx::query!("DELETE FROM sessions WHERE expires_at <= now()")
+            .execute(&self.pool)
+            .await
+            .map_err(SessionError::Storage)?;
+
+        Ok(result.rows_affected())
+    }
+}
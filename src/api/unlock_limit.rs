@@ -0,0 +1,131 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use dashmap::DashMap;
+
+/// Links tracked before ones without recent failures are dropped
+const MAX_TRACKED: usize = 100_000;
+
+/// Failed unlock attempts per link within a sliding window
+pub struct UnlockLimiter {
+    max_failures: usize,
+    window: Duration,
+    /// Times of failures within the window, oldest first
+    failures: DashMap<i64, VecDeque<Instant>>,
+}
+
+impl UnlockLimiter {
+    /// `max_failures` of 0 disables limiting
+    pub fn new(max_failures: u32, window_s: u64) -> Self {
+        Self {
+            max_failures: max_failures as usize,
+            window: Duration::from_secs(window_s),
+            failures: DashMap::new(),
+        }
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.max_failures > 0
+    }
+
+    /// Whether the link takes attempts, returns how long to wait if it doesn't
+    pub fn check(&self, link_id: i64, now: Instant) -> Result<(), Duration> {
+        if !self.is_enabled() {
+            return Ok(());
+        }
+        let Some(mut failures) = self.failures.get_mut(&link_id) else {
+            return Ok(());
+        };
+
+        self.expire(&mut failures, now);
+        if failures.len() < self.max_failures {
+            return Ok(());
+        }
+
+        let oldest = failures[0];
+        Err((oldest + self.window).saturating_duration_since(now))
+    }
+
+    pub fn record_failure(&self, link_id: i64, now: Instant) {
+        if !self.is_enabled() {
+            return;
+        }
+        if self.failures.len() >= MAX_TRACKED {
+            self.sweep(now);
+        }
+
+        let mut failures = self.failures.entry(link_id).or_default();
+        self.expire(&mut failures, now);
+        failures.push_back(now);
+        // Older failures don't matter once the link is refusing attempts
+        if failures.len() > self.max_failures {
+            failures.pop_front();
+        }
+    }
+
+    /// A successful unlock starts over
+    pub fn reset(&self, link_id: i64) {
+        self.failures.remove(&link_id);
+    }
+
+    fn expire(&self, failures: &mut VecDeque<Instant>, now: Instant) {
+        while failures
+            .front()
+            .is_some_and(|&at| now.saturating_duration_since(at) >= self.window)
+        {
+            failures.pop_front();
+        }
+    }
+
+    /// Drop links whose failures all left the window
+    fn sweep(&self, now: Instant) {
+        self.failures.retain(|_, failures| {
+            failures
+                .back()
+                .is_some_and(|&at| now.saturating_duration_since(at) < self.window)
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn failures_lock_out_within_window() {
+        let limiter = UnlockLimiter::new(3, 60);
+        let start = Instant::now();
+
+        for i in 0..3 {
+            assert!(limiter.check(1, start).is_ok());
+            limiter.record_failure(1, start + Duration::from_secs(i * 10));
+        }
+        let retry_after = limiter
+            .check(1, start + Duration::from_secs(20))
+            .unwrap_err();
+        assert_eq!(retry_after, Duration::from_secs(40));
+
+        // Other links have their own counter
+        assert!(limiter.check(2, start).is_ok());
+
+        // The first failure slides out of the window
+        assert!(limiter.check(1, start + Duration::from_secs(60)).is_ok());
+
+        limiter.reset(1);
+        limiter.record_failure(1, start + Duration::from_secs(61));
+        assert!(limiter.check(1, start + Duration::from_secs(61)).is_ok());
+    }
+
+    #[test]
+    fn disabled_never_locks_out() {
+        let limiter = UnlockLimiter::new(0, 60);
+        let now = Instant::now();
+
+        for _ in 0..10 {
+            limiter.record_failure(1, now);
+        }
+        assert!(limiter.check(1, now).is_ok());
+    }
+}
@@ -0,0 +1,90 @@
+use std::{
+    hash::{DefaultHasher, Hash, Hasher},
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+};
+
+/// Bloom filter of existing aliases, used to answer "definitely not present" without touching the DB
+///
+/// Aliases can't be removed from the filter, deleted aliases remain as false positives which just fall through to the DB.
+/// Until the filter is marked as populated, every alias is reported as possibly present.
+pub struct AliasFilter {
+    bits: Box<[AtomicU64]>,
+    populated: AtomicBool,
+}
+
+impl AliasFilter {
+    /// 16M bits (2 MiB), keeps false positives around 1% for ~1.6M aliases
+    const BITS: u64 = 1 << 24;
+    const HASHES: u64 = 7;
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&self, alias: &str) {
+        for idx in Self::bit_indexes(alias) {
+            self.bits[(idx / 64) as usize].fetch_or(1 << (idx % 64), Ordering::Relaxed);
+        }
+    }
+
+    /// Returns false only if the alias was never inserted
+    pub fn might_contain(&self, alias: &str) -> bool {
+        if !self.populated.load(Ordering::Acquire) {
+            return true;
+        }
+
+        Self::bit_indexes(alias).all(|idx| {
+            self.bits[(idx / 64) as usize].load(Ordering::Relaxed) & (1 << (idx % 64)) != 0
+        })
+    }
+
+    /// Start rejecting aliases, must be called only after all existing aliases were inserted
+    pub fn mark_populated(&self) {
+        self.populated.store(true, Ordering::Release);
+    }
+
+    fn bit_indexes(alias: &str) -> impl Iterator<Item = u64> {
+        // double hashing: h1 + i * h2
+        let mut hasher = DefaultHasher::new();
+        alias.hash(&mut hasher);
+        let h1 = hasher.finish();
+        h1.hash(&mut hasher);
+        let h2 = hasher.finish() | 1;
+
+        (0..Self::HASHES).map(move |i| h1.wrapping_add(i.wrapping_mul(h2)) % Self::BITS)
+    }
+}
+
+impl Default for AliasFilter {
+    fn default() -> Self {
+        Self {
+            bits: (0..Self::BITS / 64).map(|_| AtomicU64::new(0)).collect(),
+            populated: AtomicBool::new(false),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn unpopulated_allows_everything() {
+        let filter = AliasFilter::new();
+        assert!(filter.might_contain("missing"));
+    }
+
+    #[test]
+    fn populated_rejects_missing() {
+        let filter = AliasFilter::new();
+        filter.insert("present");
+        filter.mark_populated();
+
+        assert!(filter.might_contain("present"));
+        assert!(!filter.might_contain("missing"));
+
+        // inserts after population are visible
+        filter.insert("missing");
+        assert!(filter.might_contain("missing"));
+    }
+}
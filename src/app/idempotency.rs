@@ -0,0 +1,198 @@
+use std::time::{Duration, Instant};
+
+use dashmap::{DashMap, mapref::entry::Entry};
+
+/// How long a completed request is replayed for its key
+const REPLAY_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+/// How long a claim holds without completing, in case its request never finished
+const CLAIM_TTL: Duration = Duration::from_secs(60);
+/// Keys tracked at most, new keys are refused until a sweep makes room
+const MAX_TRACKED: usize = 100_000;
+
+/// Link created by a shorten request, replayed to retries with the same key
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShortenOutcome {
+    /// Target of the request, a retry must ask for the same one
+    pub url: String,
+    pub alias: String,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum IdempotencyClaim {
+    /// First request with the key, it must be completed or released
+    New,
+    /// A request with the key is still running
+    InFlight,
+    Done(ShortenOutcome),
+    /// Too many keys are tracked to take a new one
+    Full,
+}
+
+/// Remembers what shorten requests with an `Idempotency-Key` produced
+///
+/// Keys are scoped by the caller, e.g. prefixed with the user, before reaching the store.
+/// Only links of signed in users go through it, so there are no deletion tokens to keep.
+pub trait IdempotencyStore: Send + Sync {
+    /// Claim the key for a new request, or get what an earlier one produced
+    fn claim(&self, key: &str) -> IdempotencyClaim;
+
+    /// Store the outcome of the claimed request
+    fn complete(&self, key: &str, outcome: ShortenOutcome);
+
+    /// Drop the claim of a failed request so a retry runs again
+    fn release(&self, key: &str);
+
+    /// Drop expired keys, returns how many were dropped
+    fn sweep(&self) -> u64;
+}
+
+enum Slot {
+    Claimed(Instant),
+    Done(Instant, ShortenOutcome),
+}
+
+impl Slot {
+    fn is_expired(&self, now: Instant) -> bool {
+        match self {
+            Self::Claimed(at) => now.saturating_duration_since(*at) >= CLAIM_TTL,
+            Self::Done(at, _) => now.saturating_duration_since(*at) >= REPLAY_TTL,
+        }
+    }
+}
+
+/// Process-local store, retries must reach the same instance
+pub struct MemoryIdempotency {
+    slots: DashMap<String, Slot>,
+    max_tracked: usize,
+}
+
+impl MemoryIdempotency {
+    fn claim_at(&self, key: &str, now: Instant) -> IdempotencyClaim {
+        // Counted before taking the entry, counting needs every shard
+        let full = self.slots.len() >= self.max_tracked;
+
+        match self.slots.entry(key.to_string()) {
+            Entry::Occupied(mut entry) => {
+                if entry.get().is_expired(now) {
+                    entry.insert(Slot::Claimed(now));
+                    return IdempotencyClaim::New;
+                }
+                match entry.get() {
+                    Slot::Claimed(_) => IdempotencyClaim::InFlight,
+                    Slot::Done(_, outcome) => IdempotencyClaim::Done(outcome.clone()),
+                }
+            }
+            Entry::Vacant(_) if full => IdempotencyClaim::Full,
+            Entry::Vacant(entry) => {
+                entry.insert(Slot::Claimed(now));
+                IdempotencyClaim::New
+            }
+        }
+    }
+
+    fn sweep_at(&self, now: Instant) -> u64 {
+        let before = self.slots.len();
+        self.slots.retain(|_, slot| !slot.is_expired(now));
+        before.saturating_sub(self.slots.len()) as u64
+    }
+}
+
+impl Default for MemoryIdempotency {
+    fn default() -> Self {
+        Self {
+            slots: DashMap::new(),
+            max_tracked: MAX_TRACKED,
+        }
+    }
+}
+
+impl IdempotencyStore for MemoryIdempotency {
+    fn claim(&self, key: &str) -> IdempotencyClaim {
+        self.claim_at(key, Instant::now())
+    }
+
+    fn complete(&self, key: &str, outcome: ShortenOutcome) {
+        self.slots
+            .insert(key.to_string(), Slot::Done(Instant::now(), outcome));
+    }
+
+    fn release(&self, key: &str) {
+        self.slots
+            .remove_if(key, |_, slot| matches!(slot, Slot::Claimed(_)));
+    }
+
+    fn sweep(&self) -> u64 {
+        self.sweep_at(Instant::now())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn claimed_key_replays_outcome() {
+        let store = MemoryIdempotency::default();
+        let outcome = ShortenOutcome {
+            url: "https://example.com/".to_string(),
+            alias: "abcdef".to_string(),
+        };
+        let start = Instant::now();
+
+        assert_eq!(store.claim_at("k", start), IdempotencyClaim::New);
+        assert_eq!(store.claim_at("k", start), IdempotencyClaim::InFlight);
+
+        // A failed request leaves the key to its retry
+        store.release("k");
+        assert_eq!(store.claim_at("k", start), IdempotencyClaim::New);
+
+        store.complete("k", outcome.clone());
+        assert_eq!(
+            store.claim_at("k", Instant::now()),
+            IdempotencyClaim::Done(outcome.clone())
+        );
+        store.release("k");
+        assert_eq!(
+            store.claim_at("k", Instant::now()),
+            IdempotencyClaim::Done(outcome)
+        );
+
+        assert_eq!(
+            store.claim_at("k", Instant::now() + REPLAY_TTL),
+            IdempotencyClaim::New
+        );
+    }
+
+    #[test]
+    fn full_store_refuses_new_keys() {
+        let store = MemoryIdempotency {
+            max_tracked: 2,
+            ..MemoryIdempotency::default()
+        };
+        let start = Instant::now();
+
+        assert_eq!(store.claim_at("a", start), IdempotencyClaim::New);
+        assert_eq!(store.claim_at("b", start), IdempotencyClaim::New);
+        assert_eq!(store.claim_at("c", start), IdempotencyClaim::Full);
+        // Tracked keys still answer
+        assert_eq!(store.claim_at("a", start), IdempotencyClaim::InFlight);
+
+        // Claiming doesn't sweep, the periodic task does
+        let later = start + CLAIM_TTL;
+        assert_eq!(store.claim_at("c", later), IdempotencyClaim::Full);
+        assert_eq!(store.sweep_at(later), 2);
+        assert_eq!(store.claim_at("c", later), IdempotencyClaim::New);
+    }
+
+    #[test]
+    fn stale_claim_is_taken_over() {
+        let store = MemoryIdempotency::default();
+        let start = Instant::now();
+
+        assert_eq!(store.claim_at("k", start), IdempotencyClaim::New);
+        assert_eq!(
+            store.claim_at("k", start + CLAIM_TTL),
+            IdempotencyClaim::New
+        );
+    }
+}
@@ -0,0 +1,214 @@
+use std::{sync::Arc, time::Duration};
+
+use moka::future::Cache;
+use rand_core::{OsRng, RngCore};
+use sqlx::{PgPool, postgres::PgListener};
+use tokio::sync::broadcast::{self, Receiver, Sender, error::RecvError};
+
+use crate::{app::CachedLink, domain::Alias};
+
+/// Stale cached lookups announced on the bus
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Invalidation {
+    Alias(Alias),
+    /// Any lookup may be stale, e.g. after a bulk cleanup
+    All,
+}
+
+/// Carries cache invalidations between app instances sharing the database
+///
+/// Links changed on one instance would otherwise stay cached on the others until their entries go idle.
+pub trait InvalidationBus: Send + Sync {
+    /// Announce stale cached lookups to the other instances
+    fn publish(&self, invalidation: Invalidation);
+
+    /// Invalidations published on other instances, `None` if there are no other instances
+    fn subscribe(&self) -> Option<Receiver<Invalidation>>;
+}
+
+/// Bus for single instance deployments, there is nobody to notify
+pub struct LocalBus;
+
+impl InvalidationBus for LocalBus {
+    fn publish(&self, _invalidation: Invalidation) {}
+
+    fn subscribe(&self) -> Option<Receiver<Invalidation>> {
+        None
+    }
+}
+
+/// Channel the instances notify each other on
+const PG_CHANNEL: &str = "link_cache_invalidations";
+/// Payload of [`Invalidation::All`], not a valid alias
+const PG_ALL: &str = "*";
+
+/// Bus over Postgres `LISTEN`/`NOTIFY` between the instances sharing the database
+///
+/// Payloads are `{instance} {alias}`, or `{instance} *` to invalidate everything.
+pub struct PgBus {
+    pool: PgPool,
+    /// Tags notifications of this instance, it has applied them already
+    instance: u64,
+}
+
+impl PgBus {
+    pub fn new(pool: PgPool) -> Self {
+        Self {
+            pool,
+            instance: OsRng.next_u64(),
+        }
+    }
+}
+
+impl InvalidationBus for PgBus {
+    fn publish(&self, invalidation: Invalidation) {
+        let payload = match &invalidation {
+            Invalidation::Alias(alias) => format!("{} {}", self.instance, alias.as_str()),
+            Invalidation::All => format!("{} {PG_ALL}", self.instance),
+        };
+        let pool = self.pool.clone();
+
+        // Lost notifications leave entries stale until they go idle, not worth failing the request
+        tokio::spawn(async move {
+            let result = sqlx::query("SELECT pg_notify($1, $2)")
+                .bind(PG_CHANNEL)
+                .bind(payload)
+                .execute(&pool)
+                .await;
+            if let Err(e) = result {
+                tracing::warn!(error = %e, "Failed to publish a cache invalidation");
+            }
+        });
+    }
+
+    fn subscribe(&self) -> Option<Receiver<Invalidation>> {
+        let (tx, rx) = broadcast::channel(1024);
+        tokio::spawn(forward_notifications(self.pool.clone(), self.instance, tx));
+        Some(rx)
+    }
+}
+
+/// Pass invalidations of other instances on until nobody receives them or the pool closes
+async fn forward_notifications(pool: PgPool, instance: u64, tx: Sender<Invalidation>) {
+    const RETRY_DELAY: Duration = Duration::from_secs(1);
+
+    let mut listener = loop {
+        let listener = match PgListener::connect_with(&pool).await {
+            Ok(mut listener) => listener.listen(PG_CHANNEL).await.map(|_| listener),
+            Err(e) => Err(e),
+        };
+        match listener {
+            Ok(listener) => break listener,
+            Err(sqlx::Error::PoolClosed) => return,
+            Err(e) => {
+                tracing::warn!(error = %e, "Failed to listen for cache invalidations");
+                tokio::time::sleep(RETRY_DELAY).await;
+            }
+        }
+    };
+    let instance = instance.to_string();
+
+    loop {
+        let invalidation = match listener.try_recv().await {
+            Ok(Some(notification)) => match notification.payload().split_once(' ') {
+                Some((from, _)) if from == instance => continue,
+                Some((_, PG_ALL)) => Invalidation::All,
+                Some((_, alias)) => match Alias::try_from(alias.to_string()) {
+                    Ok(alias) => Invalidation::Alias(alias),
+                    Err(_) => continue,
+                },
+                None => continue,
+            },
+            // Notifications sent while the connection was lost are gone, they could be for any alias
+            Ok(None) => Invalidation::All,
+            Err(sqlx::Error::PoolClosed) => return,
+            Err(e) => {
+                tracing::warn!(error = %e, "Failed to receive cache invalidations");
+                tokio::time::sleep(RETRY_DELAY).await;
+                continue;
+            }
+        };
+
+        if tx.send(invalidation).is_err() {
+            return;
+        }
+    }
+}
+
+/// Process-local cache of alias lookups, invalidations are broadcast over the bus
+#[derive(Clone)]
+pub struct LinkCache {
+    local: Cache<Alias, Option<CachedLink>>,
+    bus: Arc<dyn InvalidationBus>,
+}
+
+impl LinkCache {
+    pub fn new(bus: Arc<dyn InvalidationBus>) -> Self {
+        let local = Cache::builder()
+            .time_to_idle(Duration::from_secs(60 * 60 * 24))
+            .max_capacity(3_000)
+            .build();
+
+        Self { local, bus }
+    }
+
+    pub async fn get(&self, alias: &Alias) -> Option<Option<CachedLink>> {
+        self.local.get(alias).await
+    }
+
+    /// Get the cached lookup or resolve it with `init`, concurrent misses share a single `init`
+    pub async fn try_get_with<F, E>(
+        &self,
+        alias: &Alias,
+        init: F,
+    ) -> Result<Option<CachedLink>, Arc<E>>
+    where
+        F: Future<Output = Result<Option<CachedLink>, E>>,
+        E: Send + Sync + 'static,
+    {
+        self.local.try_get_with_by_ref(alias, init).await
+    }
+
+    /// Drop the lookup here and on every other instance
+    pub async fn invalidate(&self, alias: &Alias) {
+        self.local.invalidate(alias).await;
+        self.bus.publish(Invalidation::Alias(alias.clone()));
+    }
+
+    /// Drop the lookup of this instance only, when nothing changed for the others
+    pub async fn invalidate_local(&self, alias: &Alias) {
+        self.local.invalidate(alias).await;
+    }
+
+    /// Drop every lookup here and on every other instance
+    pub fn invalidate_all(&self) {
+        self.local.invalidate_all();
+        self.bus.publish(Invalidation::All);
+    }
+
+    /// Apply invalidations from other instances until the bus closes
+    pub async fn listen(self) {
+        let Some(mut rx) = self.bus.subscribe() else {
+            return;
+        };
+
+        loop {
+            match rx.recv().await {
+                Ok(Invalidation::Alias(alias)) => self.local.invalidate(&alias).await,
+                Ok(Invalidation::All) => self.local.invalidate_all(),
+                // Missed invalidations could be for any alias
+                Err(RecvError::Lagged(skipped)) => {
+                    tracing::warn!(skipped, "Missed cache invalidations, clearing the cache");
+                    self.local.invalidate_all();
+                }
+                Err(RecvError::Closed) => break,
+            }
+        }
+    }
+}
+
+impl Default for LinkCache {
+    fn default() -> Self {
+        Self::new(Arc::new(LocalBus))
+    }
+}
@@ -1,5 +1,6 @@
+use serde::Serialize;
 use std::sync::atomic::{AtomicUsize, Ordering};
-use time::OffsetDateTime;
+use time::{OffsetDateTime, UtcOffset, Weekday};
 
 #[derive(Default)]
 pub struct MetricsDay {
@@ -13,10 +14,11 @@ pub struct Metrics {
 
 #[derive(Default)]
 pub struct Hour {
-    pub categories: [AtomicUsize; 6],
+    pub categories: [AtomicUsize; 7],
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
 #[repr(u8)]
 pub enum Category {
     Redirect,
@@ -25,6 +27,72 @@ pub enum Category {
     RecentlyAdded,
     AuthenticateSession,
     AuthenticateUser,
+    /// Requests rejected by the rate limiter
+    Throttled,
+}
+
+impl Category {
+    pub const ALL: [Category; 7] = [
+        Category::Redirect,
+        Category::Recent,
+        Category::Shorten,
+        Category::RecentlyAdded,
+        Category::AuthenticateSession,
+        Category::AuthenticateUser,
+        Category::Throttled,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Category::Redirect => "redirect",
+            Category::Recent => "recent",
+            Category::Shorten => "shorten",
+            Category::RecentlyAdded => "recently_added",
+            Category::AuthenticateSession => "authenticate_session",
+            Category::AuthenticateUser => "authenticate_user",
+            Category::Throttled => "throttled",
+        }
+    }
+}
+
+/// Weekly usage of a category, peaks are in UTC and absent until a hit is logged
+#[derive(Serialize)]
+pub struct CategoryUsage {
+    pub category: Category,
+    pub total: usize,
+    pub peak_weekday: Option<String>,
+    pub peak_hour: Option<usize>,
+}
+
+/// Hours of the day with low traffic in an operator's UTC offset, `end` is exclusive
+///
+/// The range wraps past midnight when `end` is before `start`, e.g. 22-4.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct QuietHours {
+    start: u8,
+    end: u8,
+    offset: UtcOffset,
+}
+
+impl QuietHours {
+    pub fn new(start: u8, end: u8, offset: UtcOffset) -> anyhow::Result<Self> {
+        anyhow::ensure!(
+            start < 24 && end < 24,
+            "Quiet hours must be within 0-23, got {start}-{end}"
+        );
+        anyhow::ensure!(start != end, "Quiet hours must not be empty");
+
+        Ok(Self { start, end, offset })
+    }
+
+    pub fn contains(&self, at: OffsetDateTime) -> bool {
+        let hour = at.to_offset(self.offset).hour();
+        if self.start < self.end {
+            (self.start..self.end).contains(&hour)
+        } else {
+            hour >= self.start || hour < self.end
+        }
+    }
 }
 
 impl Metrics {
@@ -32,7 +100,7 @@ impl Metrics {
         let date_time = OffsetDateTime::now_utc();
         let date = date_time.date();
         let time = date_time.time();
-        let week_day = date.weekday().number_from_monday() as usize;
+        let week_day = date.weekday().number_days_from_monday() as usize;
         let hour = time.hour() as usize;
 
         self.week_days[week_day].hours[hour].categories[cat as usize]
@@ -56,6 +124,51 @@ impl Metrics {
             .map(|day| day.total_usage_in(cat))
             .sum()
     }
+
+    /// whether this hour of the week usually sees well above the day's average hits in a category
+    pub fn is_busy_hour(&self, cat: Category, at: OffsetDateTime) -> bool {
+        const BUSY_FACTOR: f64 = 1.5;
+        // Too few hits to tell busy hours apart
+        const MIN_BUSY_HITS: usize = 100;
+
+        let day = &self.week_days[at.weekday().number_days_from_monday() as usize];
+        let hits = day.hours[at.hour() as usize].categories[cat as usize].load(Ordering::Relaxed);
+
+        hits >= MIN_BUSY_HITS && hits as f64 > day.avg_hourly_hits_in(cat) * BUSY_FACTOR
+    }
+
+    /// computes the hour of day which saw the most hits in a given category across the week
+    pub fn most_hit_hour_in(&self, cat: Category) -> usize {
+        let (idx, _) = (0..24)
+            .map(|hour| {
+                self.week_days
+                    .iter()
+                    .map(|day| day.hours[hour].categories[cat as usize].load(Ordering::Relaxed))
+                    .sum::<usize>()
+            })
+            .enumerate()
+            .max_by_key(|(_, reds)| *reds)
+            .unwrap();
+        idx
+    }
+
+    pub fn usage_summary(&self) -> Vec<CategoryUsage> {
+        Category::ALL
+            .into_iter()
+            .map(|cat| {
+                let total = self.total_usage_in(cat);
+                let peak = |f: fn(&Self, Category) -> usize| (total > 0).then(|| f(self, cat));
+
+                CategoryUsage {
+                    category: cat,
+                    total,
+                    peak_weekday: peak(Self::most_frequented_weekday_in)
+                        .map(|idx| Weekday::Monday.nth_next(idx as u8).to_string()),
+                    peak_hour: peak(Self::most_hit_hour_in),
+                }
+            })
+            .collect()
+    }
 }
 
 impl MetricsDay {
@@ -127,3 +240,87 @@ impl Hour {
             .fold(0, |acc, e| acc + e.load(Ordering::Relaxed))
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn hit(day: &MetricsDay, hour: usize, cat: Category, count: usize) {
+        day.hours[hour].categories[cat as usize].fetch_add(count, Ordering::Relaxed);
+    }
+
+    #[test]
+    fn peaks_follow_category() {
+        let metrics = Metrics::default();
+        let day = &metrics.week_days[2];
+        hit(day, 3, Category::Redirect, 10);
+        hit(day, 17, Category::Shorten, 4);
+        hit(&metrics.week_days[5], 9, Category::Shorten, 2);
+
+        assert_eq!(day.most_hit_hour(Category::Redirect), 3);
+        assert_eq!(day.most_hit_hour(Category::Shorten), 17);
+        assert_eq!(day.usage_frequency_in(17, Category::Shorten).unwrap(), 1.);
+        assert_eq!(day.usage_frequency_in(3, Category::Shorten).unwrap(), 0.);
+
+        assert_eq!(metrics.most_hit_hour_in(Category::Shorten), 17);
+        assert_eq!(metrics.most_frequented_weekday_in(Category::Shorten), 2);
+        assert_eq!(metrics.total_usage_in(Category::Shorten), 6);
+    }
+
+    #[test]
+    fn busy_hours() {
+        use time::macros::datetime;
+
+        let metrics = Metrics::default();
+        // 2026-10-14 is a Wednesday
+        let wednesday = &metrics.week_days[2];
+        for hour in 0..24 {
+            hit(wednesday, hour, Category::Redirect, 50);
+        }
+        hit(wednesday, 18, Category::Redirect, 450);
+        hit(wednesday, 20, Category::Shorten, 30);
+
+        assert!(metrics.is_busy_hour(Category::Redirect, datetime!(2026-10-14 18:30 UTC)));
+        assert!(!metrics.is_busy_hour(Category::Redirect, datetime!(2026-10-14 03:00 UTC)));
+        // Same hour on another day
+        assert!(!metrics.is_busy_hour(Category::Redirect, datetime!(2026-10-15 18:30 UTC)));
+        // Too few hits to count as busy
+        assert!(!metrics.is_busy_hour(Category::Shorten, datetime!(2026-10-14 20:00 UTC)));
+    }
+
+    #[test]
+    fn quiet_hours_in_offset() {
+        use time::macros::{datetime, offset};
+
+        let night = QuietHours::new(2, 6, offset!(+2)).unwrap();
+        assert!(night.contains(datetime!(2026-10-16 00:00 UTC)));
+        assert!(night.contains(datetime!(2026-10-16 03:59 UTC)));
+        assert!(!night.contains(datetime!(2026-10-16 04:00 UTC)));
+        assert!(!night.contains(datetime!(2026-10-15 23:59 UTC)));
+
+        let wrapping = QuietHours::new(22, 4, UtcOffset::UTC).unwrap();
+        assert!(wrapping.contains(datetime!(2026-10-16 23:30 UTC)));
+        assert!(wrapping.contains(datetime!(2026-10-17 03:00 UTC)));
+        assert!(!wrapping.contains(datetime!(2026-10-16 04:00 UTC)));
+        assert!(!wrapping.contains(datetime!(2026-10-16 21:59 UTC)));
+
+        assert!(QuietHours::new(3, 3, UtcOffset::UTC).is_err());
+        assert!(QuietHours::new(22, 24, UtcOffset::UTC).is_err());
+    }
+
+    #[test]
+    fn summary_omits_peaks_without_hits() {
+        let metrics = Metrics::default();
+        hit(&metrics.week_days[6], 23, Category::Shorten, 1);
+
+        let summary = metrics.usage_summary();
+        let shorten = &summary[Category::Shorten as usize];
+        assert_eq!(shorten.total, 1);
+        assert_eq!(shorten.peak_weekday.as_deref(), Some("Sunday"));
+        assert_eq!(shorten.peak_hour, Some(23));
+
+        let redirect = &summary[Category::Redirect as usize];
+        assert_eq!(redirect.total, 0);
+        assert!(redirect.peak_weekday.is_none() && redirect.peak_hour.is_none());
+    }
+}
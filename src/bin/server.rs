@@ -1,12 +1,14 @@
 use anyhow::Result;
 
-use url_shorten::{app, config};
+use url_shorten::{app, config, telemetry};
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    tracing_subscriber::fmt::init();
+    // Settings decide how traces are exported, log config warnings to stdout meanwhile
+    let config =
+        tracing::subscriber::with_default(tracing_subscriber::fmt().finish(), config::load)?;
 
-    let config = config::load()?;
+    let _telemetry = telemetry::init(&config)?;
 
     app::run(config).await
 }
@@ -1,30 +1,457 @@
-use anyhow::{Result, anyhow, bail};
+use std::{
+    net::{IpAddr, SocketAddr},
+    time::Duration,
+};
+
+use anyhow::{Context, Result, anyhow, bail};
 use config::{Config, File};
 use serde::Deserialize;
+use time::{UtcOffset, macros::format_description};
 use url::Url;
 
+use crate::{
+    app::usage_metrics::QuietHours,
+    domain::{
+        AccountPasswordPolicy, Alias, AliasParseError, LinkPasswordPolicy, PasswordPolicy,
+        ReservedAliases, UrlPolicy,
+    },
+};
+
 const DEFAULT_CONFIG_PATH: &str = "settings.yml";
 const APP_PORT_ENV: &str = "APP_PORT";
+const APP_BIND_ADDRESS_ENV: &str = "APP_BIND_ADDRESS";
+const APP_REDIRECT_PORT_ENV: &str = "APP_REDIRECT_PORT";
+const APP_ALIAS_FILTER_ENV: &str = "APP_ALIAS_FILTER";
+const APP_COMPRESSION_ENV: &str = "APP_COMPRESSION";
+const APP_SQIDS_HIGH_WATER_ENV: &str = "APP_SQIDS_HIGH_WATER";
+const APP_ALIAS_GRACE_DAYS_ENV: &str = "APP_ALIAS_GRACE_DAYS";
+const APP_LINK_IDLE_DAYS_ENV: &str = "APP_LINK_IDLE_DAYS";
+const APP_CLEANUP_BATCH_SIZE_ENV: &str = "APP_CLEANUP_BATCH_SIZE";
+const APP_CLEANUP_BATCH_PAUSE_MS_ENV: &str = "APP_CLEANUP_BATCH_PAUSE_MS";
+const APP_LOW_TRAFFIC_HOURS_ENV: &str = "APP_LOW_TRAFFIC_HOURS";
+const APP_METRICS_RETENTION_DAYS_ENV: &str = "APP_METRICS_RETENTION_DAYS";
+const APP_METRICS_MAX_ENTRIES_ENV: &str = "APP_METRICS_MAX_ENTRIES";
+const APP_UTC_OFFSET_ENV: &str = "APP_UTC_OFFSET";
+const APP_ALIAS_SECRET_ENV: &str = "APP_ALIAS_SECRET";
+const ALIAS_MIN_LENGTH_ENV: &str = "ALIAS_MIN_LENGTH";
+const APP_RESERVED_ALIASES_ENV: &str = "APP_RESERVED_ALIASES";
+const APP_ALIAS_SEPARATORS_ENV: &str = "APP_ALIAS_SEPARATORS";
+const APP_ALLOWED_SCHEMES_ENV: &str = "APP_ALLOWED_SCHEMES";
+const APP_ALLOWED_HOSTS_ENV: &str = "APP_ALLOWED_HOSTS";
+const APP_NORMALIZE_URLS_ENV: &str = "APP_NORMALIZE_URLS";
+const APP_BASE_URL_ENV: &str = "APP_BASE_URL";
+const APP_WEB_BASE_URL_ENV: &str = "APP_WEB_BASE_URL";
+const APP_RATE_LIMIT_PER_MINUTE_ENV: &str = "APP_RATE_LIMIT_PER_MINUTE";
+const APP_RATE_LIMIT_BURST_ENV: &str = "APP_RATE_LIMIT_BURST";
+const APP_UNLOCK_MAX_FAILURES_ENV: &str = "APP_UNLOCK_MAX_FAILURES";
+const APP_UNLOCK_WINDOW_S_ENV: &str = "APP_UNLOCK_WINDOW_S";
+const APP_TRUST_PROXY_ENV: &str = "APP_TRUST_PROXY";
+const APP_SECURE_COOKIES_ENV: &str = "APP_SECURE_COOKIES";
+const APP_FORWARD_QUERY_PARAMS_ENV: &str = "APP_FORWARD_QUERY_PARAMS";
+const APP_INTERSTITIAL_ENV: &str = "APP_INTERSTITIAL";
+const APP_COLLECT_REFERRERS_ENV: &str = "APP_COLLECT_REFERRERS";
+const APP_METRICS_ENABLED_ENV: &str = "APP_METRICS_ENABLED";
+const APP_FIRST_USER_ADMIN_ENV: &str = "APP_FIRST_USER_ADMIN";
+const APP_SESSION_STORE_ENV: &str = "APP_SESSION_STORE";
+const APP_CACHE_BUS_ENV: &str = "APP_CACHE_BUS";
+const APP_SESSION_TTL_HOURS_ENV: &str = "APP_SESSION_TTL_HOURS";
+const APP_SHUTDOWN_TIMEOUT_ENV: &str = "APP_SHUTDOWN_TIMEOUT";
+const APP_OTLP_ENDPOINT_ENV: &str = "APP_OTLP_ENDPOINT";
+const APP_LOG_FORMAT_ENV: &str = "APP_LOG_FORMAT";
+const APP_AUTO_MIGRATE_ENV: &str = "APP_AUTO_MIGRATE";
 const DATABASE_URL_ENV: &str = "DATABASE_URL";
+const DB_MAX_CONNECTIONS_ENV: &str = "DB_MAX_CONNECTIONS";
+const DB_ACQUIRE_TIMEOUT_S_ENV: &str = "DB_ACQUIRE_TIMEOUT_S";
+const DB_IDLE_TIMEOUT_S_ENV: &str = "DB_IDLE_TIMEOUT_S";
+const DB_CONNECT_ATTEMPTS_ENV: &str = "DB_CONNECT_ATTEMPTS";
+const DB_CONNECT_RETRY_DELAY_MS_ENV: &str = "DB_CONNECT_RETRY_DELAY_MS";
+
+const DEFAULT_BIND_ADDRESS: &str = "0.0.0.0";
+const DEFAULT_SHUTDOWN_TIMEOUT_S: u64 = 60;
 
 pub struct Settings {
+    pub bind_address: String,
     pub port: u16,
+    /// Serve `/r/{alias}` on this port instead of `port` when set
+    pub redirect_port: Option<u16>,
+    /// Keep a Bloom filter of aliases to reject missing ones without a DB query
+    ///
+    /// Single instance deployments only, aliases created on other instances would be rejected
+    pub alias_filter: bool,
+    /// Seconds given to in-flight requests and background tasks to finish on shutdown
+    pub shutdown_timeout_s: u64,
+    /// OTLP/HTTP traces endpoint (e.g. `http://localhost:4318/v1/traces`), requires the `otel` feature
+    pub otlp_endpoint: Option<String>,
+    pub log_format: LogFormat,
     pub database_url: Url,
+    pub db_pool: DbPoolConfig,
+    /// Apply pending SQL migrations at startup, disable to migrate separately
+    pub auto_migrate: bool,
+    pub app: AppConfig,
+}
+
+/// Sizing and timeouts of the database connection pool
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DbPoolConfig {
+    pub max_connections: u32,
+    /// Seconds a query waits for a free connection before failing
+    pub acquire_timeout_s: u64,
+    /// Seconds an unused connection is kept open, 0 keeps them open
+    pub idle_timeout_s: u64,
+    /// Tries to connect at startup before giving up, at least 1
+    pub connect_attempts: u32,
+    /// Milliseconds before the first retry, doubled for every following one
+    pub connect_retry_delay_ms: u64,
+}
+
+impl DbPoolConfig {
+    /// Upper bound of the wait between connection attempts
+    const MAX_RETRY_DELAY: Duration = Duration::from_secs(30);
+
+    /// Wait after the failed attempt, counted from 1
+    pub fn retry_delay(&self, attempt: u32) -> Duration {
+        let factor = 2u64.saturating_pow(attempt.saturating_sub(1));
+        Duration::from_millis(self.connect_retry_delay_ms.saturating_mul(factor))
+            .min(Self::MAX_RETRY_DELAY)
+    }
 }
 
-#[derive(Deserialize)]
+impl Default for DbPoolConfig {
+    fn default() -> Self {
+        Self {
+            max_connections: 32,
+            acquire_timeout_s: 15,
+            idle_timeout_s: 10 * 60,
+            connect_attempts: 5,
+            connect_retry_delay_ms: 500,
+        }
+    }
+}
+
+/// Settings used by the app at runtime, shared through `AppState`
+#[derive(Clone)]
+pub struct AppConfig {
+    pub account_password_policy: AccountPasswordPolicy,
+    pub link_password_policy: LinkPasswordPolicy,
+    /// Compress API responses when the client accepts gzip or brotli
+    pub compression: bool,
+    /// Fraction of minimum length aliases that can be generated before warning about longer aliases
+    pub sqids_high_water: f64,
+    /// Days a removed link's alias answers 410 and can't be claimed by a new link
+    pub alias_grace_days: u16,
+    /// Days a link without an explicit expiry lives after its last visit
+    pub link_idle_days: u16,
+    /// Links deleted per statement by the cleanup, smaller batches hold locks for less time
+    pub cleanup_batch_size: u32,
+    /// Milliseconds the cleanup waits between batches, leaving the DB to foreground queries
+    pub cleanup_batch_pause_ms: u64,
+    /// Local quiet hours the cleanup is confined to, by default it only avoids busy hours
+    pub low_traffic_hours: Option<QuietHours>,
+    /// Days daily metrics partitions are kept, link stats totals only count these days
+    pub metrics_retention_days: u16,
+    /// Distinct links counted in memory before hits are flushed ahead of the next interval, unbounded if unset
+    pub metrics_max_entries: Option<usize>,
+    /// Secret shuffling the Sqids alphabet, so generated aliases can't be decoded into sequential ids
+    pub alias_secret: Option<String>,
+    /// Minimum length of generated aliases, shorter ones are padded by Sqids
+    pub alias_min_length: usize,
+    pub reserved_aliases: ReservedAliases,
+    /// Let chosen aliases contain `-` and `_` between letters and digits
+    pub alias_separators: bool,
+    pub url_policy: UrlPolicy,
+    /// Public URL the app is reachable at, without a trailing slash, used to build short URLs
+    pub base_url: String,
+    /// Public URL of the web UI and API when redirects are served on a separate port
+    pub web_base_url: Option<String>,
+    /// Hours a login session stays valid
+    pub session_ttl_hours: u32,
+    pub session_store: SessionStoreKind,
+    pub cache_bus: CacheBusKind,
+    /// Shorten and auth requests allowed per client IP and minute, 0 disables rate limiting
+    pub rate_limit_per_minute: u32,
+    /// Requests a client can make at once before being limited to the rate
+    pub rate_limit_burst: u32,
+    /// Wrong passwords a protected link takes within the window before refusing attempts, 0 disables the limit
+    pub unlock_max_failures: u32,
+    /// Seconds wrong passwords are remembered for
+    pub unlock_window_s: u64,
+    /// Take client IPs from `X-Forwarded-For`, only safe behind a reverse proxy that sets it
+    pub trust_proxy: bool,
+    /// Mark the session cookie `Secure`, required when served over HTTPS
+    pub secure_cookies: bool,
+    /// Append the query string of a short link visit, e.g. UTM parameters, to the target URL
+    pub forward_query_params: bool,
+    /// Show the target on a page to confirm before redirecting, against phishing through short links
+    pub interstitial: bool,
+    /// Count visits by referrer host and language for link stats, no visitor is identified
+    pub collect_referrers: bool,
+    /// Serve Prometheus metrics on `/metrics`
+    pub metrics_enabled: bool,
+    /// Make the first registered user an admin, for bootstrapping a fresh deployment
+    pub first_user_admin: bool,
+}
+
+/// Where login sessions are kept
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SessionStoreKind {
+    /// Process memory, sessions are lost on restart
+    #[default]
+    Memory,
+    /// The database, sessions survive restarts and are shared between instances
+    Postgres,
+}
+
+/// How cached link lookups are invalidated on other instances
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CacheBusKind {
+    /// Nowhere, for single instance deployments
+    #[default]
+    Local,
+    /// Postgres `LISTEN`/`NOTIFY` through the shared database
+    Postgres,
+}
+
+/// How log lines are written to stdout
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// Human readable lines
+    #[default]
+    Text,
+    /// One JSON object per line for log aggregation, with the fields of the current span
+    Json,
+}
+
+impl std::str::FromStr for LogFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            _ => bail!("Unknown log format `{s}`"),
+        }
+    }
+}
+
+impl std::str::FromStr for SessionStoreKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "memory" => Ok(Self::Memory),
+            "postgres" => Ok(Self::Postgres),
+            _ => bail!("Unknown session store `{s}`"),
+        }
+    }
+}
+
+impl std::str::FromStr for CacheBusKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "local" => Ok(Self::Local),
+            "postgres" => Ok(Self::Postgres),
+            _ => bail!("Unknown cache bus `{s}`"),
+        }
+    }
+}
+
+impl AppConfig {
+    /// Public URL redirecting to the link of the alias
+    pub fn short_url(&self, alias: &str) -> String {
+        format!("{}/r/{alias}", self.base_url)
+    }
+
+    /// Public URL of the web UI, the short URL host unless redirects have their own port
+    pub fn web_url(&self) -> &str {
+        self.web_base_url.as_deref().unwrap_or(&self.base_url)
+    }
+
+    /// Whether users may claim the alias for a new link
+    pub fn check_alias(&self, alias: &Alias) -> Result<(), AliasParseError> {
+        if !self.alias_separators && alias.has_separators() {
+            return Err(AliasParseError::InvalidCharacters);
+        }
+        self.reserved_aliases.check(alias)
+    }
+}
+
+impl Default for AppConfig {
+    fn default() -> Self {
+        Self {
+            account_password_policy: AccountPasswordPolicy::default(),
+            link_password_policy: LinkPasswordPolicy::default(),
+            compression: true,
+            sqids_high_water: 0.8,
+            alias_grace_days: 30,
+            link_idle_days: 30,
+            cleanup_batch_size: 5_000,
+            cleanup_batch_pause_ms: 100,
+            low_traffic_hours: None,
+            metrics_retention_days: 400,
+            metrics_max_entries: None,
+            alias_secret: None,
+            alias_min_length: 6,
+            reserved_aliases: ReservedAliases::default(),
+            alias_separators: false,
+            url_policy: UrlPolicy::default(),
+            base_url: "http://localhost:3000".to_string(),
+            web_base_url: None,
+            session_ttl_hours: 7 * 24,
+            session_store: SessionStoreKind::default(),
+            cache_bus: CacheBusKind::default(),
+            rate_limit_per_minute: 0,
+            rate_limit_burst: 10,
+            unlock_max_failures: 10,
+            unlock_window_s: 15 * 60,
+            trust_proxy: false,
+            secure_cookies: false,
+            forward_query_params: false,
+            interstitial: false,
+            collect_referrers: false,
+            metrics_enabled: false,
+            first_user_admin: false,
+        }
+    }
+}
+
+impl Settings {
+    /// Socket address the server listens on, fails if `bind_address` is not an IP address
+    pub fn listen_addr(&self) -> Result<SocketAddr> {
+        let ip: IpAddr = self.bind_address.parse().with_context(|| {
+            format!(
+                "Invalid bind address `{}`, expected an IPv4 or IPv6 address",
+                self.bind_address
+            )
+        })?;
+        Ok(SocketAddr::new(ip, self.port))
+    }
+
+    /// Socket address for the separate redirect server, if it's enabled
+    pub fn redirect_listen_addr(&self) -> Result<Option<SocketAddr>> {
+        let Some(redirect_port) = self.redirect_port else {
+            return Ok(None);
+        };
+
+        if redirect_port == self.port {
+            bail!("Redirect port {redirect_port} must differ from the API port");
+        }
+
+        let addr = self.listen_addr()?;
+        Ok(Some(SocketAddr::new(addr.ip(), redirect_port)))
+    }
+}
+
+/// Settings read from the config file, every field is optional as env takes precedence
+#[derive(Deserialize, Default)]
+#[serde(default)]
 struct DefaultConfig {
-    app_port: u16,
-    db_name: String,
-    db_host: String,
-    db_port: u16,
-    db_user: String,
-    db_pass: String,
+    app_port: Option<u16>,
+    app_bind_address: Option<String>,
+    app_redirect_port: Option<u16>,
+    app_alias_filter: Option<bool>,
+    app_compression: Option<bool>,
+    app_sqids_high_water: Option<f64>,
+    app_alias_grace_days: Option<u16>,
+    app_link_idle_days: Option<u16>,
+    app_cleanup_batch_size: Option<u32>,
+    app_cleanup_batch_pause_ms: Option<u64>,
+    app_low_traffic_hours: Option<String>,
+    app_utc_offset: Option<String>,
+    app_metrics_retention_days: Option<u16>,
+    app_metrics_max_entries: Option<usize>,
+    app_alias_secret: Option<String>,
+    alias_min_length: Option<usize>,
+    app_reserved_aliases: Option<Vec<String>>,
+    app_alias_separators: Option<bool>,
+    app_allowed_schemes: Option<Vec<String>>,
+    app_allowed_hosts: Option<Vec<String>>,
+    app_normalize_urls: Option<bool>,
+    app_base_url: Option<String>,
+    app_web_base_url: Option<String>,
+    app_shutdown_timeout: Option<u64>,
+    app_session_ttl_hours: Option<u32>,
+    app_session_store: Option<SessionStoreKind>,
+    app_cache_bus: Option<CacheBusKind>,
+    app_rate_limit_per_minute: Option<u32>,
+    app_rate_limit_burst: Option<u32>,
+    app_unlock_max_failures: Option<u32>,
+    app_unlock_window_s: Option<u64>,
+    app_trust_proxy: Option<bool>,
+    app_secure_cookies: Option<bool>,
+    app_forward_query_params: Option<bool>,
+    app_interstitial: Option<bool>,
+    app_collect_referrers: Option<bool>,
+    app_metrics_enabled: Option<bool>,
+    app_first_user_admin: Option<bool>,
+    app_otlp_endpoint: Option<String>,
+    app_log_format: Option<LogFormat>,
+    app_auto_migrate: Option<bool>,
+    db_name: Option<String>,
+    db_host: Option<String>,
+    db_port: Option<u16>,
+    db_user: Option<String>,
+    db_pass: Option<String>,
+    db_max_connections: Option<u32>,
+    db_acquire_timeout_s: Option<u64>,
+    db_idle_timeout_s: Option<u64>,
+    db_connect_attempts: Option<u32>,
+    db_connect_retry_delay_ms: Option<u64>,
+    account_password: PasswordPolicyConfig,
+    link_password: PasswordPolicyConfig,
+}
+
+/// Password policy overrides, unset fields keep the policy defaults
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct PasswordPolicyConfig {
+    min_length: Option<usize>,
+    max_length: Option<usize>,
+    require_letter: Option<bool>,
+    require_digit: Option<bool>,
+}
+
+impl PasswordPolicyConfig {
+    fn apply(&self, default: PasswordPolicy) -> Result<PasswordPolicy> {
+        let policy = PasswordPolicy {
+            min_length: self.min_length.unwrap_or(default.min_length),
+            max_length: self.max_length.unwrap_or(default.max_length),
+            require_letter: self.require_letter.unwrap_or(default.require_letter),
+            require_digit: self.require_digit.unwrap_or(default.require_digit),
+        };
+
+        if policy.min_length == 0 || policy.min_length > policy.max_length {
+            bail!(
+                "Invalid password policy length bounds {}..={}",
+                policy.min_length,
+                policy.max_length
+            );
+        }
+
+        Ok(policy)
+    }
+}
+
+impl DefaultConfig {
+    fn database_url(&self) -> Option<String> {
+        Some(format!(
+            "postgres://{}:{}@{}:{}/{}",
+            self.db_user.as_ref()?,
+            self.db_pass.as_ref()?,
+            self.db_host.as_ref()?,
+            self.db_port?,
+            self.db_name.as_ref()?
+        ))
+    }
 }
 
 fn load_default_config() -> Result<DefaultConfig> {
     let settings = Config::builder()
-        .add_source(File::with_name(DEFAULT_CONFIG_PATH))
+        .add_source(File::with_name(DEFAULT_CONFIG_PATH).required(false))
         .build()
         .map_err(|_| anyhow!("Failed to read config file"))?;
 
@@ -48,24 +475,214 @@ where
     }
 }
 
-/// Load configuration from env with fallback to default config file. Early returns if everything is set in env.
+/// Parse a `start-end` range of hours, e.g. `22-4`
+fn parse_hour_range(value: &str) -> Result<(u8, u8)> {
+    let (start, end) = value
+        .split_once('-')
+        .with_context(|| format!("Expected an hour range like 22-4, got `{value}`"))?;
+    Ok((start.trim().parse()?, end.trim().parse()?))
+}
+
+/// Parse a UTC offset like `+02:00`
+fn parse_utc_offset(value: &str) -> Result<UtcOffset> {
+    let format = format_description!("[offset_hour sign:mandatory]:[offset_minute]");
+    UtcOffset::parse(value.trim(), format)
+        .with_context(|| format!("Expected a UTC offset like +02:00, got `{value}`"))
+}
+
+/// Check a public URL, dropping its trailing slash
+fn parse_base_url(value: &str) -> Result<String> {
+    Url::parse(value).with_context(|| format!("Invalid base URL `{value}`"))?;
+    Ok(value.trim_end_matches('/').to_string())
+}
+
+/// Load configuration from env with fallback to default config file.
 pub fn load() -> Result<Settings> {
     let port_opt: Option<u16> = try_from_env(APP_PORT_ENV, |env_str| {
         env_str.parse::<u16>().map_err(|e| e.into())
     })?;
 
+    let bind_address_opt: Option<String> = try_from_env(APP_BIND_ADDRESS_ENV, Ok)?;
+
+    let redirect_port_opt: Option<u16> = try_from_env(APP_REDIRECT_PORT_ENV, |env_str| {
+        env_str.parse::<u16>().map_err(|e| e.into())
+    })?;
+
+    let alias_filter_opt: Option<bool> = try_from_env(APP_ALIAS_FILTER_ENV, |env_str| {
+        env_str.parse::<bool>().map_err(|e| e.into())
+    })?;
+
+    let compression_opt: Option<bool> = try_from_env(APP_COMPRESSION_ENV, |env_str| {
+        env_str.parse::<bool>().map_err(|e| e.into())
+    })?;
+
+    let sqids_high_water_opt: Option<f64> = try_from_env(APP_SQIDS_HIGH_WATER_ENV, |env_str| {
+        env_str.parse::<f64>().map_err(|e| e.into())
+    })?;
+
+    let alias_grace_days_opt: Option<u16> = try_from_env(APP_ALIAS_GRACE_DAYS_ENV, |env_str| {
+        env_str.parse::<u16>().map_err(|e| e.into())
+    })?;
+
+    let link_idle_days_opt: Option<u16> = try_from_env(APP_LINK_IDLE_DAYS_ENV, |env_str| {
+        env_str.parse::<u16>().map_err(|e| e.into())
+    })?;
+
+    let cleanup_batch_size_opt: Option<u32> =
+        try_from_env(APP_CLEANUP_BATCH_SIZE_ENV, |env_str| {
+            env_str.parse::<u32>().map_err(|e| e.into())
+        })?;
+
+    let cleanup_batch_pause_ms_opt: Option<u64> =
+        try_from_env(APP_CLEANUP_BATCH_PAUSE_MS_ENV, |env_str| {
+            env_str.parse::<u64>().map_err(|e| e.into())
+        })?;
+
+    let low_traffic_hours_opt: Option<(u8, u8)> =
+        try_from_env(APP_LOW_TRAFFIC_HOURS_ENV, |env_str| {
+            parse_hour_range(&env_str)
+        })?;
+
+    let metrics_retention_days_opt: Option<u16> =
+        try_from_env(APP_METRICS_RETENTION_DAYS_ENV, |env_str| {
+            env_str.parse::<u16>().map_err(|e| e.into())
+        })?;
+
+    let metrics_max_entries_opt: Option<usize> =
+        try_from_env(APP_METRICS_MAX_ENTRIES_ENV, |env_str| {
+            env_str.parse::<usize>().map_err(|e| e.into())
+        })?;
+
+    let utc_offset_opt: Option<UtcOffset> =
+        try_from_env(APP_UTC_OFFSET_ENV, |env_str| parse_utc_offset(&env_str))?;
+
+    let alias_secret_opt: Option<String> = try_from_env(APP_ALIAS_SECRET_ENV, Ok)?;
+
+    let alias_min_length_opt: Option<usize> = try_from_env(ALIAS_MIN_LENGTH_ENV, |env_str| {
+        env_str.parse::<usize>().map_err(|e| e.into())
+    })?;
+
+    let reserved_aliases_opt: Option<Vec<String>> =
+        try_from_env(APP_RESERVED_ALIASES_ENV, |env_str| {
+            Ok(env_str.split(',').map(|s| s.trim().to_string()).collect())
+        })?;
+
+    let alias_separators_opt: Option<bool> = try_from_env(APP_ALIAS_SEPARATORS_ENV, |env_str| {
+        env_str.parse::<bool>().map_err(|e| e.into())
+    })?;
+
+    let allowed_schemes_opt: Option<Vec<String>> =
+        try_from_env(APP_ALLOWED_SCHEMES_ENV, |env_str| {
+            Ok(env_str.split(',').map(|s| s.trim().to_string()).collect())
+        })?;
+
+    let allowed_hosts_opt: Option<Vec<String>> = try_from_env(APP_ALLOWED_HOSTS_ENV, |env_str| {
+        Ok(env_str.split(',').map(|s| s.trim().to_string()).collect())
+    })?;
+
+    let normalize_urls_opt: Option<bool> = try_from_env(APP_NORMALIZE_URLS_ENV, |env_str| {
+        env_str.parse::<bool>().map_err(|e| e.into())
+    })?;
+
+    let base_url_opt: Option<String> = try_from_env(APP_BASE_URL_ENV, Ok)?;
+
+    let web_base_url_opt: Option<String> = try_from_env(APP_WEB_BASE_URL_ENV, Ok)?;
+
+    let session_ttl_hours_opt: Option<u32> = try_from_env(APP_SESSION_TTL_HOURS_ENV, |env_str| {
+        env_str.parse::<u32>().map_err(|e| e.into())
+    })?;
+
+    let session_store_opt: Option<SessionStoreKind> =
+        try_from_env(APP_SESSION_STORE_ENV, |env_str| env_str.parse())?;
+
+    let cache_bus_opt: Option<CacheBusKind> =
+        try_from_env(APP_CACHE_BUS_ENV, |env_str| env_str.parse())?;
+
+    let rate_limit_per_minute_opt: Option<u32> =
+        try_from_env(APP_RATE_LIMIT_PER_MINUTE_ENV, |env_str| {
+            env_str.parse::<u32>().map_err(|e| e.into())
+        })?;
+
+    let rate_limit_burst_opt: Option<u32> = try_from_env(APP_RATE_LIMIT_BURST_ENV, |env_str| {
+        env_str.parse::<u32>().map_err(|e| e.into())
+    })?;
+
+    let unlock_max_failures_opt: Option<u32> =
+        try_from_env(APP_UNLOCK_MAX_FAILURES_ENV, |env_str| {
+            env_str.parse::<u32>().map_err(|e| e.into())
+        })?;
+
+    let unlock_window_s_opt: Option<u64> = try_from_env(APP_UNLOCK_WINDOW_S_ENV, |env_str| {
+        env_str.parse::<u64>().map_err(|e| e.into())
+    })?;
+
+    let trust_proxy_opt: Option<bool> = try_from_env(APP_TRUST_PROXY_ENV, |env_str| {
+        env_str.parse::<bool>().map_err(|e| e.into())
+    })?;
+
+    let secure_cookies_opt: Option<bool> = try_from_env(APP_SECURE_COOKIES_ENV, |env_str| {
+        env_str.parse::<bool>().map_err(|e| e.into())
+    })?;
+
+    let forward_query_params_opt: Option<bool> =
+        try_from_env(APP_FORWARD_QUERY_PARAMS_ENV, |env_str| {
+            env_str.parse::<bool>().map_err(|e| e.into())
+        })?;
+
+    let interstitial_opt: Option<bool> = try_from_env(APP_INTERSTITIAL_ENV, |env_str| {
+        env_str.parse::<bool>().map_err(|e| e.into())
+    })?;
+
+    let collect_referrers_opt: Option<bool> = try_from_env(APP_COLLECT_REFERRERS_ENV, |env_str| {
+        env_str.parse::<bool>().map_err(|e| e.into())
+    })?;
+
+    let metrics_enabled_opt: Option<bool> = try_from_env(APP_METRICS_ENABLED_ENV, |env_str| {
+        env_str.parse::<bool>().map_err(|e| e.into())
+    })?;
+
+    let first_user_admin_opt: Option<bool> = try_from_env(APP_FIRST_USER_ADMIN_ENV, |env_str| {
+        env_str.parse::<bool>().map_err(|e| e.into())
+    })?;
+
+    let shutdown_timeout_opt: Option<u64> = try_from_env(APP_SHUTDOWN_TIMEOUT_ENV, |env_str| {
+        env_str.parse::<u64>().map_err(|e| e.into())
+    })?;
+
+    let otlp_endpoint_opt: Option<String> = try_from_env(APP_OTLP_ENDPOINT_ENV, Ok)?;
+
+    let log_format_opt: Option<LogFormat> =
+        try_from_env(APP_LOG_FORMAT_ENV, |env_str| env_str.parse())?;
+
+    let auto_migrate_opt: Option<bool> = try_from_env(APP_AUTO_MIGRATE_ENV, |env_str| {
+        env_str.parse::<bool>().map_err(|e| e.into())
+    })?;
+
     let database_url_opt: Option<Url> = try_from_env(DATABASE_URL_ENV, |env_str| {
         Url::parse(&env_str).map_err(|e| e.into())
     })?;
 
-    // to avoid destructuring database_url_opt (we need it later)
-    #[allow(clippy::unnecessary_unwrap)]
-    if port_opt.is_some() && database_url_opt.is_some() {
-        return Ok(Settings {
-            port: port_opt.unwrap(),
-            database_url: database_url_opt.unwrap(),
-        });
-    }
+    let db_max_connections_opt: Option<u32> = try_from_env(DB_MAX_CONNECTIONS_ENV, |env_str| {
+        env_str.parse::<u32>().map_err(|e| e.into())
+    })?;
+
+    let db_acquire_timeout_s_opt: Option<u64> =
+        try_from_env(DB_ACQUIRE_TIMEOUT_S_ENV, |env_str| {
+            env_str.parse::<u64>().map_err(|e| e.into())
+        })?;
+
+    let db_idle_timeout_s_opt: Option<u64> = try_from_env(DB_IDLE_TIMEOUT_S_ENV, |env_str| {
+        env_str.parse::<u64>().map_err(|e| e.into())
+    })?;
+
+    let db_connect_attempts_opt: Option<u32> = try_from_env(DB_CONNECT_ATTEMPTS_ENV, |env_str| {
+        env_str.parse::<u32>().map_err(|e| e.into())
+    })?;
+
+    let db_connect_retry_delay_ms_opt: Option<u64> =
+        try_from_env(DB_CONNECT_RETRY_DELAY_MS_ENV, |env_str| {
+            env_str.parse::<u64>().map_err(|e| e.into())
+        })?;
 
     let config = load_default_config()?;
 
@@ -73,21 +690,321 @@ pub fn load() -> Result<Settings> {
         Some(val) => val,
         None => {
             tracing::warn!("{APP_PORT_ENV} is not set, using value from {DEFAULT_CONFIG_PATH}");
-            config.app_port
+            config
+                .app_port
+                .with_context(|| format!("app_port is missing from {DEFAULT_CONFIG_PATH}"))?
         }
     };
 
+    let bind_address = bind_address_opt
+        .or(config.app_bind_address.clone())
+        .unwrap_or_else(|| DEFAULT_BIND_ADDRESS.to_string());
+
+    let redirect_port = redirect_port_opt.or(config.app_redirect_port);
+
+    let alias_filter = alias_filter_opt
+        .or(config.app_alias_filter)
+        .unwrap_or(false);
+
+    let compression = compression_opt.or(config.app_compression).unwrap_or(true);
+
+    let sqids_high_water = sqids_high_water_opt
+        .or(config.app_sqids_high_water)
+        .unwrap_or(AppConfig::default().sqids_high_water);
+    if !(sqids_high_water > 0.0 && sqids_high_water <= 1.0) {
+        bail!("Sqids high-water mark must be in (0, 1], got {sqids_high_water}");
+    }
+
+    let alias_grace_days = alias_grace_days_opt
+        .or(config.app_alias_grace_days)
+        .unwrap_or(AppConfig::default().alias_grace_days);
+
+    let link_idle_days = link_idle_days_opt
+        .or(config.app_link_idle_days)
+        .unwrap_or(AppConfig::default().link_idle_days);
+    if link_idle_days == 0 {
+        bail!("Link idle days must be at least 1");
+    }
+
+    let cleanup_batch_size = cleanup_batch_size_opt
+        .or(config.app_cleanup_batch_size)
+        .unwrap_or(AppConfig::default().cleanup_batch_size);
+    if cleanup_batch_size == 0 {
+        bail!("Cleanup batch size must be at least 1");
+    }
+
+    let cleanup_batch_pause_ms = cleanup_batch_pause_ms_opt
+        .or(config.app_cleanup_batch_pause_ms)
+        .unwrap_or(AppConfig::default().cleanup_batch_pause_ms);
+
+    let low_traffic_hours = match low_traffic_hours_opt {
+        Some(hours) => Some(hours),
+        None => config
+            .app_low_traffic_hours
+            .as_deref()
+            .map(parse_hour_range)
+            .transpose()?,
+    };
+    let utc_offset = match utc_offset_opt {
+        Some(offset) => offset,
+        None => config
+            .app_utc_offset
+            .as_deref()
+            .map(parse_utc_offset)
+            .transpose()?
+            .unwrap_or(UtcOffset::UTC),
+    };
+    let low_traffic_hours = low_traffic_hours
+        .map(|(start, end)| QuietHours::new(start, end, utc_offset))
+        .transpose()?;
+
+    let metrics_retention_days = metrics_retention_days_opt
+        .or(config.app_metrics_retention_days)
+        .unwrap_or(AppConfig::default().metrics_retention_days);
+    // Link stats show the last week of daily hits
+    if metrics_retention_days < 7 {
+        bail!("Metrics retention must be at least 7 days, got {metrics_retention_days}");
+    }
+
+    let metrics_max_entries = metrics_max_entries_opt.or(config.app_metrics_max_entries);
+    if metrics_max_entries == Some(0) {
+        bail!("Metrics max entries must be at least 1");
+    }
+
+    let alias_secret = alias_secret_opt
+        .or(config.app_alias_secret.clone())
+        .filter(|secret| !secret.is_empty());
+
+    let alias_min_length = alias_min_length_opt
+        .or(config.alias_min_length)
+        .unwrap_or(AppConfig::default().alias_min_length);
+    if alias_min_length == 0 {
+        bail!("Alias min length must be greater than zero");
+    }
+    // Generated aliases must still parse as aliases to be looked up
+    if !(Alias::MIN_ALIAS_LENGTH..=Alias::MAX_ALIAS_LENGTH).contains(&alias_min_length) {
+        bail!(
+            "Alias min length must be between {} and {}, got {alias_min_length}",
+            Alias::MIN_ALIAS_LENGTH,
+            Alias::MAX_ALIAS_LENGTH
+        );
+    }
+
+    // Configured list replaces the defaults
+    let reserved_aliases = reserved_aliases_opt
+        .or(config.app_reserved_aliases.clone())
+        .map(ReservedAliases::new)
+        .unwrap_or_default();
+
+    let alias_separators = alias_separators_opt
+        .or(config.app_alias_separators)
+        .unwrap_or(false);
+
+    let mut url_policy = UrlPolicy::default();
+    if let Some(schemes) = allowed_schemes_opt.or(config.app_allowed_schemes.clone()) {
+        url_policy.allowed_schemes = schemes
+            .iter()
+            .map(|scheme| scheme.to_ascii_lowercase())
+            .filter(|scheme| !scheme.is_empty())
+            .collect();
+        if url_policy.allowed_schemes.is_empty() {
+            bail!("At least one URL scheme must be allowed");
+        }
+    }
+    if let Some(hosts) = allowed_hosts_opt.or(config.app_allowed_hosts.clone()) {
+        url_policy.allowed_hosts = hosts
+            .iter()
+            .map(|host| UrlPolicy::normalize_host(host))
+            .filter(|host| !host.is_empty())
+            .collect();
+    }
+    url_policy.normalize = normalize_urls_opt
+        .or(config.app_normalize_urls)
+        .unwrap_or(false);
+
+    // Short URLs point at the port serving redirects
+    let base_url = match base_url_opt.or(config.app_base_url.clone()) {
+        Some(base_url) => parse_base_url(&base_url)?,
+        None => format!("http://localhost:{}", redirect_port.unwrap_or(port)),
+    };
+
+    let web_base_url = match web_base_url_opt.or(config.app_web_base_url.clone()) {
+        Some(web_base_url) => Some(parse_base_url(&web_base_url)?),
+        None if redirect_port.is_some() => {
+            let web_base_url = format!("http://localhost:{port}");
+            tracing::warn!(
+                "Redirects are served on their own port but {APP_WEB_BASE_URL_ENV} is not set, using {web_base_url}"
+            );
+            Some(web_base_url)
+        }
+        None => None,
+    };
+
+    let session_ttl_hours = session_ttl_hours_opt
+        .or(config.app_session_ttl_hours)
+        .unwrap_or(AppConfig::default().session_ttl_hours);
+    if session_ttl_hours == 0 {
+        bail!("Session TTL must be at least one hour");
+    }
+
+    let session_store = session_store_opt
+        .or(config.app_session_store)
+        .unwrap_or_default();
+
+    let cache_bus = cache_bus_opt.or(config.app_cache_bus).unwrap_or_default();
+
+    let rate_limit_per_minute = rate_limit_per_minute_opt
+        .or(config.app_rate_limit_per_minute)
+        .unwrap_or(AppConfig::default().rate_limit_per_minute);
+
+    let rate_limit_burst = rate_limit_burst_opt
+        .or(config.app_rate_limit_burst)
+        .unwrap_or(AppConfig::default().rate_limit_burst);
+    if rate_limit_burst == 0 {
+        bail!("Rate limit burst must be at least 1");
+    }
+
+    let unlock_max_failures = unlock_max_failures_opt
+        .or(config.app_unlock_max_failures)
+        .unwrap_or(AppConfig::default().unlock_max_failures);
+
+    let unlock_window_s = unlock_window_s_opt
+        .or(config.app_unlock_window_s)
+        .unwrap_or(AppConfig::default().unlock_window_s);
+    if unlock_window_s == 0 {
+        bail!("Unlock window must be at least one second");
+    }
+
+    let trust_proxy = trust_proxy_opt.or(config.app_trust_proxy).unwrap_or(false);
+
+    let secure_cookies = secure_cookies_opt
+        .or(config.app_secure_cookies)
+        .unwrap_or(false);
+
+    let forward_query_params = forward_query_params_opt
+        .or(config.app_forward_query_params)
+        .unwrap_or(false);
+
+    let interstitial = interstitial_opt
+        .or(config.app_interstitial)
+        .unwrap_or(false);
+
+    let collect_referrers = collect_referrers_opt
+        .or(config.app_collect_referrers)
+        .unwrap_or(false);
+
+    let metrics_enabled = metrics_enabled_opt
+        .or(config.app_metrics_enabled)
+        .unwrap_or(false);
+
+    let first_user_admin = first_user_admin_opt
+        .or(config.app_first_user_admin)
+        .unwrap_or(false);
+
+    let shutdown_timeout_s = shutdown_timeout_opt
+        .or(config.app_shutdown_timeout)
+        .unwrap_or(DEFAULT_SHUTDOWN_TIMEOUT_S);
+
+    let otlp_endpoint = otlp_endpoint_opt.or(config.app_otlp_endpoint.clone());
+
+    let log_format = log_format_opt.or(config.app_log_format).unwrap_or_default();
+
+    let auto_migrate = auto_migrate_opt.or(config.app_auto_migrate).unwrap_or(true);
+
     let database_url = match database_url_opt {
         Some(url) => url,
         None => {
             tracing::warn!("{DATABASE_URL_ENV} is not set, using value from {DEFAULT_CONFIG_PATH}");
-            let url_str = format!(
-                "postgres://{}:{}@{}:{}/{}",
-                config.db_user, config.db_pass, config.db_host, config.db_port, config.db_name
-            );
+            let url_str = config.database_url().with_context(|| {
+                format!("Database settings are missing from {DEFAULT_CONFIG_PATH}")
+            })?;
             Url::parse(&url_str)?
         }
     };
 
-    Ok(Settings { port, database_url })
+    let db_pool = DbPoolConfig {
+        max_connections: db_max_connections_opt
+            .or(config.db_max_connections)
+            .unwrap_or(DbPoolConfig::default().max_connections),
+        acquire_timeout_s: db_acquire_timeout_s_opt
+            .or(config.db_acquire_timeout_s)
+            .unwrap_or(DbPoolConfig::default().acquire_timeout_s),
+        idle_timeout_s: db_idle_timeout_s_opt
+            .or(config.db_idle_timeout_s)
+            .unwrap_or(DbPoolConfig::default().idle_timeout_s),
+        connect_attempts: db_connect_attempts_opt
+            .or(config.db_connect_attempts)
+            .unwrap_or(DbPoolConfig::default().connect_attempts),
+        connect_retry_delay_ms: db_connect_retry_delay_ms_opt
+            .or(config.db_connect_retry_delay_ms)
+            .unwrap_or(DbPoolConfig::default().connect_retry_delay_ms),
+    };
+    if db_pool.max_connections == 0 {
+        bail!("Database pool needs at least one connection");
+    }
+    if db_pool.acquire_timeout_s == 0 {
+        bail!("Database acquire timeout must be at least 1 second");
+    }
+    if db_pool.connect_attempts == 0 {
+        bail!("Database connection needs at least one attempt");
+    }
+
+    let app = AppConfig {
+        account_password_policy: AccountPasswordPolicy(
+            config
+                .account_password
+                .apply(AccountPasswordPolicy::default().0)
+                .context("account_password")?,
+        ),
+        link_password_policy: LinkPasswordPolicy(
+            config
+                .link_password
+                .apply(LinkPasswordPolicy::default().0)
+                .context("link_password")?,
+        ),
+        compression,
+        sqids_high_water,
+        alias_grace_days,
+        link_idle_days,
+        cleanup_batch_size,
+        cleanup_batch_pause_ms,
+        low_traffic_hours,
+        metrics_retention_days,
+        metrics_max_entries,
+        alias_secret,
+        alias_min_length,
+        reserved_aliases,
+        alias_separators,
+        url_policy,
+        base_url,
+        web_base_url,
+        session_ttl_hours,
+        session_store,
+        cache_bus,
+        rate_limit_per_minute,
+        rate_limit_burst,
+        unlock_max_failures,
+        unlock_window_s,
+        trust_proxy,
+        secure_cookies,
+        forward_query_params,
+        interstitial,
+        collect_referrers,
+        metrics_enabled,
+        first_user_admin,
+    };
+
+    Ok(Settings {
+        bind_address,
+        port,
+        redirect_port,
+        alias_filter,
+        shutdown_timeout_s,
+        otlp_endpoint,
+        log_format,
+        database_url,
+        db_pool,
+        auto_migrate,
+        app,
+    })
 }
@@ -1,3 +1,5 @@
+use std::collections::HashSet;
+
 use thiserror::Error;
 
 #[derive(Debug, Clone, Hash, PartialEq, Eq)]
@@ -9,17 +11,26 @@ pub enum AliasParseError {
     TooShort,
     #[error("too long")]
     TooLong,
+    /// Anything but ASCII letters and digits, or `-` and `_` at the ends or next to each other
     #[error("contains invalid characters")]
     InvalidCharacters,
+    #[error("is reserved")]
+    Reserved,
 }
 
 impl Alias {
     pub const MIN_ALIAS_LENGTH: usize = 4;
     pub const MAX_ALIAS_LENGTH: usize = 64;
+    /// Characters allowed between letters and digits, e.g. `my-cool-link`
+    pub const SEPARATORS: [char; 2] = ['-', '_'];
 
     pub fn as_str(&self) -> &str {
         &self.0
     }
+
+    pub fn has_separators(&self) -> bool {
+        self.0.contains(Self::SEPARATORS)
+    }
 }
 
 impl TryFrom<String> for Alias {
@@ -36,7 +47,16 @@ impl TryFrom<String> for Alias {
             return Err(AliasParseError::TooLong);
         }
 
-        let valid = value.chars().all(|c| c.is_ascii_alphanumeric());
+        let is_separator = |c: char| Self::SEPARATORS.contains(&c);
+        let valid = value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || is_separator(c))
+            && !value.starts_with(is_separator)
+            && !value.ends_with(is_separator)
+            && !value
+                .as_bytes()
+                .windows(2)
+                .any(|pair| is_separator(pair[0] as char) && is_separator(pair[1] as char));
 
         if !valid {
             return Err(AliasParseError::InvalidCharacters);
@@ -46,13 +66,58 @@ impl TryFrom<String> for Alias {
     }
 }
 
+/// Aliases users can't claim, matched case-insensitively
+#[derive(Debug, Clone)]
+pub struct ReservedAliases(HashSet<String>);
+
+impl ReservedAliases {
+    /// Words colliding with our own routes
+    pub const DEFAULT: &[&str] = &[
+        "r", "api", "unlock", "admin", "login", "logout", "register", "assets", "static", "index",
+        "health",
+    ];
+
+    pub fn new<I, S>(aliases: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        Self(
+            aliases
+                .into_iter()
+                .map(|alias| alias.as_ref().to_lowercase())
+                .collect(),
+        )
+    }
+
+    pub fn check(&self, alias: &Alias) -> Result<(), AliasParseError> {
+        if self.0.contains(&alias.as_str().to_lowercase()) {
+            return Err(AliasParseError::Reserved);
+        }
+        Ok(())
+    }
+}
+
+impl Default for ReservedAliases {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT)
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
 
     #[test]
     fn allowed_aliases() {
-        let aliases = ["abcdef", "abcde1234567890", "abcde12345678901234"];
+        let aliases = [
+            "abcdef",
+            "abcde1234567890",
+            "abcde12345678901234",
+            "ab-cde",
+            "ab_cde",
+            "my-cool_link-2",
+        ];
         for alias in aliases {
             let result: Result<Alias, _> = alias.to_string().try_into();
             assert!(
@@ -70,8 +135,11 @@ mod test {
             "",
             "a",
             "abcde1234567890!@#$%",
-            "ab-cde",
-            "ab_cde",
+            "-abcde",
+            "abcde_",
+            "ab--cde",
+            "ab-_cde",
+            "----",
             "ab.cde",
             "ab&cde",
             "ab cde",
@@ -87,4 +155,20 @@ mod test {
             );
         }
     }
+
+    #[test]
+    fn reserved_aliases() {
+        let reserved = ReservedAliases::default();
+        for alias in ["unlock", "UNLOCK", "Admin"] {
+            let alias: Alias = alias.to_string().try_into().unwrap();
+            assert!(
+                matches!(reserved.check(&alias), Err(AliasParseError::Reserved)),
+                "{} should be reserved",
+                alias.as_str()
+            );
+        }
+
+        let alias: Alias = "unlocked".to_string().try_into().unwrap();
+        assert!(reserved.check(&alias).is_ok());
+    }
 }
@@ -1,7 +1,11 @@
 mod alias;
+mod password;
 mod url;
 mod user;
 
-pub use alias::{Alias, AliasParseError};
-pub use url::{Url, UrlParseError};
-pub use user::{CredentialsError, User, UserId, UserName, UserPassword};
+pub use alias::{Alias, AliasParseError, ReservedAliases};
+pub use password::{
+    AccountPasswordPolicy, LinkPasswordPolicy, PasswordPolicy, PasswordPolicyError,
+};
+pub use url::{Url, UrlParseError, UrlPolicy};
+pub use user::{CredentialsError, ImportedPasswordHash, User, UserId, UserName, UserPassword};
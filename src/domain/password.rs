@@ -0,0 +1,155 @@
+use std::ops::Deref;
+
+use thiserror::Error;
+
+/// Length and character rules for a password
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PasswordPolicy {
+    pub min_length: usize,
+    pub max_length: usize,
+    pub require_letter: bool,
+    pub require_digit: bool,
+}
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum PasswordPolicyError {
+    #[error("shorter than {0} characters")]
+    TooShort(usize),
+    #[error("longer than {0} characters")]
+    TooLong(usize),
+    #[error("contains invalid characters")]
+    InvalidChars,
+    #[error("does not contain a letter")]
+    MissingLetter,
+    #[error("does not contain a digit")]
+    MissingDigit,
+}
+
+impl PasswordPolicy {
+    pub fn check(&self, password: &str) -> Result<(), PasswordPolicyError> {
+        let len = password.chars().count();
+
+        if len < self.min_length {
+            return Err(PasswordPolicyError::TooShort(self.min_length));
+        }
+        if len > self.max_length {
+            return Err(PasswordPolicyError::TooLong(self.max_length));
+        }
+
+        if password.chars().any(|c| c.is_control()) {
+            return Err(PasswordPolicyError::InvalidChars);
+        }
+
+        if self.require_letter && !password.chars().any(|c| c.is_alphabetic()) {
+            return Err(PasswordPolicyError::MissingLetter);
+        }
+        if self.require_digit && !password.chars().any(|c| c.is_ascii_digit()) {
+            return Err(PasswordPolicyError::MissingDigit);
+        }
+
+        Ok(())
+    }
+}
+
+/// Policy for user account passwords
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AccountPasswordPolicy(pub PasswordPolicy);
+
+/// Policy for passwords protecting links
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LinkPasswordPolicy(pub PasswordPolicy);
+
+impl Deref for AccountPasswordPolicy {
+    type Target = PasswordPolicy;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl Deref for LinkPasswordPolicy {
+    type Target = PasswordPolicy;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl Default for AccountPasswordPolicy {
+    fn default() -> Self {
+        Self(PasswordPolicy {
+            min_length: 8,
+            max_length: 128,
+            require_letter: false,
+            require_digit: false,
+        })
+    }
+}
+
+impl Default for LinkPasswordPolicy {
+    fn default() -> Self {
+        Self(PasswordPolicy {
+            min_length: 1,
+            max_length: 128,
+            require_letter: false,
+            require_digit: false,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn length_bounds() {
+        let policy = PasswordPolicy {
+            min_length: 4,
+            max_length: 8,
+            require_letter: false,
+            require_digit: false,
+        };
+
+        assert_eq!(policy.check("abc"), Err(PasswordPolicyError::TooShort(4)));
+        assert_eq!(
+            policy.check("abcdefghi"),
+            Err(PasswordPolicyError::TooLong(8))
+        );
+        assert_eq!(
+            policy.check("ab\ncd"),
+            Err(PasswordPolicyError::InvalidChars)
+        );
+        assert_eq!(policy.check("abcd"), Ok(()));
+        assert_eq!(policy.check("abcdefgh"), Ok(()));
+    }
+
+    #[test]
+    fn character_rules() {
+        let policy = PasswordPolicy {
+            min_length: 1,
+            max_length: 16,
+            require_letter: true,
+            require_digit: true,
+        };
+
+        assert_eq!(
+            policy.check("12345"),
+            Err(PasswordPolicyError::MissingLetter)
+        );
+        assert_eq!(
+            policy.check("abcde"),
+            Err(PasswordPolicyError::MissingDigit)
+        );
+        assert_eq!(policy.check("abc12"), Ok(()));
+    }
+
+    #[test]
+    fn policies_are_independent() {
+        let account = AccountPasswordPolicy::default();
+        let link = LinkPasswordPolicy::default();
+
+        // short passwords are fine for links but not for accounts
+        assert!(link.check("abc").is_ok());
+        assert_eq!(account.check("abc"), Err(PasswordPolicyError::TooShort(8)));
+    }
+}
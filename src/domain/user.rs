@@ -1,14 +1,19 @@
+use argon2::PasswordHash;
+
+use crate::domain::{AccountPasswordPolicy, PasswordPolicyError};
+
 pub type UserId = i64;
 
 #[derive(Debug, Clone)]
 pub struct User {
     id: UserId,
     name: UserName,
+    is_admin: bool,
 }
 
 impl User {
-    pub fn new(id: UserId, name: UserName) -> Self {
-        Self { id, name }
+    pub fn new(id: UserId, name: UserName, is_admin: bool) -> Self {
+        Self { id, name, is_admin }
     }
 
     pub fn id(&self) -> UserId {
@@ -18,15 +23,24 @@ impl User {
     pub fn name(&self) -> &str {
         self.name.as_str()
     }
+
+    pub fn is_admin(&self) -> bool {
+        self.is_admin
+    }
 }
 
 pub enum CredentialsError {
     UsernameInvalidChars,
     UsernameTooShort,
     UsernameTooLong,
-    PasswordInvalidChars,
-    PasswordTooShort,
-    PasswordTooLong,
+    Password(PasswordPolicyError),
+    PasswordHashUnsupported,
+}
+
+impl From<PasswordPolicyError> for CredentialsError {
+    fn from(error: PasswordPolicyError) -> Self {
+        CredentialsError::Password(error)
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -69,31 +83,92 @@ impl TryFrom<String> for UserName {
 pub struct UserPassword(String);
 
 impl UserPassword {
-    pub const MIN_PASSWORD_LENGTH: usize = 8;
-    pub const MAX_PASSWORD_LENGTH: usize = 128;
+    /// Validate a new password against the account password policy
+    pub fn new(value: String, policy: &AccountPasswordPolicy) -> Result<Self, CredentialsError> {
+        policy.check(&value)?;
+        Ok(UserPassword(value))
+    }
+
+    /// Accept a password for an existing account, only bounding its length
+    ///
+    /// The policy might have changed since the account was created, so it's not enforced here
+    pub fn existing(
+        value: String,
+        policy: &AccountPasswordPolicy,
+    ) -> Result<Self, CredentialsError> {
+        let len = value.chars().count();
+        if len == 0 {
+            return Err(PasswordPolicyError::TooShort(1).into());
+        }
+        if len > policy.max_length {
+            return Err(PasswordPolicyError::TooLong(policy.max_length).into());
+        }
+
+        Ok(UserPassword(value))
+    }
 
     pub fn as_str(&self) -> &str {
         &self.0
     }
 }
 
-impl TryFrom<String> for UserPassword {
+/// Password hash carried over from another system, either an Argon2 PHC string or a bcrypt hash
+#[derive(Debug, Clone)]
+pub struct ImportedPasswordHash(String);
+
+impl ImportedPasswordHash {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl TryFrom<String> for ImportedPasswordHash {
     type Error = CredentialsError;
 
     fn try_from(value: String) -> Result<Self, Self::Error> {
-        let len = value.chars().count();
-
-        if len < Self::MIN_PASSWORD_LENGTH {
-            return Err(CredentialsError::PasswordTooShort);
-        }
-        if len > Self::MAX_PASSWORD_LENGTH {
-            return Err(CredentialsError::PasswordTooLong);
+        let supported = if value.starts_with("$2") {
+            value.parse::<bcrypt::HashParts>().is_ok()
+        } else {
+            PasswordHash::new(&value)
+                .is_ok_and(|hash| argon2::Algorithm::try_from(hash.algorithm).is_ok())
+        };
+
+        if !supported {
+            return Err(CredentialsError::PasswordHashUnsupported);
         }
 
-        if value.chars().any(|c| c.is_control()) {
-            return Err(CredentialsError::PasswordInvalidChars);
+        Ok(ImportedPasswordHash(value))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn imported_hash_formats() {
+        let supported = [
+            "$argon2id$v=19$m=19456,t=2,p=1$c29tZXNhbHQ$RdescudvJCsgt3ub+b+dWRWJTmaaJObG",
+            "$2b$04$EGdrhbKUv8Oc9vGiXX0HQOxSg445d458Muh7DAHskb6QbtCvdxcie",
+        ];
+        for hash in supported {
+            assert!(
+                ImportedPasswordHash::try_from(hash.to_string()).is_ok(),
+                "{hash} should be accepted"
+            );
         }
 
-        Ok(UserPassword(value))
+        let unsupported = [
+            "",
+            "password123",
+            "$2b$04$short",
+            "$pbkdf2-sha256$i=1000$c29tZXNhbHQ$RdescudvJCsgt3ub+b+dWRWJTmaaJObG",
+        ];
+        for hash in unsupported {
+            assert!(
+                ImportedPasswordHash::try_from(hash.to_string()).is_err(),
+                "{hash} should be rejected"
+            );
+        }
     }
 }
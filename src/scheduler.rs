@@ -1,6 +1,7 @@
-use std::time::Duration;
+use std::{sync::Arc, time::Duration};
 
 use tokio::{
+    sync::Notify,
     task::JoinSet,
     time::{self, Instant},
 };
@@ -21,30 +22,56 @@ impl Scheduler {
     }
 
     /// Spawns a background task, immediately running it at provided interval
-    pub fn spawn_task<P, F, Fut>(
+    pub fn spawn_task<P, F, Fut, T>(
         &mut self,
         interval_s: u64,
         name: &'static str,
         params: P,
+        task: F,
+    ) where
+        P: Clone + Send + Sync + 'static,
+        F: FnMut(P) -> Fut + Send + 'static,
+        Fut: Future<Output = anyhow::Result<T>> + Send + 'static,
+    {
+        self.spawn_triggered_task(interval_s, name, None, params, task);
+    }
+
+    /// Spawns a background task like `spawn_task`, also running it early whenever the trigger is notified
+    ///
+    /// An early run restarts the interval
+    pub fn spawn_triggered_task<P, F, Fut, T>(
+        &mut self,
+        interval_s: u64,
+        name: &'static str,
+        trigger: Option<Arc<Notify>>,
+        params: P,
         mut task: F,
     ) where
         P: Clone + Send + Sync + 'static,
         F: FnMut(P) -> Fut + Send + 'static,
-        Fut: Future<Output = anyhow::Result<()>> + Send + 'static,
+        Fut: Future<Output = anyhow::Result<T>> + Send + 'static,
     {
         let cancel = self.cancel_token.clone();
         self.tasks.spawn(async move {
             let mut interval = time::interval(Duration::from_secs(interval_s));
 
             loop {
+                let triggered = async {
+                    match &trigger {
+                        Some(trigger) => trigger.notified().await,
+                        None => std::future::pending().await,
+                    }
+                };
+
                 tokio::select! {
                     _ = cancel.cancelled() => break,
-                    _ = interval.tick() => {
-                        if let Err(e) = task(params.clone()).await {
-                            tracing::error!(error = %e, "Task {name} failed");
-                            return (name, Err(e));
-                        }
-                    }
+                    _ = interval.tick() => {}
+                    _ = triggered => interval.reset(),
+                }
+
+                if let Err(e) = task(params.clone()).await {
+                    tracing::error!(error = %e, "Task {name} failed");
+                    return (name, Err(e));
                 }
             }
 
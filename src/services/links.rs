@@ -1,14 +1,21 @@
-use anyhow::Context;
+use std::{collections::BTreeSet, fmt, str::FromStr, sync::Mutex};
+
+use anyhow::{Context, anyhow};
 use argon2::Argon2;
+use base64::Engine;
+use rand_core::{OsRng, RngCore};
 use serde::Serialize;
+use sha2::{Digest, Sha256};
 use sqids::Sqids;
-use sqlx::PgPool;
+use sqlx::{PgConnection, PgPool};
+use subtle::ConstantTimeEq;
 use thiserror::Error;
+use time::{Date, OffsetDateTime};
 
 use crate::{
-    app::CachedLink,
+    app::{CachedLink, alias_filter::AliasFilter},
     domain::{Alias, Url, UserId},
-    services::ServiceError,
+    services::{ServiceError, escape_like},
 };
 
 use super::hash_password;
@@ -19,107 +26,270 @@ pub enum LinkServiceError {
     AlreadyExists,
     #[error("alias not found")]
     NotFound,
+    #[error("alias belongs to another user")]
+    Forbidden,
+    #[error("invalid deletion token")]
+    InvalidToken,
+    #[error("permanent links can't be repointed")]
+    Permanent,
+}
+
+/// Optional properties of a new link
+#[derive(Default)]
+pub struct LinkOptions<'a> {
+    pub user_id: Option<UserId>,
+    pub password: Option<&'a str>,
+    /// List the link in the public recent feed
+    pub public: bool,
+    /// Expire at this moment instead of after being idle
+    pub expires_at: Option<OffsetDateTime>,
+    pub never_expires: bool,
+    /// Stop resolving after this many hits
+    pub max_hits: Option<i64>,
+    /// Lets whoever holds it delete the link, see [`generate_deletion_token`]
+    pub deletion_token: Option<&'a str>,
+    /// Redirect with 308 instead of 307
+    pub permanent: bool,
+}
+
+impl LinkOptions<'_> {
+    fn password_hash(&self, hasher: &Argon2<'_>) -> Result<Option<String>, ServiceError> {
+        self.password
+            .filter(|p| !p.is_empty())
+            .map(|p| hash_password(p, hasher))
+            .transpose()
+    }
+}
+
+/// Random token for deleting a link created without an account
+pub fn generate_deletion_token() -> String {
+    use base64::engine::general_purpose::URL_SAFE_NO_PAD as Base64;
+
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    Base64.encode(bytes)
+}
+
+/// Tokens are random 256-bit values, a fast digest is enough unlike for passwords
+fn hash_deletion_token(token: &str) -> String {
+    use base64::engine::general_purpose::STANDARD_NO_PAD as Base64;
+
+    Base64.encode(Sha256::digest(token.as_bytes()))
+}
+
+/// Number of link ids reserved from `links_main_id_seq` at once
+const LINK_ID_BLOCK: i64 = 32;
+
+/// Link ids reserved ahead of use, so creating a link takes a single insert
+///
+/// Ids are handed out in increasing order per instance. Ids still reserved on shutdown
+/// are never used, like those of failed inserts
+#[derive(Default)]
+pub struct LinkIds {
+    reserved: Mutex<BTreeSet<i64>>,
+}
+
+impl LinkIds {
+    pub async fn next(&self, pool: &PgPool) -> Result<i64, ServiceError> {
+        // The guard must be gone before the refill awaits
+        let id = self.reserved.lock().unwrap().pop_first();
+        if let Some(id) = id {
+            return Ok(id);
+        }
+
+        let ids: Vec<i64> = sqlx::query_scalar!(
+            r#"SELECT nextval('links_main_id_seq') AS "id!" FROM generate_series(1, $1::bigint)"#,
+            LINK_ID_BLOCK
+        )
+        .fetch_all(pool)
+        .await
+        .map_err(ServiceError::DatabaseError)?;
+
+        // Concurrent refills share their blocks, the lowest id goes first
+        let mut reserved = self.reserved.lock().unwrap();
+        reserved.extend(ids);
+        reserved
+            .pop_first()
+            .context("Link id block was empty")
+            .map_err(ServiceError::Other)
+    }
 }
 
 /// Create a new link for the provided URL
 #[tracing::instrument(
     name = "services::create_link",
-    skip(generator, pool, password, hasher)
+    skip(generator, ids, pool, options, hasher),
+    fields(user_id = options.user_id)
 )]
 pub async fn create_link(
     url: &Url,
     generator: &Sqids,
+    ids: &LinkIds,
     pool: &PgPool,
-    user_id: Option<UserId>,
-    password: Option<&str>,
+    options: &LinkOptions<'_>,
     hasher: &Argon2<'_>,
 ) -> Result<String, ServiceError> {
-    let password_hash = password
-        .filter(|p| !p.is_empty())
-        .map(|p| hash_password(p, hasher))
-        .transpose()?;
-    let password_hash_ref = password_hash.as_deref();
+    let password_hash = options.password_hash(hasher)?;
+    let deletion_token_hash = options.deletion_token.map(hash_deletion_token);
 
-    let mut tx = pool.begin().await.map_err(ServiceError::DatabaseError)?;
-    // Insert the url into database to get a unique id
-    let rec = sqlx::query!(
+    // The alias encodes the id, a reserved id lets the insert write both
+    let id = ids.next(pool).await?;
+
+    let alias = generator
+        .encode(&[id as u64])
+        .context("Sqids alphabet was exhausted")
+        .map_err(ServiceError::Other)?;
+
+    sqlx::query!(
         r#"
-        INSERT INTO links_main (url, user_id, password_hash)
-        VALUES ($1, $2, $3)
-        RETURNING id
+        INSERT INTO links_main (id, alias, url, user_id, password_hash, is_public, expires_at, never_expires, max_hits, deletion_token_hash, permanent)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
         "#,
+        id,
+        alias,
         url.as_str(),
-        user_id,
-        password_hash_ref,
+        options.user_id,
+        password_hash,
+        options.public,
+        options.expires_at,
+        options.never_expires,
+        options.max_hits,
+        deletion_token_hash,
+        options.permanent,
     )
-    .fetch_one(&mut *tx)
+    .execute(pool)
     .await
     .map_err(ServiceError::DatabaseError)?;
 
-    let id = rec.id as u64;
+    Ok(alias)
+}
 
-    let alias = generator
-        .encode(&[id])
+/// Link created by [`create_links_batch`]
+pub struct BatchLink {
+    pub alias: String,
+    /// Only for links created without an account, required to delete them
+    pub deletion_token: Option<String>,
+}
+
+/// Create links for all provided URLs in a single statement
+///
+/// Links created without an account get a deletion token each.
+/// Returns the created links in the order of `urls`
+#[tracing::instrument(
+    name = "services::create_links_batch",
+    skip(urls, generator, pool),
+    fields(count = urls.len())
+)]
+pub async fn create_links_batch(
+    urls: &[Url],
+    user_id: Option<UserId>,
+    generator: &Sqids,
+    pool: &PgPool,
+) -> Result<Vec<BatchLink>, ServiceError> {
+    if urls.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let url_col: Vec<&str> = urls.iter().map(Url::as_str).collect();
+
+    let mut ids: Vec<i64> = sqlx::query_scalar!(
+        r#"SELECT nextval('links_main_id_seq') AS "id!" FROM generate_series(1, $1::bigint)"#,
+        urls.len() as i64
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(ServiceError::DatabaseError)?;
+
+    // Keep ids in the order of `urls`, as a single insert would have assigned them
+    ids.sort_unstable();
+
+    let aliases = ids
+        .iter()
+        .map(|&id| generator.encode(&[id as u64]))
+        .collect::<Result<Vec<_>, _>>()
         .context("Sqids alphabet was exhausted")
         .map_err(ServiceError::Other)?;
 
-    // Update the record with generated alias
-    let updated = sqlx::query!(
+    // Links of users are deleted through their account
+    let deletion_tokens: Vec<String> = match user_id {
+        Some(_) => Vec::new(),
+        None => urls.iter().map(|_| generate_deletion_token()).collect(),
+    };
+    let deletion_token_hashes: Vec<String> = deletion_tokens
+        .iter()
+        .map(|token| hash_deletion_token(token))
+        .collect();
+
+    // UNNEST pads shorter arrays with NULL, so user links get no token hash
+    sqlx::query!(
         r#"
-        UPDATE links_main
-        SET alias = $1
-        WHERE id = $2
-        RETURNING alias
+        INSERT INTO links_main (id, alias, url, user_id, deletion_token_hash)
+        SELECT t.id, t.alias, t.url, $4, t.deletion_token_hash
+        FROM UNNEST($1::bigint[], $2::text[], $3::text[], $5::text[]) AS t(id, alias, url, deletion_token_hash)
         "#,
-        alias,
-        rec.id
+        &ids,
+        &aliases,
+        &url_col as &[&str],
+        user_id,
+        &deletion_token_hashes,
     )
-    .fetch_one(&mut *tx)
+    .execute(pool)
     .await
     .map_err(ServiceError::DatabaseError)?;
 
-    tx.commit().await.map_err(ServiceError::DatabaseError)?;
-
-    let alias = updated
-        .alias
-        .context("Updated record contained no alias")
-        .map_err(ServiceError::Other)?;
+    let mut deletion_tokens = deletion_tokens.into_iter();
+    let links = aliases
+        .into_iter()
+        .map(|alias| BatchLink {
+            alias,
+            deletion_token: deletion_tokens.next(),
+        })
+        .collect();
 
-    Ok(alias)
+    Ok(links)
 }
 
 /// Create a link with user-defined alias for the provided URL
 ///
-/// Returns Ok(false) if the alias is already taken
+/// Returns the alias, fails with [`LinkServiceError::AlreadyExists`] if it is taken or still retired
 #[tracing::instrument(
     name = "services::create_link_with_alias",
-    skip(pool, password, hasher)
+    skip(alias, pool, options, hasher),
+    fields(alias = alias.as_str(), user_id = options.user_id)
 )]
 pub async fn create_link_with_alias(
     url: &Url,
     alias: &Alias,
     pool: &PgPool,
-    user_id: Option<UserId>,
-    password: Option<&str>,
+    options: &LinkOptions<'_>,
     hasher: &Argon2<'_>,
 ) -> Result<String, ServiceError> {
-    let password_hash = password
-        .filter(|p| !p.is_empty())
-        .map(|p| hash_password(p, hasher))
-        .transpose()?;
-    let password_hash_ref = password_hash.as_deref();
+    let password_hash = options.password_hash(hasher)?;
+    let deletion_token_hash = options.deletion_token.map(hash_deletion_token);
 
     let rec_opt = sqlx::query!(
         r#"
-        INSERT INTO links_main (alias, url, user_id, password_hash)
-        VALUES ($1, $2, $3, $4)
+        INSERT INTO links_main (alias, url, user_id, password_hash, is_public, expires_at, never_expires, max_hits, deletion_token_hash, permanent)
+        SELECT $1::text, $2::text, $3::bigint, $4::text, $5::boolean, $6::timestamptz, $7::boolean, $8::bigint, $9::text, $10::boolean
+        WHERE NOT EXISTS (
+            SELECT 1
+            FROM retired_aliases
+            WHERE alias = $1
+              AND retired_until > now()
+        )
         ON CONFLICT (alias) DO NOTHING
         RETURNING alias
         "#,
         alias.as_str(),
         url.as_str(),
-        user_id,
-        password_hash_ref,
+        options.user_id,
+        password_hash,
+        options.public,
+        options.expires_at,
+        options.never_expires,
+        options.max_hits,
+        deletion_token_hash,
+        options.permanent,
     )
     .fetch_optional(pool)
     .await
@@ -131,16 +301,68 @@ pub async fn create_link_with_alias(
     }
 }
 
+/// Alias of a live, unrestricted link of the user to exactly this URL, the oldest one if there are several
+#[tracing::instrument(name = "services::find_user_link_by_url", skip(url, pool))]
+pub async fn find_user_link_by_url(
+    user_id: &UserId,
+    url: &Url,
+    idle_days: u16,
+    pool: &PgPool,
+) -> Result<Option<String>, ServiceError> {
+    sqlx::query_scalar!(
+        r#"
+        SELECT alias AS "alias!"
+        FROM links_main
+        WHERE user_id = $1
+          AND url = $2
+          AND alias IS NOT NULL
+          AND deleted_at IS NULL
+          AND password_hash IS NULL
+          AND max_hits IS NULL
+          AND expires_at IS NULL
+          AND NOT permanent
+          AND (never_expires OR last_seen >= CURRENT_DATE - $3::int)
+        ORDER BY id
+        LIMIT 1
+        "#,
+        user_id,
+        url.as_str(),
+        i32::from(idle_days)
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(ServiceError::DatabaseError)
+}
+
 /// Query url from database
 ///
-/// Returns Ok(None) if the alias does not exist
-#[tracing::instrument(name = "services::query_url_by_alias", skip(pool))]
+/// Returns Ok(None) if the alias does not exist, `limits_epoch` is kept with the loaded `hit_count`
+#[tracing::instrument(
+    name = "services::query_url_by_alias",
+    skip(alias, pool),
+    fields(alias = alias.as_str())
+)]
 pub async fn query_url_by_alias(
     alias: &Alias,
+    limits_epoch: u64,
     pool: &PgPool,
 ) -> Result<Option<CachedLink>, ServiceError> {
     let rec_opt = sqlx::query!(
-        r#"SELECT id, url, last_seen, password_hash FROM links_main WHERE alias = $1"#,
+        r#"
+        SELECT
+            id,
+            url,
+            last_seen,
+            password_hash,
+            deleted_at IS NOT NULL AS "deleted!",
+            expires_at,
+            never_expires,
+            max_hits,
+            hit_count,
+            permanent
+        FROM links_main
+        WHERE alias = $1
+        "#,
         alias.as_str()
     )
     .fetch_optional(pool)
@@ -154,49 +376,333 @@ pub async fn query_url_by_alias(
                 url: rec.url,
                 last_seen: rec.last_seen,
                 password_hash: rec.password_hash,
+                deleted: rec.deleted,
+                expires_at: rec.expires_at,
+                never_expires: rec.never_expires,
+                max_hits: rec.max_hits,
+                hit_count: rec.hit_count,
+                limits_epoch,
+                permanent: rec.permanent,
             })
         })
         .transpose()
 }
 
+/// Whether the alias belonged to a removed link and is still within its grace period
+#[tracing::instrument(
+    name = "services::is_alias_retired",
+    skip(alias, pool),
+    fields(alias = alias.as_str())
+)]
+pub async fn is_alias_retired(alias: &Alias, pool: &PgPool) -> Result<bool, ServiceError> {
+    let rec = sqlx::query!(
+        r#"
+        SELECT EXISTS (
+            SELECT 1
+            FROM retired_aliases
+            WHERE alias = $1
+              AND retired_until > now()
+        ) AS "retired!"
+        "#,
+        alias.as_str()
+    )
+    .fetch_one(pool)
+    .await
+    .map_err(ServiceError::DatabaseError)?;
+
+    Ok(rec.retired)
+}
+
+/// Insert every existing alias into the filter and mark it as populated
+///
+/// Returns the number of loaded aliases
+#[tracing::instrument(name = "services::populate_alias_filter", skip_all)]
+pub async fn populate_alias_filter(
+    filter: &AliasFilter,
+    pool: &PgPool,
+) -> Result<u64, ServiceError> {
+    const BATCH_SIZE: i64 = 10_000;
+
+    let mut last_id = 0i64;
+    let mut count = 0u64;
+    loop {
+        let recs = sqlx::query!(
+            r#"
+            SELECT id, alias AS "alias!"
+            FROM links_main
+            WHERE id > $1
+              AND alias IS NOT NULL
+            ORDER BY id
+            LIMIT $2
+            "#,
+            last_id,
+            BATCH_SIZE
+        )
+        .fetch_all(pool)
+        .await
+        .map_err(ServiceError::DatabaseError)?;
+
+        for rec in &recs {
+            filter.insert(&rec.alias);
+        }
+        count += recs.len() as u64;
+
+        match recs.last() {
+            Some(rec) if recs.len() as i64 == BATCH_SIZE => last_id = rec.id,
+            _ => break,
+        }
+    }
+
+    filter.mark_populated();
+
+    Ok(count)
+}
+
+time::serde::format_description!(iso_date, Date, "[year]-[month]-[day]");
+
+/// Full link record for admin tools, including soft-deleted links
+#[derive(Debug, Clone, Serialize)]
+pub struct AdminLink {
+    pub id: i64,
+    pub alias: String,
+    pub url: String,
+    pub user_id: Option<UserId>,
+    #[serde(with = "time::serde::rfc3339")]
+    pub created_at: OffsetDateTime,
+    #[serde(with = "iso_date")]
+    pub last_seen: Date,
+    pub public: bool,
+    pub protected: bool,
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub deleted_at: Option<OffsetDateTime>,
+}
+
+/// Query a link by alias whether or not it was deleted
+///
+/// Returns Ok(None) if the alias never existed
+#[tracing::instrument(
+    name = "services::admin_get_link",
+    skip(alias, pool),
+    fields(alias = alias.as_str())
+)]
+pub async fn admin_get_link(
+    alias: &Alias,
+    pool: &PgPool,
+) -> Result<Option<AdminLink>, ServiceError> {
+    let rec_opt = sqlx::query!(
+        r#"
+        SELECT
+            id,
+            alias AS "alias!",
+            url,
+            user_id,
+            created_at,
+            last_seen,
+            is_public AS public,
+            password_hash IS NOT NULL AS "protected!",
+            deleted_at
+        FROM links_main
+        WHERE alias = $1
+        "#,
+        alias.as_str()
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(ServiceError::DatabaseError)?;
+
+    Ok(rec_opt.map(|rec| AdminLink {
+        id: rec.id,
+        alias: rec.alias,
+        url: rec.url,
+        user_id: rec.user_id,
+        created_at: rec.created_at,
+        last_seen: rec.last_seen,
+        public: rec.public,
+        protected: rec.protected,
+        deleted_at: rec.deleted_at,
+    }))
+}
+
 #[derive(Debug, Clone, Serialize)]
 pub struct LinkItem {
     pub alias: String,
     pub url: String,
+    #[serde(with = "time::serde::rfc3339")]
+    pub created_at: OffsetDateTime,
+    #[serde(with = "iso_date")]
+    pub last_seen: Date,
+    /// Hits persisted so far, recent hits are added on the next metrics flush
+    pub hit_count: i64,
+}
+
+/// Position after the last link of a page, opaque to clients
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LinkCursor {
+    created_at: OffsetDateTime,
+    id: i64,
+}
+
+impl fmt::Display for LinkCursor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}_{}", self.created_at.unix_timestamp_nanos(), self.id)
+    }
+}
+
+impl FromStr for LinkCursor {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (nanos, id) = s.split_once('_').ok_or(())?;
+        let nanos: i128 = nanos.parse().map_err(|_| ())?;
+        Ok(Self {
+            created_at: OffsetDateTime::from_unix_timestamp_nanos(nanos).map_err(|_| ())?,
+            id: id.parse().map_err(|_| ())?,
+        })
+    }
 }
 
-/// List user's links
+#[derive(Debug, Clone, Serialize)]
+pub struct LinkPage {
+    pub links: Vec<LinkItem>,
+    /// Pass back to get the following page, None on the last page
+    pub next_cursor: Option<String>,
+}
+
+/// List a page of user's links, newest first
+///
+/// With `search`, only links whose alias starts with it or whose URL contains it, ignoring case
 #[tracing::instrument(name = "services::query_links_by_user_id", skip(pool))]
 pub async fn query_links_by_user_id(
     user_id: &UserId,
+    search: Option<&str>,
+    limit: i64,
+    cursor: Option<LinkCursor>,
     pool: &PgPool,
-) -> Result<Vec<LinkItem>, ServiceError> {
-    let rec_vec = sqlx::query!(
+) -> Result<LinkPage, ServiceError> {
+    let alias_pattern = search.map(|search| format!("{}%", escape_like(search)));
+    let url_pattern = search.map(|search| format!("%{}%", escape_like(search)));
+
+    // One extra row tells whether another page follows
+    let mut rec_vec = sqlx::query!(
         r#"
-        SELECT alias, url
+        SELECT id, alias, url, created_at, last_seen, hit_count
         FROM links_main
         WHERE user_id = $1
-        ORDER BY created_at DESC
+          AND deleted_at IS NULL
+          AND ($2::timestamptz IS NULL OR (created_at, id) < ($2, $3))
+          AND ($5::text IS NULL OR alias ILIKE $5 OR url ILIKE $6)
+        ORDER BY created_at DESC, id DESC
+        LIMIT $4
         "#,
-        user_id
+        user_id,
+        cursor.map(|c| c.created_at),
+        cursor.map(|c| c.id),
+        limit + 1,
+        alias_pattern,
+        url_pattern
     )
     .fetch_all(pool)
     .await
     .map_err(ServiceError::DatabaseError)?;
 
+    let mut next_cursor = None;
+    if rec_vec.len() as i64 > limit {
+        rec_vec.truncate(limit as usize);
+        next_cursor = rec_vec.last().map(|rec| {
+            LinkCursor {
+                created_at: rec.created_at,
+                id: rec.id,
+            }
+            .to_string()
+        });
+    }
+
     let links = rec_vec
         .into_iter()
         .map(|rec| LinkItem {
             alias: rec.alias.unwrap_or_default(),
             url: rec.url,
+            created_at: rec.created_at,
+            last_seen: rec.last_seen,
+            hit_count: rec.hit_count,
+        })
+        .collect();
+
+    Ok(LinkPage { links, next_cursor })
+}
+
+/// Link record for backups of a user's links
+#[derive(Debug, Clone, Serialize)]
+pub struct ExportLink {
+    /// Position for paging, not part of the export
+    #[serde(skip)]
+    pub id: i64,
+    pub alias: String,
+    pub url: String,
+    #[serde(with = "time::serde::rfc3339")]
+    pub created_at: OffsetDateTime,
+    #[serde(with = "iso_date")]
+    pub last_seen: Date,
+    pub public: bool,
+    pub protected: bool,
+}
+
+/// List a page of user's links ordered by id, starting after `after_id`
+#[tracing::instrument(name = "services::query_export_page", skip(pool))]
+pub async fn query_export_page(
+    user_id: UserId,
+    after_id: i64,
+    limit: i64,
+    pool: &PgPool,
+) -> Result<Vec<ExportLink>, ServiceError> {
+    let rec_vec = sqlx::query!(
+        r#"
+        SELECT
+            id,
+            alias AS "alias!",
+            url,
+            created_at,
+            last_seen,
+            is_public AS public,
+            password_hash IS NOT NULL AS "protected!"
+        FROM links_main
+        WHERE user_id = $1
+          AND id > $2
+          AND alias IS NOT NULL
+          AND deleted_at IS NULL
+        ORDER BY id
+        LIMIT $3
+        "#,
+        user_id,
+        after_id,
+        limit
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(ServiceError::DatabaseError)?;
+
+    let links = rec_vec
+        .into_iter()
+        .map(|rec| ExportLink {
+            id: rec.id,
+            alias: rec.alias,
+            url: rec.url,
+            created_at: rec.created_at,
+            last_seen: rec.last_seen,
+            public: rec.public,
+            protected: rec.protected,
         })
         .collect();
 
     Ok(links)
 }
 
-/// Remove user's link
-#[tracing::instrument(name = "services::remove_user_link", skip(pool))]
+/// Soft-delete user's link, it keeps answering 410 until cleaned up
+#[tracing::instrument(
+    name = "services::remove_user_link",
+    skip(alias, pool),
+    fields(alias = alias.as_str())
+)]
 pub async fn remove_user_link(
     user_id: &UserId,
     alias: &Alias,
@@ -204,9 +710,194 @@ pub async fn remove_user_link(
 ) -> Result<(), ServiceError> {
     sqlx::query!(
         r#"
-        DELETE FROM links_main
+        UPDATE links_main
+        SET deleted_at = now()
+        WHERE user_id = $1
+          AND alias = $2
+          AND deleted_at IS NULL
+        "#,
+        user_id,
+        alias.as_str()
+    )
+    .execute(pool)
+    .await
+    .map_err(ServiceError::DatabaseError)?;
+
+    Ok(())
+}
+
+/// Soft delete a link created without an account, proving ownership with its deletion token
+///
+/// Missing links and wrong tokens are both reported as `InvalidToken`
+#[tracing::instrument(
+    name = "services::remove_link_with_token",
+    skip(alias, token, pool),
+    fields(alias = alias.as_str())
+)]
+pub async fn remove_link_with_token(
+    alias: &Alias,
+    token: &str,
+    pool: &PgPool,
+) -> Result<(), ServiceError> {
+    let stored_hash = sqlx::query_scalar!(
+        r#"
+        SELECT deletion_token_hash
+        FROM links_main
+        WHERE alias = $1
+          AND user_id IS NULL
+          AND deleted_at IS NULL
+        "#,
+        alias.as_str()
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(ServiceError::DatabaseError)?
+    .flatten();
+
+    let token_hash = hash_deletion_token(token);
+    let verified = stored_hash
+        .is_some_and(|stored| bool::from(stored.as_bytes().ct_eq(token_hash.as_bytes())));
+    if !verified {
+        return Err(LinkServiceError::InvalidToken.into());
+    }
+
+    sqlx::query!(
+        r#"
+        UPDATE links_main
+        SET deleted_at = now()
+        WHERE alias = $1
+          AND deleted_at IS NULL
+        "#,
+        alias.as_str()
+    )
+    .execute(pool)
+    .await
+    .map_err(ServiceError::DatabaseError)?;
+
+    Ok(())
+}
+
+/// Point user's link to a new URL, returns the updated link
+///
+/// Fails with [`LinkServiceError::Permanent`] for permanent links, browsers keep following
+/// the cached redirect to the old URL
+#[tracing::instrument(
+    name = "services::update_user_link",
+    skip(alias, url, pool),
+    fields(alias = alias.as_str())
+)]
+pub async fn update_user_link(
+    user_id: &UserId,
+    alias: &Alias,
+    url: &Url,
+    pool: &PgPool,
+) -> Result<LinkItem, ServiceError> {
+    let permanent = sqlx::query_scalar!(
+        r#"
+        SELECT permanent
+        FROM links_main
+        WHERE user_id = $1
+          AND alias = $2
+          AND deleted_at IS NULL
+        "#,
+        user_id,
+        alias.as_str()
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(ServiceError::DatabaseError)?
+    .ok_or(LinkServiceError::NotFound)?;
+
+    if permanent {
+        return Err(LinkServiceError::Permanent.into());
+    }
+
+    let rec = sqlx::query!(
+        r#"
+        UPDATE links_main
+        SET url = $1
+        WHERE user_id = $2
+          AND alias = $3
+          AND deleted_at IS NULL
+        RETURNING alias AS "alias!", url, created_at, last_seen, hit_count
+        "#,
+        url.as_str(),
+        user_id,
+        alias.as_str()
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(ServiceError::DatabaseError)?
+    .ok_or(LinkServiceError::NotFound)?;
+
+    Ok(LinkItem {
+        alias: rec.alias,
+        url: rec.url,
+        created_at: rec.created_at,
+        last_seen: rec.last_seen,
+        hit_count: rec.hit_count,
+    })
+}
+
+/// Protect user's link with a new password, or remove the protection with `None`
+#[tracing::instrument(
+    name = "services::update_link_password",
+    skip(alias, new_password, hasher, pool),
+    fields(alias = alias.as_str())
+)]
+pub async fn update_link_password(
+    user_id: &UserId,
+    alias: &Alias,
+    new_password: Option<&str>,
+    hasher: &Argon2<'_>,
+    pool: &PgPool,
+) -> Result<(), ServiceError> {
+    let password_hash = new_password
+        .filter(|p| !p.is_empty())
+        .map(|p| hash_password(p, hasher))
+        .transpose()?;
+
+    let result = sqlx::query!(
+        r#"
+        UPDATE links_main
+        SET password_hash = $1
+        WHERE user_id = $2
+          AND alias = $3
+          AND deleted_at IS NULL
+        "#,
+        password_hash,
+        user_id,
+        alias.as_str()
+    )
+    .execute(pool)
+    .await
+    .map_err(ServiceError::DatabaseError)?;
+
+    if result.rows_affected() == 0 {
+        return Err(LinkServiceError::NotFound.into());
+    }
+
+    Ok(())
+}
+
+/// Bump user's link last seen day to today, renewing its expiry
+#[tracing::instrument(
+    name = "services::touch_user_link",
+    skip(alias, pool),
+    fields(alias = alias.as_str())
+)]
+pub async fn touch_user_link(
+    user_id: &UserId,
+    alias: &Alias,
+    pool: &PgPool,
+) -> Result<(), ServiceError> {
+    let result = sqlx::query!(
+        r#"
+        UPDATE links_main
+        SET last_seen = CURRENT_DATE
         WHERE user_id = $1
           AND alias = $2
+          AND deleted_at IS NULL
         "#,
         user_id,
         alias.as_str()
@@ -215,15 +906,276 @@ pub async fn remove_user_link(
     .await
     .map_err(ServiceError::DatabaseError)?;
 
+    if result.rows_affected() == 0 {
+        return Err(LinkServiceError::NotFound.into());
+    }
+
     Ok(())
 }
 
-#[tracing::instrument(name = "app::recently_added_links", skip(pool))]
-pub async fn recently_added_links(limit: i64, pool: &PgPool) -> Result<Vec<String>, ServiceError> {
-    let recs = sqlx::query!(
+/// Replace user's link alias with a freshly generated one, keeping the destination and metrics
+///
+/// Returns the new alias
+#[tracing::instrument(
+    name = "services::rotate_user_link",
+    skip(alias, generator, pool),
+    fields(alias = alias.as_str())
+)]
+pub async fn rotate_user_link(
+    user_id: &UserId,
+    alias: &Alias,
+    grace_days: u16,
+    generator: &Sqids,
+    pool: &PgPool,
+) -> Result<String, ServiceError> {
+    const MAX_ATTEMPTS: usize = 3;
+
+    let rec = sqlx::query!(
         r#"
-        SELECT url
+        SELECT id
         FROM links_main
+        WHERE user_id = $1
+          AND alias = $2
+          AND deleted_at IS NULL
+        "#,
+        user_id,
+        alias.as_str()
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(ServiceError::DatabaseError)?
+    .ok_or(LinkServiceError::NotFound)?;
+
+    for _ in 0..MAX_ATTEMPTS {
+        // A random nonce next to the id keeps the new alias unique among generated ones,
+        // but it can still collide with a user-defined alias
+        let nonce = OsRng.next_u32() as u64;
+        let new_alias = generator
+            .encode(&[rec.id as u64, nonce])
+            .context("Sqids alphabet was exhausted")
+            .map_err(ServiceError::Other)?;
+
+        let mut tx = pool.begin().await.map_err(ServiceError::DatabaseError)?;
+
+        let result = sqlx::query!(
+            r#"
+            UPDATE links_main
+            SET alias = $1
+            WHERE id = $2
+              AND user_id = $3
+            "#,
+            new_alias,
+            rec.id,
+            user_id
+        )
+        .execute(&mut *tx)
+        .await;
+
+        match result {
+            Ok(result) if result.rows_affected() == 0 => {
+                return Err(LinkServiceError::NotFound.into());
+            }
+            Ok(_) => {}
+            Err(sqlx::Error::Database(e)) if e.is_unique_violation() => continue,
+            Err(e) => return Err(ServiceError::DatabaseError(e)),
+        }
+
+        // Old alias answers 410 for the grace period instead of being claimed right away
+        retire_alias(alias.as_str(), grace_days, &mut tx).await?;
+
+        tx.commit().await.map_err(ServiceError::DatabaseError)?;
+
+        return Ok(new_alias);
+    }
+
+    Err(ServiceError::Other(anyhow!(
+        "Failed to generate a unique alias in {MAX_ATTEMPTS} attempts"
+    )))
+}
+
+async fn retire_alias(
+    alias: &str,
+    grace_days: u16,
+    conn: &mut PgConnection,
+) -> Result<(), ServiceError> {
+    sqlx::query!(
+        r#"
+        INSERT INTO retired_aliases (alias, retired_until)
+        VALUES ($1, now() + make_interval(days => $2))
+        ON CONFLICT (alias) DO UPDATE SET retired_until = EXCLUDED.retired_until
+        "#,
+        alias,
+        i32::from(grace_days)
+    )
+    .execute(conn)
+    .await
+    .map_err(ServiceError::DatabaseError)?;
+
+    Ok(())
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DailyHits {
+    #[serde(with = "iso_date")]
+    pub day: Date,
+    pub hits: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DimensionHits {
+    pub value: String,
+    pub hits: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LinkStats {
+    pub total_hits: i64,
+    /// Last 7 days including today, oldest first
+    pub daily_hits: Vec<DailyHits>,
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub last_access: Option<OffsetDateTime>,
+    /// Top referrer hosts over the same days, empty unless collection is enabled
+    pub referrers: Vec<DimensionHits>,
+    /// Top visitor languages over the same days, empty unless collection is enabled
+    pub languages: Vec<DimensionHits>,
+}
+
+/// Query hit statistics of user's link
+#[tracing::instrument(
+    name = "services::query_link_stats",
+    skip(alias, pool),
+    fields(alias = alias.as_str())
+)]
+pub async fn query_link_stats(
+    user_id: &UserId,
+    alias: &Alias,
+    pool: &PgPool,
+) -> Result<LinkStats, ServiceError> {
+    const DAYS: i32 = 7;
+    const TOP_VALUES: i64 = 10;
+
+    let link = sqlx::query!(
+        r#"
+        SELECT id, user_id
+        FROM links_main
+        WHERE alias = $1
+          AND deleted_at IS NULL
+        "#,
+        alias.as_str()
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(ServiceError::DatabaseError)?
+    .ok_or(LinkServiceError::NotFound)?;
+
+    if link.user_id != Some(*user_id) {
+        return Err(LinkServiceError::Forbidden.into());
+    }
+
+    let totals = sqlx::query!(
+        r#"
+        SELECT
+            COALESCE(SUM(hits), 0)::bigint AS "total_hits!",
+            MAX(last_access) AS last_access
+        FROM daily_metrics
+        WHERE link_id = $1
+        "#,
+        link.id
+    )
+    .fetch_one(pool)
+    .await
+    .map_err(ServiceError::DatabaseError)?;
+
+    let daily_hits = sqlx::query_as!(
+        DailyHits,
+        r#"
+        SELECT
+            g.day::date AS "day!",
+            COALESCE(m.hits, 0) AS "hits!"
+        FROM generate_series(CURRENT_DATE - ($2::int - 1), CURRENT_DATE, interval '1 day') AS g(day)
+        LEFT JOIN daily_metrics m
+          ON m.day = g.day::date
+         AND m.link_id = $1
+        ORDER BY g.day
+        "#,
+        link.id,
+        DAYS
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(ServiceError::DatabaseError)?;
+
+    let dimension_recs = sqlx::query!(
+        r#"
+        SELECT dimension AS "dimension!", value AS "value!", hits AS "hits!"
+        FROM (
+            SELECT
+                dimension,
+                value,
+                SUM(hits)::bigint AS hits,
+                ROW_NUMBER() OVER (PARTITION BY dimension ORDER BY SUM(hits) DESC, value) AS rank
+            FROM visit_dimensions
+            WHERE link_id = $1
+              AND day > CURRENT_DATE - $2::int
+            GROUP BY dimension, value
+        ) t
+        WHERE rank <= $3
+        ORDER BY dimension, rank
+        "#,
+        link.id,
+        DAYS,
+        TOP_VALUES
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(ServiceError::DatabaseError)?;
+
+    let mut referrers = Vec::new();
+    let mut languages = Vec::new();
+    for rec in dimension_recs {
+        let hits = DimensionHits {
+            value: rec.value,
+            hits: rec.hits,
+        };
+        match rec.dimension.as_str() {
+            "referrer" => referrers.push(hits),
+            "language" => languages.push(hits),
+            _ => {}
+        }
+    }
+
+    Ok(LinkStats {
+        total_hits: totals.total_hits,
+        daily_hits,
+        last_access: totals.last_access,
+        referrers,
+        languages,
+    })
+}
+
+/// Entry of the recent feed, without the activity of the link
+#[derive(Debug, Clone, Serialize)]
+pub struct RecentLink {
+    pub alias: String,
+    pub url: String,
+    #[serde(with = "time::serde::rfc3339")]
+    pub created_at: OffsetDateTime,
+}
+
+/// Latest links shortened as public, for everyone to see
+#[tracing::instrument(name = "services::recently_added_links", skip(pool))]
+pub async fn recently_added_links(
+    limit: i64,
+    pool: &PgPool,
+) -> Result<Vec<RecentLink>, ServiceError> {
+    let links = sqlx::query_as!(
+        RecentLink,
+        r#"
+        SELECT alias AS "alias!", url, created_at
+        FROM links_main
+        WHERE is_public
+          AND alias IS NOT NULL
+          AND deleted_at IS NULL
         ORDER BY id DESC
         LIMIT $1
         "#,
@@ -233,5 +1185,33 @@ pub async fn recently_added_links(limit: i64, pool: &PgPool) -> Result<Vec<Strin
     .await
     .context("DB select recent links query failed")?;
 
-    Ok(recs.into_iter().map(|rec| rec.url).collect())
+    Ok(links)
+}
+
+/// Latest links of the user, whether public or not
+#[tracing::instrument(name = "services::recently_added_user_links", skip(pool))]
+pub async fn recently_added_user_links(
+    user_id: &UserId,
+    limit: i64,
+    pool: &PgPool,
+) -> Result<Vec<RecentLink>, ServiceError> {
+    let links = sqlx::query_as!(
+        RecentLink,
+        r#"
+        SELECT alias AS "alias!", url, created_at
+        FROM links_main
+        WHERE user_id = $1
+          AND alias IS NOT NULL
+          AND deleted_at IS NULL
+        ORDER BY created_at DESC, id DESC
+        LIMIT $2
+        "#,
+        user_id,
+        limit
+    )
+    .fetch_all(pool)
+    .await
+    .context("DB select recent user links query failed")?;
+
+    Ok(links)
 }
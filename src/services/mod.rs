@@ -7,7 +7,9 @@ mod links;
 mod users;
 
 pub use links::*;
-pub use users::{authenticate_user, create_user};
+pub use users::{
+    UserFilter, UserSummary, admin_list_users, authenticate_user, create_user, import_user,
+};
 
 /// Hash a password with argon2, returning the hash string.
 pub fn hash_password(password: &str, hasher: &Argon2<'_>) -> Result<String, ServiceError> {
@@ -18,6 +20,18 @@ pub fn hash_password(password: &str, hasher: &Argon2<'_>) -> Result<String, Serv
     Ok(hash.to_string())
 }
 
+/// Escape `LIKE` wildcards so user input is matched literally
+pub fn escape_like(input: &str) -> String {
+    let mut escaped = String::with_capacity(input.len());
+    for c in input.chars() {
+        if matches!(c, '%' | '_' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
 #[derive(Debug, Error)]
 pub enum ServiceError {
     #[error("authentication failed")]
@@ -29,3 +43,15 @@ pub enum ServiceError {
     #[error(transparent)]
     Other(#[from] anyhow::Error),
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn like_wildcards_escaped() {
+        assert_eq!(escape_like("abc"), "abc");
+        assert_eq!(escape_like("50%_off"), "50\\%\\_off");
+        assert_eq!(escape_like("a\\b"), "a\\\\b");
+    }
+}
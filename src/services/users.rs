@@ -1,37 +1,59 @@
-use argon2::{Argon2, PasswordHash, PasswordVerifier};
+use argon2::{ARGON2ID_IDENT, Argon2, Params, PasswordHash, PasswordVerifier};
+use serde::Serialize;
 use sqlx::PgPool;
+use time::OffsetDateTime;
 
 use crate::{
-    domain::{User, UserName, UserPassword},
-    services::ServiceError,
+    domain::{ImportedPasswordHash, User, UserId, UserName, UserPassword},
+    services::{ServiceError, escape_like},
 };
 
 use super::hash_password;
 
+/// Argon2 hash of a throwaway password, using the default parameters
+const DUMMY_PASSWORD_HASH: &str = "$argon2id$v=19$m=19456,t=2,p=1$D7wyiC7LKEMVPYxYQwGJEQ$DI6wyQ8LBvrrlcKjtqBddgLt9m+lPevOm4TqgniVmDw";
+
+/// Advisory lock key held while registering with first user promotion
+const FIRST_USER_LOCK: i64 = 0x7573_6572_5f61_646d;
+
 #[tracing::instrument(name = "services::create_user_account", skip_all)]
 pub async fn create_user(
     username: UserName,
     password: UserPassword,
+    promote_first: bool,
     hasher: &Argon2<'_>,
     pool: &PgPool,
 ) -> Result<Option<User>, ServiceError> {
     let hash = hash_password(password.as_str(), hasher)?;
 
+    let mut tx = pool.begin().await.map_err(ServiceError::DatabaseError)?;
+
+    // Serialize registrations while promoting, so concurrent first users can't both become admins
+    if promote_first {
+        sqlx::query!("SELECT pg_advisory_xact_lock($1)", FIRST_USER_LOCK)
+            .execute(&mut *tx)
+            .await
+            .map_err(ServiceError::DatabaseError)?;
+    }
+
     let rec_opt = sqlx::query!(
         r#"
-        INSERT INTO users_main (username, password_hash)
-        VALUES ($1, $2)
+        INSERT INTO users_main (username, password_hash, is_admin)
+        VALUES ($1, $2, $3 AND NOT EXISTS (SELECT 1 FROM users_main))
         ON CONFLICT (username) DO NOTHING
-        RETURNING id
+        RETURNING id, is_admin
         "#,
         username.as_str(),
-        hash
+        hash,
+        promote_first
     )
-    .fetch_optional(pool)
+    .fetch_optional(&mut *tx)
     .await
     .map_err(ServiceError::DatabaseError)?;
 
-    Ok(rec_opt.map(|rec| User::new(rec.id, username)))
+    tx.commit().await.map_err(ServiceError::DatabaseError)?;
+
+    Ok(rec_opt.map(|rec| User::new(rec.id, username, rec.is_admin)))
 }
 
 #[tracing::instrument(name = "services::verify_user_password", skip_all)]
@@ -43,7 +65,7 @@ pub async fn authenticate_user(
 ) -> Result<User, ServiceError> {
     let rec = sqlx::query!(
         r#"
-        SELECT id, password_hash
+        SELECT id, password_hash, is_admin, failed_logins
         FROM users_main
         WHERE username = $1
         "#,
@@ -54,20 +76,216 @@ pub async fn authenticate_user(
     .map_err(ServiceError::DatabaseError)?;
 
     let Some(rec) = rec else {
+        // Verify against a dummy hash anyway, so an unknown username takes about as long
+        // as a wrong password and response times don't reveal which accounts exist
+        if let Ok(hash) = PasswordHash::new(DUMMY_PASSWORD_HASH) {
+            let _ = hasher.verify_password(password.as_str().as_bytes(), &hash);
+        }
         return Err(ServiceError::AuthError);
     };
 
-    let hash = PasswordHash::new(&rec.password_hash)
-        .map_err(|e| anyhow::anyhow!("invalid password hash: {e}"))
-        .map_err(ServiceError::Other)?;
-
     let password_str = password.as_str();
-    if hasher
-        .verify_password(password_str.as_bytes(), &hash)
-        .is_err()
-    {
+
+    // Imported bcrypt hashes are verified as is and replaced with argon2 below
+    let (verified, outdated) = if rec.password_hash.starts_with("$2") {
+        let verified = bcrypt::verify(password_str, &rec.password_hash)
+            .map_err(|e| anyhow::anyhow!("invalid password hash: {e}"))
+            .map_err(ServiceError::Other)?;
+        (verified, true)
+    } else {
+        let hash = PasswordHash::new(&rec.password_hash)
+            .map_err(|e| anyhow::anyhow!("invalid password hash: {e}"))
+            .map_err(ServiceError::Other)?;
+
+        let verified = hasher
+            .verify_password(password_str.as_bytes(), &hash)
+            .is_ok();
+        (verified, is_outdated(&hash, hasher))
+    };
+
+    if !verified {
+        record_failed_login(rec.id, pool).await?;
         return Err(ServiceError::AuthError);
     }
 
-    Ok(User::new(rec.id, username))
+    if rec.failed_logins > 0 {
+        reset_failed_logins(rec.id, pool).await?;
+    }
+
+    if outdated {
+        // Failing to upgrade the hash shouldn't fail the login
+        if let Err(e) = upgrade_password_hash(rec.id, password_str, hasher, pool).await {
+            tracing::warn!(error = %e, "failed to upgrade password hash");
+        }
+    }
+
+    Ok(User::new(rec.id, username, rec.is_admin))
+}
+
+async fn record_failed_login(user_id: UserId, pool: &PgPool) -> Result<(), ServiceError> {
+    sqlx::query!(
+        r#"
+        UPDATE users_main
+        SET failed_logins = failed_logins + 1,
+            last_failed_at = now()
+        WHERE id = $1
+        "#,
+        user_id
+    )
+    .execute(pool)
+    .await
+    .map_err(ServiceError::DatabaseError)?;
+
+    Ok(())
+}
+
+async fn reset_failed_logins(user_id: UserId, pool: &PgPool) -> Result<(), ServiceError> {
+    sqlx::query!(
+        r#"
+        UPDATE users_main
+        SET failed_logins = 0
+        WHERE id = $1
+        "#,
+        user_id
+    )
+    .execute(pool)
+    .await
+    .map_err(ServiceError::DatabaseError)?;
+
+    Ok(())
+}
+
+/// Whether the hash was made with a different algorithm or costs than the hasher uses
+fn is_outdated(hash: &PasswordHash<'_>, hasher: &Argon2<'_>) -> bool {
+    let current = hasher.params();
+    let same_costs = Params::try_from(hash).is_ok_and(|params| {
+        params.m_cost() == current.m_cost()
+            && params.t_cost() == current.t_cost()
+            && params.p_cost() == current.p_cost()
+    });
+
+    hash.algorithm != ARGON2ID_IDENT || !same_costs
+}
+
+/// Rehash the password with current argon2 parameters
+async fn upgrade_password_hash(
+    user_id: UserId,
+    password: &str,
+    hasher: &Argon2<'_>,
+    pool: &PgPool,
+) -> Result<(), ServiceError> {
+    let hash = hash_password(password, hasher)?;
+
+    sqlx::query!(
+        r#"
+        UPDATE users_main
+        SET password_hash = $1
+        WHERE id = $2
+        "#,
+        hash,
+        user_id
+    )
+    .execute(pool)
+    .await
+    .map_err(ServiceError::DatabaseError)?;
+
+    Ok(())
+}
+
+/// Create a user with a password hash from another system
+///
+/// Returns Ok(None) if the username is taken
+#[tracing::instrument(name = "services::import_user", skip_all)]
+pub async fn import_user(
+    username: UserName,
+    password_hash: ImportedPasswordHash,
+    pool: &PgPool,
+) -> Result<Option<User>, ServiceError> {
+    let rec_opt = sqlx::query!(
+        r#"
+        INSERT INTO users_main (username, password_hash)
+        VALUES ($1, $2)
+        ON CONFLICT (username) DO NOTHING
+        RETURNING id
+        "#,
+        username.as_str(),
+        password_hash.as_str()
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(ServiceError::DatabaseError)?;
+
+    Ok(rec_opt.map(|rec| User::new(rec.id, username, false)))
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct UserSummary {
+    pub id: UserId,
+    pub username: String,
+    #[serde(with = "time::serde::rfc3339")]
+    pub created_at: OffsetDateTime,
+    pub link_count: i64,
+    /// Failed logins since the last successful one
+    pub failed_logins: i32,
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub last_failed_at: Option<OffsetDateTime>,
+}
+
+/// Filters for listing users
+#[derive(Debug, Default)]
+pub struct UserFilter<'a> {
+    pub username_prefix: Option<&'a str>,
+    pub created_after: Option<OffsetDateTime>,
+}
+
+/// List users with their link counts ordered by id, starting after `after_id`
+#[tracing::instrument(name = "services::admin_list_users", skip(pool))]
+pub async fn admin_list_users(
+    filter: &UserFilter<'_>,
+    after_id: Option<UserId>,
+    limit: i64,
+    pool: &PgPool,
+) -> Result<Vec<UserSummary>, ServiceError> {
+    let username_pattern = filter
+        .username_prefix
+        .map(|prefix| format!("{}%", escape_like(prefix)));
+
+    let recs = sqlx::query!(
+        r#"
+        SELECT
+            u.id,
+            u.username,
+            u.created_at,
+            u.failed_logins,
+            u.last_failed_at,
+            COUNT(l.id) AS "link_count!"
+        FROM users_main u
+        LEFT JOIN links_main l ON l.user_id = u.id AND l.deleted_at IS NULL
+        WHERE u.id > $1
+          AND ($2::text IS NULL OR u.username LIKE $2)
+          AND ($3::timestamptz IS NULL OR u.created_at > $3)
+        GROUP BY u.id
+        ORDER BY u.id
+        LIMIT $4
+        "#,
+        after_id.unwrap_or(0),
+        username_pattern,
+        filter.created_after,
+        limit
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(ServiceError::DatabaseError)?;
+
+    Ok(recs
+        .into_iter()
+        .map(|rec| UserSummary {
+            id: rec.id,
+            username: rec.username,
+            created_at: rec.created_at,
+            link_count: rec.link_count,
+            failed_logins: rec.failed_logins,
+            last_failed_at: rec.last_failed_at,
+        })
+        .collect())
 }
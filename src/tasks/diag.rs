@@ -5,7 +5,7 @@ use anyhow::Result;
 use crate::app::Diag;
 
 pub async fn print_diagnostics_task(diag: Arc<Diag>) -> Result<()> {
-    let (cache_hits, cache_misses) = diag.snapshot();
+    let (cache_hits, cache_misses, alias_filter_rejects, sqids_high_water) = diag.snapshot();
     let total = cache_hits + cache_misses;
     let eff = if total == 0 {
         0.0
@@ -13,10 +13,12 @@ pub async fn print_diagnostics_task(diag: Arc<Diag>) -> Result<()> {
         cache_hits as f64 / total as f64
     };
     tracing::info!(
-        "eff={}, cache_hits={}, cache_misses={}",
+        "eff={}, cache_hits={}, cache_misses={}, alias_filter_rejects={}, sqids_high_water={}",
         eff,
         cache_hits,
-        cache_misses
+        cache_misses,
+        alias_filter_rejects,
+        sqids_high_water
     );
     Ok(())
 }
@@ -1,23 +1,187 @@
-use std::time::Instant;
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use anyhow::Result;
+use arc_swap::ArcSwap;
+use dashmap::DashSet;
 use sqlx::PgPool;
+use time::OffsetDateTime;
 
-const TTI_DAYS: i32 = 30;
-const BATCH_SIZE: i64 = 5_000;
+use crate::{
+    app::usage_metrics::{Category, Metrics, QuietHours},
+    config::AppConfig,
+};
 
-pub async fn link_cleanup_task(pool: PgPool) -> Result<()> {
+/// Upper bound of queued expired links, the periodic cleanup picks up the rest
+const MAX_PENDING: usize = 10_000;
+
+/// Retention settings shared by the cleanup tasks
+#[derive(Clone, Copy, Debug)]
+pub struct CleanupConfig {
+    /// Days a link without an explicit expiry lives after its last visit
+    pub idle_days: u16,
+    /// Links deleted per statement
+    pub batch_size: u32,
+    /// Wait between delete statements
+    pub batch_pause: Duration,
+    /// Days aliases of deleted links stay retired
+    pub grace_days: u16,
+    /// Only delete in these hours when gated on load, instead of avoiding busy hours
+    pub quiet_hours: Option<QuietHours>,
+}
+
+impl From<&AppConfig> for CleanupConfig {
+    fn from(config: &AppConfig) -> Self {
+        Self {
+            idle_days: config.link_idle_days,
+            batch_size: config.cleanup_batch_size,
+            batch_pause: Duration::from_millis(config.cleanup_batch_pause_ms),
+            grace_days: config.alias_grace_days,
+            quiet_hours: config.low_traffic_hours,
+        }
+    }
+}
+
+impl Default for CleanupConfig {
+    fn default() -> Self {
+        Self::from(&AppConfig::default())
+    }
+}
+
+/// Expired links hit by visitors, purged in batches ahead of the daily cleanup
+pub struct ExpiredLinks {
+    pending: ArcSwap<DashSet<i64>>,
+}
+
+impl ExpiredLinks {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queue the link for deletion, never blocks
+    pub fn push(&self, link_id: i64) {
+        let pending = self.pending.load();
+        if pending.len() < MAX_PENDING {
+            pending.insert(link_id);
+        }
+    }
+
+    fn take(&self) -> Arc<DashSet<i64>> {
+        self.pending.swap(Arc::new(DashSet::new()))
+    }
+}
+
+impl Default for ExpiredLinks {
+    fn default() -> Self {
+        Self {
+            pending: ArcSwap::from_pointee(DashSet::new()),
+        }
+    }
+}
+
+/// Delete queued links that are still expired or removed past the grace period,
+/// retiring their aliases like the daily cleanup
+///
+/// Returns the number of deleted links
+pub async fn purge_expired_task(
+    pool: PgPool,
+    expired: Arc<ExpiredLinks>,
+    config: CleanupConfig,
+) -> Result<u64> {
+    let ids: Vec<i64> = expired.take().iter().map(|id| *id).collect();
+    if ids.is_empty() {
+        return Ok(0);
+    }
+
+    // Expiry is checked again, cached lookups may be behind on last seen days
+    let row = sqlx::query!(
+        r#"
+        WITH deleted AS (
+            DELETE FROM links_main
+            WHERE id = ANY($1::bigint[])
+              AND (
+                deleted_at < now() - make_interval(days => $3)
+                OR (
+                  NOT never_expires
+                  AND (
+                    expires_at < now()
+                    OR (expires_at IS NULL AND last_seen < (CURRENT_DATE - $2::int))
+                  )
+                )
+              )
+            RETURNING alias
+        ),
+        retired AS (
+            INSERT INTO retired_aliases (alias, retired_until)
+            SELECT alias, now() + make_interval(days => $3)
+            FROM deleted
+            WHERE alias IS NOT NULL
+            ON CONFLICT (alias) DO UPDATE SET retired_until = EXCLUDED.retired_until
+        )
+        SELECT COUNT(*)::bigint AS "deleted_count!: i64"
+        FROM deleted;
+        "#,
+        &ids,
+        i32::from(config.idle_days),
+        i32::from(config.grace_days),
+    )
+    .fetch_one(&pool)
+    .await?;
+
+    if row.deleted_count > 0 {
+        tracing::info!("Purged {} expired links", row.deleted_count);
+    }
+
+    Ok(row.deleted_count as u64)
+}
+
+/// Delete expired links and links removed past their grace period, and release aliases past theirs
+///
+/// Removed links are deleted whatever their expiry, so their targets aren't kept forever.
+/// With `usage`, deletion stops before any batch that would run outside the configured quiet
+/// hours, or without them in a busy hour of redirects. The remaining links are left to a later run.
+/// Returns the number of deleted links
+pub async fn link_cleanup_task(
+    pool: PgPool,
+    config: CleanupConfig,
+    usage: Option<Arc<Metrics>>,
+) -> Result<u64> {
     tracing::info!("Running link cleanup task...");
 
+    let is_busy = || {
+        let Some(usage) = usage.as_ref() else {
+            return false;
+        };
+        let now = OffsetDateTime::now_utc();
+        match config.quiet_hours {
+            Some(quiet_hours) => !quiet_hours.contains(now),
+            None => usage.is_busy_hour(Category::Redirect, now),
+        }
+    };
+
     let mut entries_deleted = 0i64;
     let start = Instant::now();
     loop {
+        if is_busy() {
+            tracing::info!("Not a quiet hour, postponing the rest of the link cleanup");
+            break;
+        }
+
         let row = sqlx::query!(
             r#"
             WITH expired AS (
                 SELECT id
                 FROM links_main
-                WHERE last_seen < (CURRENT_DATE - $1::int)
+                WHERE deleted_at < now() - make_interval(days => $3)
+                   OR (
+                    NOT never_expires
+                    AND (
+                      expires_at < now()
+                      OR (expires_at IS NULL AND last_seen < (CURRENT_DATE - $1::int))
+                    )
+                   )
                 ORDER BY id
                 LIMIT $2
             ),
@@ -25,22 +189,46 @@ pub async fn link_cleanup_task(pool: PgPool) -> Result<()> {
                 DELETE FROM links_main
                 USING expired
                 WHERE links_main.id = expired.id
-                RETURNING 1
+                RETURNING links_main.alias
+            ),
+            retired AS (
+                INSERT INTO retired_aliases (alias, retired_until)
+                SELECT alias, now() + make_interval(days => $3)
+                FROM deleted
+                WHERE alias IS NOT NULL
+                ON CONFLICT (alias) DO UPDATE SET retired_until = EXCLUDED.retired_until
             )
             SELECT COUNT(*)::bigint AS "deleted_count!: i64"
             FROM deleted;
             "#,
-            TTI_DAYS,
-            BATCH_SIZE,
+            i32::from(config.idle_days),
+            i64::from(config.batch_size),
+            i32::from(config.grace_days),
         )
         .fetch_one(&pool)
         .await?;
 
         entries_deleted += row.deleted_count;
 
-        if row.deleted_count < BATCH_SIZE {
+        if row.deleted_count < i64::from(config.batch_size) {
             break;
         }
+
+        tokio::time::sleep(config.batch_pause).await;
+    }
+
+    let released = sqlx::query!(
+        r#"
+        DELETE FROM retired_aliases
+        WHERE retired_until <= now()
+        "#
+    )
+    .execute(&pool)
+    .await?
+    .rows_affected();
+
+    if released > 0 {
+        tracing::info!("Released {released} retired aliases");
     }
 
     if entries_deleted > 0 {
@@ -53,7 +241,7 @@ pub async fn link_cleanup_task(pool: PgPool) -> Result<()> {
         tracing::info!("Nothing to delete");
     }
 
-    Ok(())
+    Ok(entries_deleted as u64)
 }
 
 #[cfg(test)]
@@ -64,7 +252,110 @@ mod test {
 
     #[sqlx::test]
     async fn link_cleanup_ok(pool: PgPool) -> Result<()> {
-        const LINKS_N: usize = 12_000;
+        check_link_cleanup(pool, CleanupConfig::default(), 12_000).await
+    }
+
+    #[sqlx::test]
+    async fn link_cleanup_short_tti(pool: PgPool) -> Result<()> {
+        let config = CleanupConfig {
+            idle_days: 2,
+            batch_size: 700,
+            ..CleanupConfig::default()
+        };
+        check_link_cleanup(pool, config, 3_000).await
+    }
+
+    #[sqlx::test]
+    async fn link_cleanup_yields_to_busy_hour(pool: PgPool) -> Result<()> {
+        sqlx::query("INSERT INTO links_main (alias, url, last_seen) VALUES ('old', 'https://example.com', '2000-01-01')")
+            .execute(&pool)
+            .await?;
+
+        // All redirects so far landed in the current hour
+        let usage = Arc::new(Metrics::default());
+        for _ in 0..1_000 {
+            usage.log(Category::Redirect);
+        }
+
+        let config = CleanupConfig::default();
+        assert_eq!(
+            link_cleanup_task(pool.clone(), config, Some(usage)).await?,
+            0
+        );
+        assert_eq!(link_cleanup_task(pool, config, None).await?, 1);
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn link_cleanup_confined_to_quiet_hours(pool: PgPool) -> Result<()> {
+        sqlx::query("INSERT INTO links_main (alias, url, last_seen) VALUES ('old', 'https://example.com', '2000-01-01')")
+            .execute(&pool)
+            .await?;
+
+        let usage = Arc::new(Metrics::default());
+        let hour = OffsetDateTime::now_utc().hour();
+        let quiet_from = |start: u8| CleanupConfig {
+            quiet_hours: Some(
+                QuietHours::new(start % 24, (start + 1) % 24, time::UtcOffset::UTC).unwrap(),
+            ),
+            ..CleanupConfig::default()
+        };
+
+        let later = quiet_from(hour + 1);
+        assert_eq!(
+            link_cleanup_task(pool.clone(), later, Some(usage.clone())).await?,
+            0
+        );
+        // Admin runs aren't gated
+        assert_eq!(link_cleanup_task(pool.clone(), later, None).await?, 1);
+
+        sqlx::query("INSERT INTO links_main (alias, url, last_seen) VALUES ('older', 'https://example.com', '2000-01-01')")
+            .execute(&pool)
+            .await?;
+        assert_eq!(
+            link_cleanup_task(pool, quiet_from(hour), Some(usage)).await?,
+            1
+        );
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn link_cleanup_removed_links(pool: PgPool) -> Result<()> {
+        let config = CleanupConfig::default();
+
+        // Removed links go once their grace period is over, even if they'd never expire
+        sqlx::query(
+            r#"
+            INSERT INTO links_main (alias, url, never_expires, deleted_at)
+            VALUES
+                ('removed', 'https://example.com/removed', true, now() - make_interval(days => $1 + 1)),
+                ('recent', 'https://example.com/recent', true, now() - interval '1 day')
+            "#,
+        )
+        .bind(i32::from(config.grace_days))
+        .execute(&pool)
+        .await?;
+
+        assert_eq!(link_cleanup_task(pool.clone(), config, None).await?, 1);
+
+        let remaining: Vec<String> = sqlx::query_scalar("SELECT alias FROM links_main")
+            .fetch_all(&pool)
+            .await?;
+        assert_eq!(remaining, ["recent"]);
+
+        let retired: bool = sqlx::query_scalar(
+            "SELECT EXISTS (SELECT 1 FROM retired_aliases WHERE alias = 'removed')",
+        )
+        .fetch_one(&pool)
+        .await?;
+        assert!(retired, "Alias of the removed link should stay retired");
+
+        Ok(())
+    }
+
+    async fn check_link_cleanup(pool: PgPool, config: CleanupConfig, links_n: usize) -> Result<()> {
         const CHUNK: usize = 5_000;
 
         async fn insert_link_batch(
@@ -106,13 +397,15 @@ mod test {
             .await?
             .today;
 
-        let cutoff = today - TimeDelta::days(TTI_DAYS as i64);
+        let cutoff = today - TimeDelta::days(config.idle_days.into());
         let expired_day = cutoff - TimeDelta::days(1);
 
-        insert_link_batch(&pool, "good", LINKS_N, today, CHUNK).await?;
-        insert_link_batch(&pool, "expired", LINKS_N, expired_day, CHUNK).await?;
+        // Seen on the cutoff day, not expired yet
+        insert_link_batch(&pool, "good", links_n, cutoff, CHUNK).await?;
+        insert_link_batch(&pool, "expired", links_n, expired_day, CHUNK).await?;
 
-        link_cleanup_task(pool.clone()).await?;
+        let deleted = link_cleanup_task(pool.clone(), config, None).await?;
+        assert_eq!(deleted, links_n as u64, "Deleted count doesn't match");
 
         let after = sqlx::query!(
             r#"
@@ -127,7 +420,7 @@ mod test {
         .await?;
 
         assert_eq!(after.expired, 0, "Not all expired links have been deleted");
-        assert_eq!(after.good, LINKS_N as i64, "Missing good links");
+        assert_eq!(after.good, links_n as i64, "Missing good links");
 
         Ok(())
     }
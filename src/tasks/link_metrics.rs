@@ -1,19 +1,20 @@
 use std::{
     sync::{
         Arc,
-        atomic::{AtomicI64, Ordering},
+        atomic::{AtomicI64, AtomicU64, Ordering},
     },
     time::Instant,
 };
 
 use anyhow::{Context, Result};
 use arc_swap::ArcSwap;
-use dashmap::DashMap;
-use sqlx::PgPool;
+use dashmap::{DashMap, mapref::entry::Entry};
+use sqlx::{PgConnection, PgPool};
 use time::{
     Date, Duration as TimeDelta, OffsetDateTime, format_description::StaticFormatDescription,
     macros::format_description,
 };
+use tokio::sync::Notify;
 
 pub struct LinkMetricsData {
     hits: AtomicI64,
@@ -23,7 +24,7 @@ pub struct LinkMetricsData {
 impl LinkMetricsData {
     pub fn new(last_access_s: i64) -> Self {
         Self {
-            hits: AtomicI64::new(1),
+            hits: AtomicI64::new(0),
             last_access_s: AtomicI64::new(last_access_s),
         }
     }
@@ -39,8 +40,84 @@ impl LinkMetricsData {
 
 pub type LinkMetricsMap = DashMap<i64, LinkMetricsData>;
 
+/// Coarse property of a visit, counted per link and day when enabled
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum VisitDimension {
+    /// Host of the `Referer` header
+    Referrer,
+    /// Primary subtag of the preferred `Accept-Language`
+    Language,
+}
+
+impl VisitDimension {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Referrer => "referrer",
+            Self::Language => "language",
+        }
+    }
+}
+
+pub type VisitDimensionMap = DashMap<(i64, VisitDimension, String), AtomicI64>;
+
+/// Hits of a link with a hit limit, counted across batch swaps
+struct LimitedHits {
+    /// `hit_count` of the link when the entry was created
+    seed: i64,
+    hits: AtomicI64,
+}
+
+/// Outcome of a hit on a link with a hit limit
+#[derive(Debug, PartialEq, Eq)]
+pub enum LimitedHit {
+    Recorded,
+    /// The limit is used up, nothing was recorded
+    Exhausted,
+    /// The count of the link was pruned after its `hit_count` was loaded, reload the link and retry
+    Stale,
+}
+
+/// Upper bound of distinct dimension values kept between drains, further values are dropped
+const MAX_DIMENSION_ENTRIES: usize = 10_000;
+
 pub struct LinkMetrics {
     current: ArcSwap<LinkMetricsMap>,
+    /// Total hits of links with a hit limit, outlives batch swaps so limits hold before a flush
+    ///
+    /// Entries are dropped by [`LinkMetrics::prune_limited`] once their hits are persisted
+    limited: DashMap<i64, LimitedHits>,
+    /// Bumped whenever a count with hits is pruned, `hit_count`s loaded before it may miss them
+    limits_epoch: AtomicU64,
+    dimensions: ArcSwap<VisitDimensionMap>,
+    flushes: FlushStats,
+    /// Distinct links a batch holds before an early flush is requested, unbounded if unset
+    max_entries: Option<usize>,
+    flush_trigger: Arc<Notify>,
+}
+
+/// Counters of hit batches written to the DB
+#[derive(Default)]
+pub struct FlushStats {
+    batches: AtomicU64,
+    entries: AtomicU64,
+    last_entries: AtomicU64,
+}
+
+impl FlushStats {
+    fn record(&self, entries: usize) {
+        self.batches.fetch_add(1, Ordering::Relaxed);
+        self.entries.fetch_add(entries as u64, Ordering::Relaxed);
+        self.last_entries.store(entries as u64, Ordering::Relaxed);
+    }
+
+    /// Returns (batches, entries, entries of the last batch)
+    pub fn snapshot(&self) -> (u64, u64, u64) {
+        (
+            self.batches.load(Ordering::Relaxed),
+            self.entries.load(Ordering::Relaxed),
+            self.last_entries.load(Ordering::Relaxed),
+        )
+    }
 }
 
 impl LinkMetrics {
@@ -48,11 +125,28 @@ impl LinkMetrics {
         Self::default()
     }
 
+    /// Metrics requesting an early flush once a batch holds more than `max_entries` links
+    pub fn with_max_entries(max_entries: Option<usize>) -> Self {
+        Self {
+            max_entries,
+            ..Self::default()
+        }
+    }
+
+    /// Notified when the current batch outgrows the entry cap and should be flushed early
+    pub fn flush_trigger(&self) -> Arc<Notify> {
+        self.flush_trigger.clone()
+    }
+
     pub fn record_hit(&self, link_id: i64) {
         let now_s = OffsetDateTime::now_utc().unix_timestamp();
 
         let map = self.current.load();
-        let val = map.entry(link_id).or_insert(LinkMetricsData::new(now_s));
+        let mut inserted = false;
+        let val = map.entry(link_id).or_insert_with(|| {
+            inserted = true;
+            LinkMetricsData::new(now_s)
+        });
 
         // increment hitcount
         val.hits.fetch_add(1, Ordering::Relaxed);
@@ -70,72 +164,197 @@ impl LinkMetrics {
                 Err(next) => last_access_s = next,
             }
         }
+        // Release the shard lock before counting entries
+        drop(val);
+
+        if inserted
+            && self
+                .max_entries
+                .is_some_and(|max_entries| map.len() > max_entries)
+        {
+            // Stores a single permit, so a burst of new links wakes the flush once
+            self.flush_trigger.notify_one();
+        }
+    }
+
+    /// Hits counted towards the limit of a link, the persisted `hit_count` if none were recorded
+    pub fn limited_hits(&self, link_id: i64, hit_count: i64) -> i64 {
+        self.limited
+            .get(&link_id)
+            .map_or(hit_count, |limited| limited.hits.load(Ordering::Relaxed))
+    }
+
+    /// Epoch to keep with a loaded `hit_count`, read it before loading
+    pub fn limits_epoch(&self) -> u64 {
+        self.limits_epoch.load(Ordering::Acquire)
+    }
+
+    /// Record a hit on a link with a hit limit, the count starts at `hit_count` loaded in `loaded_epoch`
+    pub fn try_record_limited_hit(
+        &self,
+        link_id: i64,
+        hit_count: i64,
+        loaded_epoch: u64,
+        max_hits: i64,
+    ) -> LimitedHit {
+        // Seeded under the shard lock, so a prune can't land between the epoch check and the insert
+        let limited = match self.limited.entry(link_id) {
+            Entry::Occupied(entry) => entry.into_ref(),
+            Entry::Vacant(_) if loaded_epoch < self.limits_epoch() => return LimitedHit::Stale,
+            Entry::Vacant(entry) => entry.insert(LimitedHits {
+                seed: hit_count,
+                hits: AtomicI64::new(hit_count),
+            }),
+        };
+
+        let claimed = limited
+            .hits
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |hits| {
+                (hits < max_hits).then_some(hits + 1)
+            })
+            .is_ok();
+        if !claimed {
+            return LimitedHit::Exhausted;
+        }
+
+        // Still holding the entry, so a prune finds the hit pending in the batch
+        self.record_hit(link_id);
+        LimitedHit::Recorded
+    }
+
+    /// Count a visit of the link under a dimension value
+    pub fn record_dimension(&self, link_id: i64, dimension: VisitDimension, value: String) {
+        let map = self.dimensions.load();
+        let key = (link_id, dimension, value);
+
+        if let Some(hits) = map.get(&key) {
+            hits.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+        // Values come from request headers, don't let them grow the map without bound
+        if map.len() >= MAX_DIMENSION_ENTRIES {
+            return;
+        }
+        map.entry(key)
+            .or_insert_with(|| AtomicI64::new(0))
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Drop hit limit counts of links without hits waiting for the next drain
+    ///
+    /// Links loaded before a pruned count had hits must be reloaded before counting again,
+    /// see [`LimitedHit::Stale`]
+    pub fn prune_limited(&self) {
+        let pending = self.current.load();
+
+        self.limited.retain(|link_id, limited| {
+            if pending.contains_key(link_id) {
+                return true;
+            }
+            // Bumped under the shard lock, before the entry is gone
+            if limited.hits.load(Ordering::Relaxed) > limited.seed {
+                self.limits_epoch.fetch_add(1, Ordering::Release);
+            }
+            false
+        });
     }
 
     pub fn swap_map(&self) -> Arc<LinkMetricsMap> {
         self.current.swap(Arc::new(DashMap::new()))
     }
-}
 
-impl Default for LinkMetrics {
-    fn default() -> Self {
-        Self {
-            current: ArcSwap::from_pointee(DashMap::new()),
-        }
+    pub fn flushes(&self) -> &FlushStats {
+        &self.flushes
     }
-}
 
-pub async fn process_batch_task(pool: PgPool, metrics: Arc<LinkMetrics>) -> Result<()> {
-    const CHUNK_SIZE: usize = 500;
+    /// Persist hits recorded since the last drain, also run on shutdown so they aren't lost
+    ///
+    /// Returns the number of links whose hits were written
+    pub async fn drain_to_db(&self, pool: &PgPool) -> Result<u64> {
+        let map: Arc<LinkMetricsMap> = self.swap_map();
+        let dimensions = self.dimensions.swap(Arc::new(DashMap::new()));
 
-    let map: Arc<LinkMetricsMap> = metrics.swap_map();
+        let entries = self.drain_hits(pool, &map).await?;
+        flush_dimensions_to_db(pool, &dimensions).await?;
 
-    if map.is_empty() {
-        return Ok(());
+        Ok(entries)
     }
 
-    let start = Instant::now();
+    async fn drain_hits(&self, pool: &PgPool, map: &LinkMetricsMap) -> Result<u64> {
+        const CHUNK_SIZE: usize = 500;
+
+        if map.is_empty() {
+            return Ok(0);
+        }
 
-    // (link_id, hits, last_access) columns
-    let mut link_id_col: Vec<i64> = Vec::with_capacity(CHUNK_SIZE);
-    let mut hits_col: Vec<i64> = Vec::with_capacity(CHUNK_SIZE);
-    let mut last_access_col: Vec<OffsetDateTime> = Vec::with_capacity(CHUNK_SIZE);
+        let start = Instant::now();
 
-    let mut entries_updated = 0usize;
-    for entry in map.iter() {
-        let link_id = *entry.key();
-        let val = entry.value();
+        // (link_id, hits, last_access) columns
+        let mut link_id_col: Vec<i64> = Vec::with_capacity(CHUNK_SIZE);
+        let mut hits_col: Vec<i64> = Vec::with_capacity(CHUNK_SIZE);
+        let mut last_access_col: Vec<OffsetDateTime> = Vec::with_capacity(CHUNK_SIZE);
 
-        let hits = val.hits();
-        if hits == 0 {
-            continue;
+        let mut entries_updated = 0usize;
+        for entry in map.iter() {
+            let link_id = *entry.key();
+            let val = entry.value();
+
+            let hits = val.hits();
+            if hits == 0 {
+                continue;
+            }
+
+            let last_access = OffsetDateTime::from_unix_timestamp(val.last_access_s())
+                .context("Failed to convert last access seconds (i64) back into unix timestamp")?;
+
+            link_id_col.push(link_id);
+            hits_col.push(hits);
+            last_access_col.push(last_access);
+            entries_updated += 1;
+
+            // Flush once a chunk is full
+            if link_id_col.len() == CHUNK_SIZE {
+                flush_to_db(pool, &link_id_col, &hits_col, &last_access_col).await?;
+                // Clear columns
+                link_id_col.clear();
+                hits_col.clear();
+                last_access_col.clear();
+            }
         }
 
-        let last_access = OffsetDateTime::from_unix_timestamp(val.last_access_s())
-            .context("Failed to convert last access seconds (i64) back into unix timestamp")?;
+        // Flush the rest
+        flush_to_db(pool, &link_id_col, &hits_col, &last_access_col).await?;
+
+        self.flushes.record(entries_updated);
+
+        let elapsed_ms = start.elapsed().as_millis();
+        tracing::info!("Updated {} entries in {} ms", entries_updated, elapsed_ms);
 
-        link_id_col.push(link_id);
-        hits_col.push(hits);
-        last_access_col.push(last_access);
-        entries_updated += 1;
+        Ok(entries_updated as u64)
+    }
+}
 
-        // Flush once a chunk is full
-        if link_id_col.len() == CHUNK_SIZE {
-            flush_to_db(&pool, &link_id_col, &hits_col, &last_access_col).await?;
-            // Clear columns
-            link_id_col.clear();
-            hits_col.clear();
-            last_access_col.clear();
+impl Default for LinkMetrics {
+    fn default() -> Self {
+        Self {
+            current: ArcSwap::from_pointee(DashMap::new()),
+            limited: DashMap::new(),
+            limits_epoch: AtomicU64::new(0),
+            dimensions: ArcSwap::from_pointee(DashMap::new()),
+            flushes: FlushStats::default(),
+            max_entries: None,
+            flush_trigger: Arc::new(Notify::new()),
         }
     }
+}
 
-    // Flush the rest
-    flush_to_db(&pool, &link_id_col, &hits_col, &last_access_col).await?;
+pub async fn process_batch_task(pool: PgPool, metrics: Arc<LinkMetrics>) -> Result<u64> {
+    let entries = metrics.drain_to_db(&pool).await?;
 
-    let elapsed_ms = start.elapsed().as_millis();
-    tracing::info!("Updated {} entries in {} ms", entries_updated, elapsed_ms);
+    // Flushed hits are in `hit_count` now
+    metrics.prune_limited();
 
-    Ok(())
+    Ok(entries)
 }
 
 async fn flush_to_db(
@@ -173,17 +392,18 @@ async fn flush_to_db(
 
     sqlx::query!(
         r#"
-        WITH ids AS (
-          SELECT link_id
-          FROM UNNEST($1::bigint[]) AS t(link_id)
+        WITH t AS (
+          SELECT link_id, hits
+          FROM UNNEST($1::bigint[], $2::bigint[]) AS t(link_id, hits)
         )
         UPDATE links_main
-        SET last_seen = CURRENT_DATE
-        FROM ids
-        WHERE links_main.id = ids.link_id
-          AND links_main.last_seen < CURRENT_DATE
+        SET last_seen = GREATEST(links_main.last_seen, CURRENT_DATE),
+            hit_count = links_main.hit_count + t.hits
+        FROM t
+        WHERE links_main.id = t.link_id
         "#,
         link_id_col,
+        hits_col,
     )
     .execute(&mut *tx)
     .await?;
@@ -192,47 +412,347 @@ async fn flush_to_db(
     Ok(())
 }
 
+async fn flush_dimensions_to_db(pool: &PgPool, dimensions: &VisitDimensionMap) -> Result<()> {
+    if dimensions.is_empty() {
+        return Ok(());
+    }
+
+    // (link_id, dimension, value, hits) columns
+    let mut link_id_col: Vec<i64> = Vec::with_capacity(dimensions.len());
+    let mut dimension_col: Vec<&str> = Vec::with_capacity(dimensions.len());
+    let mut value_col: Vec<String> = Vec::with_capacity(dimensions.len());
+    let mut hits_col: Vec<i64> = Vec::with_capacity(dimensions.len());
+
+    for entry in dimensions.iter() {
+        let (link_id, dimension, value) = entry.key();
+        link_id_col.push(*link_id);
+        dimension_col.push(dimension.as_str());
+        value_col.push(value.clone());
+        hits_col.push(entry.value().load(Ordering::Relaxed));
+    }
+
+    // Links deleted since the visit are skipped
+    sqlx::query!(
+        r#"
+        INSERT INTO visit_dimensions (day, link_id, dimension, value, hits)
+        SELECT CURRENT_DATE, t.link_id, t.dimension, t.value, t.hits
+        FROM UNNEST($1::bigint[], $2::text[], $3::text[], $4::bigint[])
+            AS t(link_id, dimension, value, hits)
+        WHERE EXISTS (SELECT 1 FROM links_main WHERE id = t.link_id)
+        ON CONFLICT (link_id, day, dimension, value) DO UPDATE
+          SET hits = visit_dimensions.hits + EXCLUDED.hits
+        "#,
+        &link_id_col,
+        &dimension_col as &[&str],
+        &value_col,
+        &hits_col,
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
 static PART_NAME_DATE_FD: StaticFormatDescription = format_description!("[year][month][day]");
 static ISO_DATE_FD: StaticFormatDescription = format_description!("[year]-[month]-[day]");
 
-pub async fn create_partitions_task(pool: PgPool) -> Result<()> {
+/// Partitions of daily metrics with the day they hold, named `daily_metrics_YYYYMMDD`
+async fn daily_partitions(conn: &mut PgConnection) -> Result<Vec<(String, Date)>> {
+    let names: Vec<String> = sqlx::query_scalar(
+        r#"
+        SELECT child.relname::text
+        FROM pg_inherits
+        JOIN pg_class child ON child.oid = pg_inherits.inhrelid
+        JOIN pg_class parent ON parent.oid = pg_inherits.inhparent
+        WHERE parent.relname = 'daily_metrics'
+        "#,
+    )
+    .fetch_all(conn)
+    .await?;
+
+    Ok(names
+        .into_iter()
+        .filter_map(|name| {
+            let day = name.strip_prefix("daily_metrics_")?;
+            let day = Date::parse(day, &PART_NAME_DATE_FD).ok()?;
+            Some((name, day))
+        })
+        .collect())
+}
+
+/// Days of partitions kept ready ahead of today
+const PARTITION_DAYS_AHEAD: i64 = 3;
+/// Upper bound of past days backfilled after downtime, older hits can't arrive anymore
+const MAX_BACKFILL_DAYS: i64 = 31;
+
+/// Create daily metrics partitions up to a few days ahead, backfilling days missed while down
+///
+/// Returns the number of created partitions
+pub async fn create_partitions_task(pool: PgPool) -> Result<u64> {
     tracing::info!("Creating daily metrics partitions...");
 
+    let mut tx = pool.begin().await?;
+
+    // Serialize with other instances running the task
+    sqlx::query("LOCK TABLE daily_metrics IN SHARE ROW EXCLUSIVE MODE")
+        .execute(&mut *tx)
+        .await?;
+
     let today: Date = sqlx::query_scalar("SELECT CURRENT_DATE")
-        .fetch_one(&pool)
+        .fetch_one(&mut *tx)
         .await?;
 
-    // Create partitions for 4 days
-    for offset in 0..=3 {
-        let start = today + TimeDelta::days(offset);
-        let end = start + TimeDelta::days(1);
+    let existing = daily_partitions(&mut tx).await?;
+
+    // Days after the latest partition were missed if it is behind today
+    let latest = existing.iter().map(|(_, day)| *day).max();
+    let first = match latest {
+        Some(latest) if latest < today => {
+            (latest + TimeDelta::days(1)).max(today - TimeDelta::days(MAX_BACKFILL_DAYS))
+        }
+        _ => today,
+    };
+    let last = today + TimeDelta::days(PARTITION_DAYS_AHEAD);
 
-        let iso_start = start.format(&ISO_DATE_FD)?;
-        let iso_end = end.format(&ISO_DATE_FD)?;
+    let mut created = 0u64;
+    let mut start = first;
+    while start <= last {
+        let end = start + TimeDelta::days(1);
 
         // daily_metrics_YYYYMMDD
         let part_name = format!("daily_metrics_{}", start.format(&PART_NAME_DATE_FD)?);
-        let sql = format!(
-            r#"
-            CREATE TABLE IF NOT EXISTS {part}
-            PARTITION OF daily_metrics
-            FOR VALUES FROM ('{from}') TO ('{to}');
-            "#,
-            part = part_name,
-            from = iso_start,
-            to = iso_end,
-        );
+        if !existing.iter().any(|(name, _)| *name == part_name) {
+            let sql = format!(
+                r#"
+                CREATE TABLE {part}
+                PARTITION OF daily_metrics
+                FOR VALUES FROM ('{from}') TO ('{to}');
+                "#,
+                part = part_name,
+                from = start.format(&ISO_DATE_FD)?,
+                to = end.format(&ISO_DATE_FD)?,
+            );
+
+            sqlx::query(&sql).execute(&mut *tx).await?;
+            created += 1;
+        }
 
-        sqlx::query(&sql).execute(&pool).await?;
+        start = end;
     }
 
-    Ok(())
+    tx.commit().await?;
+
+    if created > 0 {
+        tracing::info!("Created {created} daily metrics partitions");
+    }
+
+    Ok(created)
+}
+
+/// Drop daily metrics partitions of days before the retention window
+///
+/// Returns the number of dropped partitions
+pub async fn drop_old_partitions_task(pool: PgPool, retention_days: u16) -> Result<u64> {
+    let mut tx = pool.begin().await?;
+
+    let today: Date = sqlx::query_scalar("SELECT CURRENT_DATE")
+        .fetch_one(&mut *tx)
+        .await?;
+    let cutoff = today - TimeDelta::days(retention_days.into());
+
+    let mut dropped = 0u64;
+    for (name, day) in daily_partitions(&mut tx).await? {
+        if day >= cutoff {
+            continue;
+        }
+
+        sqlx::query(&format!("DROP TABLE IF EXISTS {name}"))
+            .execute(&mut *tx)
+            .await?;
+        tracing::info!("Dropped metrics partition {name}");
+        dropped += 1;
+    }
+
+    tx.commit().await?;
+
+    Ok(dropped)
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
 
+    #[sqlx::test]
+    async fn partitions_backfilled_after_gap(pool: PgPool) -> Result<()> {
+        let today: Date = sqlx::query_scalar("SELECT CURRENT_DATE")
+            .fetch_one(&pool)
+            .await?;
+        let partition_exists = |day: Date| {
+            let name = format!("daily_metrics_{}", day.format(&PART_NAME_DATE_FD).unwrap());
+            let pool = pool.clone();
+            async move {
+                sqlx::query_scalar::<_, bool>("SELECT to_regclass($1) IS NOT NULL")
+                    .bind(name)
+                    .fetch_one(&pool)
+                    .await
+                    .unwrap()
+            }
+        };
+
+        // Partitions were last created 6 days ago, then the app was down
+        let last_run = today - TimeDelta::days(6);
+        let name = format!("daily_metrics_{}", last_run.format(&PART_NAME_DATE_FD)?);
+        sqlx::query(&format!(
+            "CREATE TABLE {name} PARTITION OF daily_metrics FOR VALUES FROM ('{}') TO ('{}')",
+            last_run.format(&ISO_DATE_FD)?,
+            (last_run + TimeDelta::days(1)).format(&ISO_DATE_FD)?,
+        ))
+        .execute(&pool)
+        .await?;
+
+        // 5 missed days, today and 3 days ahead
+        assert_eq!(create_partitions_task(pool.clone()).await?, 9);
+        for offset in -6..=PARTITION_DAYS_AHEAD {
+            let day = today + TimeDelta::days(offset);
+            assert!(partition_exists(day).await, "Missing partition for {day}");
+        }
+        assert!(!partition_exists(last_run - TimeDelta::days(1)).await);
+
+        // A hit flushed late for a missed day has somewhere to go
+        sqlx::query(
+            "INSERT INTO links_main (id, alias, url) VALUES (1, 'late', 'https://example.com')",
+        )
+        .execute(&pool)
+        .await?;
+        sqlx::query(
+            "INSERT INTO daily_metrics (day, link_id, hits, last_access) VALUES ($1, 1, 1, now())",
+        )
+        .bind(today - TimeDelta::days(2))
+        .execute(&pool)
+        .await?;
+
+        assert_eq!(create_partitions_task(pool).await?, 0);
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn old_partitions_dropped(pool: PgPool) -> Result<()> {
+        let today: Date = sqlx::query_scalar("SELECT CURRENT_DATE")
+            .fetch_one(&pool)
+            .await?;
+
+        // A partition for every day of the last 10, plus the ones ahead of today
+        for offset in -10..=0 {
+            let day = today + TimeDelta::days(offset);
+            let name = format!("daily_metrics_{}", day.format(&PART_NAME_DATE_FD)?);
+            sqlx::query(&format!(
+                "CREATE TABLE {name} PARTITION OF daily_metrics FOR VALUES FROM ('{}') TO ('{}')",
+                day.format(&ISO_DATE_FD)?,
+                (day + TimeDelta::days(1)).format(&ISO_DATE_FD)?,
+            ))
+            .execute(&pool)
+            .await?;
+        }
+        create_partitions_task(pool.clone()).await?;
+
+        assert_eq!(drop_old_partitions_task(pool.clone(), 7).await?, 3);
+        assert_eq!(drop_old_partitions_task(pool.clone(), 7).await?, 0);
+
+        let mut conn = pool.acquire().await?;
+        let mut days: Vec<Date> = daily_partitions(&mut conn)
+            .await?
+            .into_iter()
+            .map(|(_, day)| day)
+            .collect();
+        days.sort();
+        assert_eq!(days.first(), Some(&(today - TimeDelta::days(7))));
+        assert_eq!(days.len(), 7 + 1 + PARTITION_DAYS_AHEAD as usize);
+
+        Ok(())
+    }
+
+    #[test]
+    fn early_flush_requested_over_cap() {
+        use futures_util::FutureExt;
+
+        let metrics = LinkMetrics::with_max_entries(Some(2));
+        let trigger = metrics.flush_trigger();
+
+        metrics.record_hit(1);
+        metrics.record_hit(2);
+        // Hits on links already in the batch don't grow it
+        metrics.record_hit(2);
+        assert!(trigger.notified().now_or_never().is_none());
+
+        metrics.record_hit(3);
+        assert!(trigger.notified().now_or_never().is_some());
+
+        // A fresh batch starts below the cap again
+        metrics.swap_map();
+        metrics.record_hit(4);
+        assert!(trigger.notified().now_or_never().is_none());
+    }
+
+    #[test]
+    fn first_visit_counted_once() {
+        let metrics = LinkMetrics::new();
+
+        metrics.record_hit(1);
+        assert_eq!(metrics.swap_map().get(&1).unwrap().hits(), 1);
+
+        metrics.record_hit(1);
+        metrics.record_hit(1);
+        assert_eq!(metrics.swap_map().get(&1).unwrap().hits(), 2);
+    }
+
+    #[test]
+    fn limited_hits_pruned_once_persisted() {
+        let metrics = LinkMetrics::new();
+        let epoch = metrics.limits_epoch();
+        let hit = |hit_count, epoch| metrics.try_record_limited_hit(1, hit_count, epoch, 2);
+
+        // Checking a limit doesn't track the link
+        assert_eq!(metrics.limited_hits(1, 0), 0);
+        assert!(metrics.limited.is_empty());
+
+        assert_eq!(hit(0, epoch), LimitedHit::Recorded);
+        assert_eq!(hit(0, epoch), LimitedHit::Recorded);
+        assert_eq!(hit(0, epoch), LimitedHit::Exhausted);
+
+        // Hits waiting for a drain keep the count
+        metrics.prune_limited();
+        assert_eq!(metrics.limited_hits(1, 0), 2);
+        assert_eq!(metrics.limits_epoch(), epoch);
+
+        metrics.swap_map();
+        metrics.prune_limited();
+        assert!(metrics.limited.is_empty());
+
+        // A visit at the flush boundary still holds the link loaded before it
+        assert_eq!(hit(0, epoch), LimitedHit::Stale);
+        assert!(metrics.limited.is_empty());
+
+        // Reloaded with the persisted count
+        assert_eq!(hit(2, metrics.limits_epoch()), LimitedHit::Exhausted);
+    }
+
+    #[test]
+    fn unhit_limits_pruned_without_reload() {
+        let metrics = LinkMetrics::new();
+        let epoch = metrics.limits_epoch();
+
+        // Used up when loaded, the cached `hit_count` stays right
+        assert_eq!(
+            metrics.try_record_limited_hit(1, 2, epoch, 2),
+            LimitedHit::Exhausted
+        );
+        metrics.prune_limited();
+
+        assert!(metrics.limited.is_empty());
+        assert_eq!(metrics.limits_epoch(), epoch);
+    }
+
     #[test]
     fn date_formatting() {
         let date = time::macros::date!(2026 - 01 - 19);
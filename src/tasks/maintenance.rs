@@ -0,0 +1,175 @@
+use std::time::Instant;
+
+use anyhow::Result;
+use serde::Serialize;
+use tokio::sync::Mutex;
+
+use crate::{
+    app::AppState,
+    tasks::{
+        link_cleanup::{self, CleanupConfig},
+        link_metrics, session_sweep,
+    },
+};
+
+/// Scheduled tasks that can also be run on demand, named like in the scheduler
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MaintenanceTask {
+    DailyMetrics,
+    LinkCleanup,
+    ExpiredPurge,
+    SessionSweep,
+    IdempotencySweep,
+}
+
+impl MaintenanceTask {
+    pub const ALL: [MaintenanceTask; 5] = [
+        MaintenanceTask::DailyMetrics,
+        MaintenanceTask::LinkCleanup,
+        MaintenanceTask::ExpiredPurge,
+        MaintenanceTask::SessionSweep,
+        MaintenanceTask::IdempotencySweep,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::DailyMetrics => "daily_metrics",
+            Self::LinkCleanup => "link_cleanup",
+            Self::ExpiredPurge => "expired_purge",
+            Self::SessionSweep => "session_sweep",
+            Self::IdempotencySweep => "idempotency_sweep",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|task| task.name() == name)
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct TaskSummary {
+    pub task: &'static str,
+    /// Rows written or deleted by the task
+    pub rows: u64,
+    pub elapsed_ms: u64,
+}
+
+/// Keeps every maintenance task from overlapping with itself, whether scheduled or on demand
+#[derive(Default)]
+pub struct Maintenance {
+    running: [Mutex<()>; MaintenanceTask::ALL.len()],
+}
+
+impl Maintenance {
+    /// Run the task once its current run, if any, finishes, bulk deletes yield to busy hours
+    pub async fn run(&self, task: MaintenanceTask, app: &AppState) -> Result<TaskSummary> {
+        let _running = self.running[task as usize].lock().await;
+        execute(task, app, true).await
+    }
+
+    /// Run the task right away regardless of load, `None` if it is already running
+    pub async fn try_run(
+        &self,
+        task: MaintenanceTask,
+        app: &AppState,
+    ) -> Option<Result<TaskSummary>> {
+        let _running = self.running[task as usize].try_lock().ok()?;
+        Some(execute(task, app, false).await)
+    }
+}
+
+async fn execute(
+    task: MaintenanceTask,
+    app: &AppState,
+    yield_to_load: bool,
+) -> Result<TaskSummary> {
+    let start = Instant::now();
+    let cleanup = CleanupConfig::from(app.config.as_ref());
+
+    let rows = match task {
+        MaintenanceTask::DailyMetrics => {
+            link_metrics::process_batch_task(app.pool.clone(), app.metrics.clone()).await?
+        }
+        MaintenanceTask::LinkCleanup => {
+            let usage = yield_to_load.then(|| app.usage_metrics.clone());
+            let deleted = link_cleanup::link_cleanup_task(app.pool.clone(), cleanup, usage).await?;
+            invalidate_deleted(app, deleted);
+            deleted
+        }
+        MaintenanceTask::ExpiredPurge => {
+            let deleted = link_cleanup::purge_expired_task(
+                app.pool.clone(),
+                app.expired_links.clone(),
+                cleanup,
+            )
+            .await?;
+            invalidate_deleted(app, deleted);
+            deleted
+        }
+        MaintenanceTask::SessionSweep => {
+            session_sweep::session_sweep_task(app.sessions.clone()).await?
+        }
+        MaintenanceTask::IdempotencySweep => app.idempotency.sweep(),
+    };
+
+    Ok(TaskSummary {
+        task: task.name(),
+        rows,
+        elapsed_ms: start.elapsed().as_millis() as u64,
+    })
+}
+
+/// Drop cached lookups on every instance after links were deleted in bulk, they may hold any of them
+fn invalidate_deleted(app: &AppState, deleted: u64) {
+    if deleted > 0 {
+        app.cache.invalidate_all();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use sqlx::PgPool;
+
+    use super::*;
+    use crate::app::build_test_app_state;
+
+    #[sqlx::test]
+    async fn tasks_do_not_overlap(pool: PgPool) -> Result<()> {
+        let app = build_test_app_state(pool)?;
+        let maintenance = Maintenance::default();
+
+        let scheduled = maintenance.running[MaintenanceTask::LinkCleanup as usize]
+            .lock()
+            .await;
+        assert!(
+            maintenance
+                .try_run(MaintenanceTask::LinkCleanup, &app)
+                .await
+                .is_none()
+        );
+
+        // Other tasks are unaffected
+        let summary = maintenance
+            .try_run(MaintenanceTask::SessionSweep, &app)
+            .await
+            .expect("Session sweep is not running")?;
+        assert_eq!(summary.task, "session_sweep");
+
+        drop(scheduled);
+        let summary = maintenance
+            .try_run(MaintenanceTask::LinkCleanup, &app)
+            .await
+            .expect("Link cleanup finished")?;
+        assert_eq!(summary.rows, 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn names_round_trip() {
+        for task in MaintenanceTask::ALL {
+            assert_eq!(MaintenanceTask::from_name(task.name()), Some(task));
+        }
+        assert_eq!(MaintenanceTask::from_name("diag"), None);
+    }
+}
@@ -1,3 +1,5 @@
 pub mod diag;
 pub mod link_cleanup;
 pub mod link_metrics;
+pub mod maintenance;
+pub mod session_sweep;
@@ -0,0 +1,15 @@
+use anyhow::Result;
+
+use crate::api::Sessions;
+
+/// Returns the number of removed sessions
+pub async fn session_sweep_task(sessions: Sessions) -> Result<u64> {
+    let removed = sessions
+        .sweep_expired()
+        .await
+        .map_err(|_| anyhow::anyhow!("Failed to remove expired sessions"))?;
+    if removed > 0 {
+        tracing::info!("Removed {removed} expired sessions");
+    }
+    Ok(removed)
+}
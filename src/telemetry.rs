@@ -0,0 +1,95 @@
+use anyhow::Result;
+use tracing_subscriber::{filter::LevelFilter, layer::SubscriberExt, util::SubscriberInitExt};
+
+use crate::config::{LogFormat, Settings};
+
+/// Keeps span exporting alive, flushes remaining spans when dropped
+pub struct TelemetryGuard {
+    #[cfg(feature = "otel")]
+    provider: Option<opentelemetry_sdk::trace::SdkTracerProvider>,
+}
+
+impl Drop for TelemetryGuard {
+    fn drop(&mut self) {
+        #[cfg(feature = "otel")]
+        if let Some(provider) = self.provider.take() {
+            if let Err(e) = provider.shutdown() {
+                eprintln!("Failed to shut down span exporter: {e}");
+            }
+        }
+    }
+}
+
+/// Install the global tracing subscriber, exporting spans over OTLP if configured
+pub fn init(settings: &Settings) -> Result<TelemetryGuard> {
+    let json = settings.log_format == LogFormat::Json;
+    // Fields of the current span, e.g. the alias, are logged along the event
+    let json_layer = json.then(|| {
+        tracing_subscriber::fmt::layer()
+            .json()
+            .flatten_event(true)
+            .with_current_span(true)
+            .with_span_list(false)
+    });
+    let text_layer = (!json).then(tracing_subscriber::fmt::layer);
+
+    let registry = tracing_subscriber::registry()
+        .with(LevelFilter::INFO)
+        .with(text_layer)
+        .with(json_layer);
+
+    #[cfg(feature = "otel")]
+    {
+        use opentelemetry::trace::TracerProvider;
+
+        let provider = settings
+            .otlp_endpoint
+            .as_deref()
+            .map(otlp_tracer_provider)
+            .transpose()?;
+
+        let otel_layer = provider.as_ref().map(|provider| {
+            tracing_opentelemetry::layer().with_tracer(provider.tracer(env!("CARGO_PKG_NAME")))
+        });
+        registry.with(otel_layer).try_init()?;
+
+        if let Some(endpoint) = &settings.otlp_endpoint {
+            tracing::info!("Exporting spans to {endpoint}");
+        }
+
+        Ok(TelemetryGuard { provider })
+    }
+
+    #[cfg(not(feature = "otel"))]
+    {
+        registry.try_init()?;
+
+        if settings.otlp_endpoint.is_some() {
+            tracing::warn!(
+                "OTLP endpoint is set, but the app was built without the `otel` feature"
+            );
+        }
+
+        Ok(TelemetryGuard {})
+    }
+}
+
+#[cfg(feature = "otel")]
+fn otlp_tracer_provider(endpoint: &str) -> Result<opentelemetry_sdk::trace::SdkTracerProvider> {
+    use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+    use opentelemetry_sdk::{Resource, trace::SdkTracerProvider};
+
+    let exporter = SpanExporter::builder()
+        .with_http()
+        .with_endpoint(endpoint)
+        .build()?;
+
+    Ok(SdkTracerProvider::builder()
+        .with_resource(
+            Resource::builder()
+                .with_service_name(env!("CARGO_PKG_NAME"))
+                .build(),
+        )
+        .with_batch_exporter(exporter)
+        .build())
+}
@@ -1,22 +1,33 @@
 use axum::{
     body::Body,
-    http::{Request, StatusCode, header::LOCATION},
+    http::{
+        Request, StatusCode,
+        header::{
+            ACCEPT_ENCODING, ACCEPT_LANGUAGE, ALLOW, CONTENT_ENCODING, CONTENT_TYPE, COOKIE,
+            LOCATION, REFERER, SET_COOKIE, WWW_AUTHENTICATE,
+        },
+    },
     response::Response,
 };
 use serde::de::DeserializeOwned;
 use serde_json::json;
 use sqlx::PgPool;
 use time::{Duration, OffsetDateTime};
+use tokio::sync::broadcast;
 use tower::ServiceExt;
 
 use axum::Router;
 
 use url_shorten::{
-    api::{
+    api::{self, handlers::UNLOCK_PATH},
+    app::{
         self,
-        handlers::{EXPIRY_DAYS, UNLOCK_PATH},
+        link_cache::{Invalidation, InvalidationBus, LinkCache},
     },
-    app,
+    config::{AppConfig, CacheBusKind, SessionStoreKind},
+    domain::{AccountPasswordPolicy, Alias, LinkPasswordPolicy, PasswordPolicy, UrlPolicy},
+    services,
+    tasks::{self, link_cleanup::CleanupConfig, maintenance::MaintenanceTask},
 };
 
 // Deserialize a Response into T
@@ -27,9 +38,69 @@ async fn json<T: DeserializeOwned>(response: Response) -> T {
     serde_json::from_slice(&bytes).unwrap()
 }
 
+// Days links without an explicit expiry live after their last visit
+fn idle_days() -> i64 {
+    AppConfig::default().link_idle_days.into()
+}
+
 async fn router(pool: PgPool) -> Router {
     let state = app::build_test_app_state(pool).unwrap();
-    api::build_router(state.into())
+    api::build_router(state)
+}
+
+// Register a new user, returning the session cookie
+async fn register(router: &Router, username: &str) -> String {
+    let request_body = Body::from(
+        serde_json::to_vec(&json!({ "username": username, "password": "password123" })).unwrap(),
+    );
+    let request = Request::post("/api/auth/register")
+        .header("content-type", "application/json")
+        .body(request_body)
+        .unwrap();
+    let response = router.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK, "Registration failed");
+
+    let set_cookie = response
+        .headers()
+        .get(SET_COOKIE)
+        .unwrap()
+        .to_str()
+        .unwrap();
+    set_cookie.split(';').next().unwrap().to_string()
+}
+
+// Log in an existing user, returning the session cookie
+async fn login(router: &Router, username: &str) -> String {
+    let request_body = Body::from(
+        serde_json::to_vec(&json!({ "username": username, "password": "password123" })).unwrap(),
+    );
+    let request = Request::post("/api/auth/login")
+        .header("content-type", "application/json")
+        .body(request_body)
+        .unwrap();
+    let response = router.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK, "Login failed");
+
+    let set_cookie = response
+        .headers()
+        .get(SET_COOKIE)
+        .unwrap()
+        .to_str()
+        .unwrap();
+    set_cookie.split(';').next().unwrap().to_string()
+}
+
+// Register a user with admin rights, returning the session cookie
+async fn register_admin(router: &Router, pool: &PgPool, username: &str) -> String {
+    register(router, username).await;
+    sqlx::query!(
+        "UPDATE users_main SET is_admin = true WHERE username = $1",
+        username
+    )
+    .execute(pool)
+    .await
+    .unwrap();
+    login(router, username).await
 }
 
 #[sqlx::test]
@@ -52,8 +123,18 @@ async fn shorten_and_redirect(pool: PgPool) {
         "Request to shorten {TEST_URL} failed"
     );
 
+    let location = response.headers().get(LOCATION).cloned().unwrap();
+
     // Parse the returned alias
-    let api::handlers::ShortenResponse { alias } = json(response).await;
+    let api::handlers::ShortenResponse {
+        alias, short_url, ..
+    } = json(response).await;
+    assert_eq!(short_url, AppConfig::default().short_url(&alias));
+    assert_eq!(
+        location,
+        format!("/r/{alias}").as_str(),
+        "Location header does not match the created alias"
+    );
 
     // Make a GET request to /r/{alias}
     let request_body = Body::empty();
@@ -78,6 +159,254 @@ async fn shorten_and_redirect(pool: PgPool) {
     );
 }
 
+#[sqlx::test]
+async fn permanent_redirect_per_link(pool: PgPool) {
+    const TEST_URL: &str = "https://example.com/stable";
+
+    let router = router(pool).await;
+
+    for (alias, permanent, status) in [
+        ("stable", json!(true), StatusCode::PERMANENT_REDIRECT),
+        ("movable", json!(false), StatusCode::TEMPORARY_REDIRECT),
+    ] {
+        let request = Request::post("/api/shorten")
+            .header("content-type", "application/json")
+            .body(Body::from(
+                json!({ "url": TEST_URL, "name": alias, "permanent": permanent }).to_string(),
+            ))
+            .unwrap();
+        let response = router.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let request = Request::get(format!("/r/{alias}"))
+            .body(Body::empty())
+            .unwrap();
+        let response = router.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), status, "{alias}");
+        assert_eq!(response.headers().get(LOCATION).unwrap(), TEST_URL);
+    }
+
+    // Browsers cache the redirect, so limits could never be enforced
+    for body in [
+        json!({ "url": TEST_URL, "permanent": true, "max_hits": 5 }),
+        json!({ "url": TEST_URL, "permanent": true, "expires_in_days": 7 }),
+    ] {
+        let request = Request::post("/api/shorten")
+            .header("content-type", "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        let response = router.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{body}");
+        let body: serde_json::Value = json(response).await;
+        assert_eq!(body["code"], "permanent_conflict");
+    }
+
+    // Nor can the link be repointed
+    let cookie = register(&router, "keeper").await;
+    let request = Request::post("/api/shorten")
+        .header("content-type", "application/json")
+        .header(COOKIE, &cookie)
+        .body(Body::from(
+            json!({ "url": TEST_URL, "name": "kept", "permanent": true }).to_string(),
+        ))
+        .unwrap();
+    let response = router.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+
+    let request = Request::put("/api/links/kept")
+        .header("content-type", "application/json")
+        .header(COOKIE, &cookie)
+        .body(Body::from(
+            json!({ "url": "https://example.com/other" }).to_string(),
+        ))
+        .unwrap();
+    let response = router.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+    let body: serde_json::Value = json(response).await;
+    assert_eq!(body["code"], "link_permanent");
+}
+
+#[sqlx::test]
+async fn shorten_retry_with_idempotency_key(pool: PgPool) {
+    let router = router(pool).await;
+    let cookie = register(&router, "retrier").await;
+
+    let shorten = |key: &'static str, url: &'static str| {
+        let request = Request::post("/api/shorten")
+            .header("content-type", "application/json")
+            .header(COOKIE, &cookie)
+            .header("idempotency-key", key)
+            .body(Body::from(json!({ "url": url }).to_string()))
+            .unwrap();
+        router.clone().oneshot(request)
+    };
+
+    let response = shorten("retry-1", "https://example.com").await.unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let first: serde_json::Value = json(response).await;
+
+    // The retry gets the same link
+    let response = shorten("retry-1", "https://example.com").await.unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let retried: serde_json::Value = json(response).await;
+    assert_eq!(retried, first);
+
+    let response = shorten("retry-1", "https://example.org").await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+    let response = shorten("retry-2", "https://example.com").await.unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let other: serde_json::Value = json(response).await;
+    assert_ne!(other["alias"], first["alias"]);
+}
+
+#[sqlx::test]
+async fn anonymous_shorten_ignores_idempotency_key(pool: PgPool) {
+    let router = router(pool).await;
+
+    let shorten = || {
+        let request = Request::post("/api/shorten")
+            .header("content-type", "application/json")
+            .header("idempotency-key", "shared-key")
+            .body(Body::from(
+                json!({ "url": "https://example.com" }).to_string(),
+            ))
+            .unwrap();
+        let router = router.clone();
+        async move {
+            let response = router.oneshot(request).await.unwrap();
+            assert_eq!(response.status(), StatusCode::CREATED);
+            json::<serde_json::Value>(response).await
+        }
+    };
+
+    // Anonymous clients sending the same key each get a link and token of their own
+    let first = shorten().await;
+    let second = shorten().await;
+    assert_ne!(second["alias"], first["alias"]);
+    assert!(first["deletion_token"].is_string());
+    assert!(second["deletion_token"].is_string());
+    assert_ne!(second["deletion_token"], first["deletion_token"]);
+}
+
+#[sqlx::test]
+async fn shorten_dedup_reuses_own_link(pool: PgPool) {
+    const TEST_URL: &str = "https://example.com/again";
+
+    let router = router(pool).await;
+    let cookie = register(&router, "repeater").await;
+    let other_cookie = register(&router, "copycat").await;
+
+    let shorten = |cookie: Option<String>, body: serde_json::Value| {
+        let mut request = Request::post("/api/shorten").header(CONTENT_TYPE, "application/json");
+        if let Some(cookie) = cookie {
+            request = request.header(COOKIE, cookie);
+        }
+        let request = request.body(Body::from(body.to_string())).unwrap();
+        let router = router.clone();
+        async move {
+            let response = router.oneshot(request).await.unwrap();
+            assert_eq!(response.status(), StatusCode::CREATED);
+            let body: serde_json::Value = json(response).await;
+            body["alias"].as_str().unwrap().to_string()
+        }
+    };
+
+    let first = shorten(Some(cookie.clone()), json!({ "url": TEST_URL })).await;
+
+    // Duplicates stay the default
+    let duplicate = shorten(Some(cookie.clone()), json!({ "url": TEST_URL })).await;
+    assert_ne!(duplicate, first);
+
+    let reused = shorten(
+        Some(cookie.clone()),
+        json!({ "url": TEST_URL, "dedup": true }),
+    )
+    .await;
+    assert_eq!(reused, first, "The oldest matching link is reused");
+
+    // Links of other users, anonymous ones and links with options aren't reused
+    let other = shorten(
+        Some(other_cookie),
+        json!({ "url": TEST_URL, "dedup": true }),
+    )
+    .await;
+    assert_ne!(other, first);
+    let anonymous = shorten(None, json!({ "url": TEST_URL, "dedup": true })).await;
+    assert_ne!(anonymous, first);
+    let limited = shorten(
+        Some(cookie),
+        json!({ "url": TEST_URL, "dedup": true, "max_hits": 5 }),
+    )
+    .await;
+    assert_ne!(limited, first);
+}
+
+#[sqlx::test]
+async fn concurrent_shorten_unique_aliases(pool: PgPool) {
+    let state = app::build_test_app_state(pool.clone()).unwrap();
+    let router = api::build_router(state.clone());
+
+    let single = (0..32).map(|i| {
+        let body = json!({ "url": format!("https://example.com/{i}") });
+        let request = Request::post("/api/shorten")
+            .header("content-type", "application/json")
+            .body(Body::from(serde_json::to_vec(&body).unwrap()))
+            .unwrap();
+        tokio::spawn(router.clone().oneshot(request))
+    });
+    let batch = (0..4).map(|i| {
+        let urls: Vec<String> = (0..8)
+            .map(|j| format!("https://example.org/{i}/{j}"))
+            .collect();
+        let request = Request::post("/api/shorten/batch")
+            .header("content-type", "application/json")
+            .body(Body::from(
+                serde_json::to_vec(&json!({ "urls": urls })).unwrap(),
+            ))
+            .unwrap();
+        tokio::spawn(router.clone().oneshot(request))
+    });
+
+    for handle in single.chain(batch).collect::<Vec<_>>() {
+        let response = handle.await.unwrap().unwrap();
+        assert!(response.status().is_success());
+    }
+
+    // Every generated alias decodes back to its own row id
+    let rows: Vec<(i64, String)> = sqlx::query_as("SELECT id, alias FROM links_main ORDER BY id")
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+    assert_eq!(rows.len(), 64);
+
+    for (id, alias) in rows {
+        assert_eq!(state.sqids.decode(&alias), [id as u64]);
+    }
+}
+
+#[sqlx::test]
+async fn shorten_ids_follow_creation_order(pool: PgPool) {
+    let state = app::build_test_app_state(pool.clone()).unwrap();
+    let router = api::build_router(state.clone());
+
+    // More links than a reserved block holds
+    let mut ids = Vec::new();
+    for i in 0..40 {
+        let body = json!({ "url": format!("https://example.com/{i}") });
+        let request = Request::post("/api/shorten")
+            .header("content-type", "application/json")
+            .body(Body::from(serde_json::to_vec(&body).unwrap()))
+            .unwrap();
+        let response = router.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let api::handlers::ShortenResponse { alias, .. } = json(response).await;
+        ids.push(state.sqids.decode(&alias)[0]);
+    }
+
+    assert!(ids.is_sorted(), "Ids must increase: {ids:?}");
+}
+
 #[sqlx::test]
 async fn save_named_and_redirect(pool: PgPool) {
     // similar to shorten_and_redirect() but providing "name" in request body
@@ -101,8 +430,14 @@ async fn save_named_and_redirect(pool: PgPool) {
         "Request to shorten {TEST_URL} failed"
     );
 
+    assert_eq!(
+        response.headers().get(LOCATION).unwrap(),
+        &format!("/r/{TEST_ALIAS}"),
+        "Location header does not point at the created link"
+    );
+
     // Parse the returned alias
-    let api::handlers::ShortenResponse { alias } = json(response).await;
+    let api::handlers::ShortenResponse { alias, .. } = json(response).await;
     assert_eq!(alias, TEST_ALIAS, "Response alias does not match request");
 
     // Make a GET request to /r/{alias}
@@ -168,44 +503,153 @@ async fn save_named_already_exists(pool: PgPool) {
 }
 
 #[sqlx::test]
-async fn recently_added_links(pool: PgPool) {
-    const TEST_URL: &str = "https://example.com";
-    const TEST_URL2: &str = "https://example2.com";
-
+async fn save_named_reserved(pool: PgPool) {
     let router = router(pool).await;
 
-    // Make a POST request to /api/shorten (first url)
-    let request_body = Body::from(serde_json::to_vec(&json!({"url": TEST_URL})).unwrap());
-    let request = Request::post("/api/shorten")
-        .header("content-type", "application/json")
-        .body(request_body)
-        .unwrap();
+    for name in ["unlock", "Unlock"] {
+        let request = Request::post("/api/shorten")
+            .header("content-type", "application/json")
+            .body(Body::from(
+                serde_json::to_vec(&json!({ "url": "https://example.com", "name": name })).unwrap(),
+            ))
+            .unwrap();
+        let response = router.clone().oneshot(request).await.unwrap();
 
-    let _response = router.clone().oneshot(request).await.unwrap();
+        assert_eq!(
+            response.status(),
+            StatusCode::BAD_REQUEST,
+            "Reserved alias {name} should be rejected"
+        );
+        let body: serde_json::Value = json(response).await;
+        assert_eq!(body["code"], "alias_reserved");
+        assert_eq!(body["message"], "Chosen link is reserved");
+    }
+}
 
-    //Make a  POST request to api/shorten (second url)
-    let request_body = Body::from(serde_json::to_vec(&json!({"url": TEST_URL2})).unwrap());
-    let request = Request::post("/api/shorten")
-        .header("content-type", "application/json")
-        .body(request_body)
+#[sqlx::test]
+async fn save_named_with_separators(pool: PgPool) {
+    let shorten = |router: Router, name: &'static str| async move {
+        let request = Request::post("/api/shorten")
+            .header("content-type", "application/json")
+            .body(Body::from(
+                serde_json::to_vec(&json!({ "url": "https://example.com", "name": name })).unwrap(),
+            ))
+            .unwrap();
+        router.oneshot(request).await.unwrap()
+    };
+
+    // Off by default
+    let response = shorten(router(pool.clone()).await, "my-cool-link").await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let body: serde_json::Value = json(response).await;
+    assert_eq!(body["code"], "alias_invalid");
+    assert_eq!(body["message"], "Chosen link contains invalid characters");
+
+    let config = AppConfig {
+        alias_separators: true,
+        ..AppConfig::default()
+    };
+    let state = app::build_test_app_state_with_config(pool, config).unwrap();
+    let router = api::build_router(state);
+
+    let response = shorten(router.clone(), "my-cool_link").await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let response = router
+        .clone()
+        .oneshot(Request::get("/r/my-cool_link").body(Body::empty()).unwrap())
+        .await
         .unwrap();
+    assert_eq!(response.status(), StatusCode::TEMPORARY_REDIRECT);
 
-    let _response = router.clone().oneshot(request).await.unwrap();
+    for name in ["-leading", "trailing_", "double--dash"] {
+        let response = shorten(router.clone(), name).await;
+        assert_eq!(
+            response.status(),
+            StatusCode::BAD_REQUEST,
+            "{name} should be rejected"
+        );
+    }
+}
 
-    //Make a GET request to /api/recent
-    let request_body = Body::empty();
-    let request = Request::get("/api/recent").body(request_body).unwrap();
+#[sqlx::test]
+async fn recently_added_links(pool: PgPool) {
+    const TEST_URL: &str = "https://example.com";
+    const TEST_URL2: &str = "https://example2.com";
+    const UNLISTED_URL: &str = "https://example.com/unlisted";
+    const USER_URL: &str = "https://example.com/mine";
 
-    let response = router.oneshot(request).await.unwrap();
+    let router = router(pool).await;
+    let cookie = register(&router, "recent").await;
+
+    let shorten = |body: serde_json::Value, cookie: Option<&str>| {
+        let mut request = Request::post("/api/shorten").header(CONTENT_TYPE, "application/json");
+        if let Some(cookie) = cookie {
+            request = request.header(COOKIE, cookie);
+        }
+        let request = request.body(Body::from(body.to_string())).unwrap();
+        router.clone().oneshot(request)
+    };
+    let recent = |cookie: Option<&str>| {
+        let mut request = Request::get("/api/recent");
+        if let Some(cookie) = cookie {
+            request = request.header(COOKIE, cookie);
+        }
+        let request = request.body(Body::empty()).unwrap();
+        let router = router.clone();
+        async move {
+            let response = router.oneshot(request).await.unwrap();
+            assert_eq!(
+                response.status(),
+                StatusCode::OK,
+                "recently added links request failed"
+            );
+            let links: Vec<serde_json::Value> = json(response).await;
+            for link in &links {
+                assert!(link["created_at"].is_string(), "Missing created_at");
+            }
+            links
+                .into_iter()
+                .map(|link| {
+                    (
+                        link["alias"].as_str().unwrap().to_string(),
+                        link["url"].as_str().unwrap().to_string(),
+                    )
+                })
+                .collect::<Vec<_>>()
+        }
+    };
+
+    let mut aliases = Vec::new();
+    for body in [
+        json!({ "url": TEST_URL, "public": true }),
+        json!({ "url": UNLISTED_URL }),
+        json!({ "url": TEST_URL2, "public": true }),
+    ] {
+        let response = shorten(body, None).await.unwrap();
+        let api::handlers::ShortenResponse { alias, .. } = json(response).await;
+        aliases.push(alias);
+    }
+    let response = shorten(json!({ "url": USER_URL }), Some(&cookie))
+        .await
+        .unwrap();
+    let api::handlers::ShortenResponse {
+        alias: user_alias, ..
+    } = json(response).await;
 
+    // Only links shortened as public are listed for everyone
     assert_eq!(
-        response.status(),
-        StatusCode::OK,
-        "recently added links request failed"
+        recent(None).await,
+        vec![
+            (aliases[2].clone(), TEST_URL2.to_string()),
+            (aliases[0].clone(), TEST_URL.to_string()),
+        ]
     );
 
-    let links: Vec<String> = json(response).await;
-    assert_eq!(links, vec![TEST_URL2, TEST_URL]);
+    // Users see their own links instead
+    assert_eq!(
+        recent(Some(&cookie)).await,
+        vec![(user_alias, USER_URL.to_string())]
+    );
 }
 
 #[sqlx::test]
@@ -215,7 +659,7 @@ async fn redirect_expired_link(pool: PgPool) {
 
     let expired_on = OffsetDateTime::now_utc()
         .date()
-        .saturating_sub(Duration::days(EXPIRY_DAYS + 1));
+        .saturating_sub(Duration::days(idle_days() + 1));
 
     sqlx::query!(
         r#"
@@ -245,6 +689,24 @@ async fn redirect_expired_link(pool: PgPool) {
     );
 }
 
+#[sqlx::test]
+async fn redirect_overlong_alias_not_found(pool: PgPool) {
+    let router = router(pool).await;
+
+    let alias = "a".repeat(200);
+    let request = Request::get(format!("/r/{alias}"))
+        .body(Body::empty())
+        .unwrap();
+
+    let response = router.oneshot(request).await.unwrap();
+
+    assert_eq!(
+        response.status(),
+        StatusCode::NOT_FOUND,
+        "Expected over-long alias to return 404 Not Found"
+    );
+}
+
 #[sqlx::test]
 async fn password_protected_link_unlock(pool: PgPool) {
     const TEST_URL: &str = "https://example.com";
@@ -304,6 +766,24 @@ async fn password_protected_link_unlock(pool: PgPool) {
         StatusCode::UNAUTHORIZED,
         "Expected 401 when provided with wrong password"
     );
+    assert_eq!(
+        response.headers().get(WWW_AUTHENTICATE).unwrap(),
+        "LinkPassword"
+    );
+    let body: serde_json::Value = json(response).await;
+    assert_eq!(body["code"], "wrong_password");
+    assert_eq!(body["message"], "Wrong password");
+
+    // An empty password asks for one rather than failing it
+    let request = Request::post(format!("/api/unlock/{TEST_ALIAS}"))
+        .header("content-type", "application/json")
+        .body(Body::from(json!({ "password": "" }).to_string()))
+        .unwrap();
+
+    let response = router.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let body: serde_json::Value = json(response).await;
+    assert_eq!(body["code"], "password_required");
 
     // 4. POST unlock with correct password
     let request_body =
@@ -332,3 +812,2864 @@ async fn password_protected_link_unlock(pool: PgPool) {
         "Expected unlock endpoint to return target url"
     );
 }
+
+#[sqlx::test]
+async fn protected_link_hit_counted_on_unlock(pool: PgPool) {
+    const TEST_ALIAS: &str = "guarded";
+    const TEST_PASSWORD: &str = "password123";
+
+    let state = app::build_test_app_state(pool).unwrap();
+    let router = api::build_router(state.clone());
+
+    let request = Request::post("/api/shorten")
+        .header("content-type", "application/json")
+        .body(Body::from(
+            json!({
+                "url": "https://example.com",
+                "name": TEST_ALIAS,
+                "password": TEST_PASSWORD
+            })
+            .to_string(),
+        ))
+        .unwrap();
+    let response = router.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+
+    let unlock = |password: &str| {
+        let request = Request::post(format!("/api/unlock/{TEST_ALIAS}"))
+            .header("content-type", "application/json")
+            .body(Body::from(json!({ "password": password }).to_string()))
+            .unwrap();
+        let router = router.clone();
+        async move { router.oneshot(request).await.unwrap().status() }
+    };
+    let recorded_hits = || {
+        let map = state.metrics.swap_map();
+        map.iter().map(|entry| entry.value().hits()).sum::<i64>()
+    };
+
+    // Neither the unlock prompt nor a password in the query resolve the link
+    for path in [
+        format!("/r/{TEST_ALIAS}"),
+        format!("/r/{TEST_ALIAS}?password={TEST_PASSWORD}"),
+    ] {
+        let request = Request::get(&path).body(Body::empty()).unwrap();
+        let response = router.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::TEMPORARY_REDIRECT);
+        let location = response.headers().get(LOCATION).unwrap().to_str().unwrap();
+        assert_eq!(location, format!("/{UNLOCK_PATH}/{TEST_ALIAS}"), "{path}");
+    }
+    assert_eq!(recorded_hits(), 0);
+
+    assert_eq!(unlock("qwerty").await, StatusCode::UNAUTHORIZED);
+    assert_eq!(recorded_hits(), 0);
+
+    assert_eq!(unlock(TEST_PASSWORD).await, StatusCode::OK);
+    assert_eq!(recorded_hits(), 1);
+}
+
+#[sqlx::test]
+async fn unlock_attempts_limited_per_link(pool: PgPool) {
+    const TEST_PASSWORD: &str = "password123";
+
+    let config = AppConfig {
+        unlock_max_failures: 2,
+        unlock_window_s: 60,
+        ..AppConfig::default()
+    };
+    let state = app::build_test_app_state_with_config(pool, config).unwrap();
+    let router = api::build_router(state);
+
+    for alias in ["locked", "unlocked"] {
+        let request = Request::post("/api/shorten")
+            .header("content-type", "application/json")
+            .body(Body::from(
+                json!({
+                    "url": "https://example.com",
+                    "name": alias,
+                    "password": TEST_PASSWORD
+                })
+                .to_string(),
+            ))
+            .unwrap();
+        let response = router.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+    }
+
+    let unlock = |alias: &str, password: &str| {
+        let request = Request::post(format!("/api/unlock/{alias}"))
+            .header("content-type", "application/json")
+            .body(Body::from(json!({ "password": password }).to_string()))
+            .unwrap();
+        router.clone().oneshot(request)
+    };
+
+    // A success resets the count
+    assert_eq!(
+        unlock("unlocked", "qwerty").await.unwrap().status(),
+        StatusCode::UNAUTHORIZED
+    );
+    assert_eq!(
+        unlock("unlocked", TEST_PASSWORD).await.unwrap().status(),
+        StatusCode::OK
+    );
+    assert_eq!(
+        unlock("unlocked", "qwerty").await.unwrap().status(),
+        StatusCode::UNAUTHORIZED
+    );
+
+    for _ in 0..2 {
+        assert_eq!(
+            unlock("locked", "qwerty").await.unwrap().status(),
+            StatusCode::UNAUTHORIZED
+        );
+    }
+    // Even the right password is refused until the window passes
+    let response = unlock("locked", TEST_PASSWORD).await.unwrap();
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    let retry_after: u64 = response.headers()["retry-after"]
+        .to_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!((1..=60).contains(&retry_after));
+
+    // Other links take attempts
+    assert_eq!(
+        unlock("unlocked", TEST_PASSWORD).await.unwrap().status(),
+        StatusCode::OK
+    );
+}
+
+#[sqlx::test]
+async fn wrong_method_not_allowed(pool: PgPool) {
+    let router = router(pool).await;
+
+    let request = Request::get("/api/shorten").body(Body::empty()).unwrap();
+    let response = router.oneshot(request).await.unwrap();
+
+    assert_eq!(
+        response.status(),
+        StatusCode::METHOD_NOT_ALLOWED,
+        "Expected 405 for GET /api/shorten"
+    );
+    assert_eq!(
+        response.headers().get(ALLOW).unwrap(),
+        "POST",
+        "Allow header should list permitted methods"
+    );
+
+    let body: serde_json::Value = json(response).await;
+    assert_eq!(body["code"], "method_not_allowed");
+    assert_eq!(body["message"], "Method not allowed");
+}
+
+#[sqlx::test]
+async fn separate_redirect_and_api_routers(pool: PgPool) {
+    const TEST_URL: &str = "https://example.com";
+
+    let state = app::build_test_app_state(pool).unwrap();
+    let api_router = api::build_api_router(state.clone());
+    let redirect_router = api::build_redirect_router(state);
+
+    let shorten_request = || {
+        let request_body = Body::from(serde_json::to_vec(&json!({ "url": TEST_URL })).unwrap());
+        Request::post("/api/shorten")
+            .header("content-type", "application/json")
+            .body(request_body)
+            .unwrap()
+    };
+
+    // The redirect router does not serve the API
+    let response = redirect_router
+        .clone()
+        .oneshot(shorten_request())
+        .await
+        .unwrap();
+    assert_eq!(
+        response.status(),
+        StatusCode::NOT_FOUND,
+        "Redirect router should not serve /api/shorten"
+    );
+
+    let response = api_router.clone().oneshot(shorten_request()).await.unwrap();
+    assert_eq!(
+        response.status(),
+        StatusCode::CREATED,
+        "Request to shorten {TEST_URL} failed"
+    );
+    let api::handlers::ShortenResponse { alias, .. } = json(response).await;
+
+    // The API router does not serve redirects
+    let request = Request::get(format!("/r/{alias}"))
+        .body(Body::empty())
+        .unwrap();
+    let response = api_router.oneshot(request).await.unwrap();
+    assert_eq!(
+        response.status(),
+        StatusCode::NOT_FOUND,
+        "API router should not serve /r/{alias}"
+    );
+
+    let request = Request::get(format!("/r/{alias}"))
+        .body(Body::empty())
+        .unwrap();
+    let response = redirect_router.oneshot(request).await.unwrap();
+    assert_eq!(
+        response.status(),
+        StatusCode::TEMPORARY_REDIRECT,
+        "Redirect request to /r/{alias} failed"
+    );
+    assert_eq!(response.headers().get(LOCATION).unwrap(), TEST_URL);
+}
+
+#[sqlx::test]
+async fn protected_link_with_separate_redirect_port(pool: PgPool) {
+    const WEB_URL: &str = "https://app.sho.rt";
+    const TEST_PASSWORD: &str = "password123";
+
+    let config = AppConfig {
+        base_url: "https://sho.rt".to_string(),
+        web_base_url: Some(WEB_URL.to_string()),
+        ..AppConfig::default()
+    };
+    let state = app::build_test_app_state_with_config(pool, config).unwrap();
+    let api_router = api::build_api_router(state.clone());
+    let redirect_router = api::build_redirect_router(state);
+
+    let request = Request::post("/api/shorten")
+        .header("content-type", "application/json")
+        .body(Body::from(
+            json!({ "url": "https://example.com", "password": TEST_PASSWORD }).to_string(),
+        ))
+        .unwrap();
+    let response = api_router.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let api::handlers::ShortenResponse { alias, .. } = json(response).await;
+
+    // The unlock view is served next to the API, not on the redirect port
+    let request = Request::get(format!("/r/{alias}"))
+        .body(Body::empty())
+        .unwrap();
+    let response = redirect_router.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::TEMPORARY_REDIRECT);
+    assert_eq!(
+        response.headers()[LOCATION],
+        format!("{WEB_URL}/{UNLOCK_PATH}/{alias}")
+    );
+
+    // The API port answers redirect paths itself, rather than with the web UI
+    let request = Request::get(format!("/r/{alias}"))
+        .body(Body::empty())
+        .unwrap();
+    let response = api_router.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    let body: serde_json::Value = json(response).await;
+    assert_eq!(body["code"], "not_found");
+
+    let request = Request::post(format!("/api/unlock/{alias}"))
+        .header("content-type", "application/json")
+        .body(Body::from(json!({ "password": TEST_PASSWORD }).to_string()))
+        .unwrap();
+    let response = api_router.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body: serde_json::Value = json(response).await;
+    assert_eq!(body["url"], "https://example.com");
+}
+
+#[sqlx::test]
+async fn touch_renews_expiry(pool: PgPool) {
+    const TEST_URL: &str = "https://example.com/";
+    const ALIAS: &str = "touched";
+
+    let router = router(pool.clone()).await;
+    let cookie = register(&router, "toucher").await;
+
+    let expired_on = OffsetDateTime::now_utc()
+        .date()
+        .saturating_sub(Duration::days(idle_days() + 1));
+
+    sqlx::query!(
+        r#"
+        INSERT INTO links_main (alias, url, last_seen, user_id)
+        SELECT $1, $2, $3, id FROM users_main WHERE username = 'toucher'
+        "#,
+        ALIAS,
+        TEST_URL,
+        expired_on
+    )
+    .execute(&pool)
+    .await
+    .unwrap();
+
+    // Link is expired (and now cached as such)
+    let request = Request::get(format!("/r/{ALIAS}"))
+        .body(Body::empty())
+        .unwrap();
+    let response = router.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::GONE);
+
+    // Anonymous touch is rejected
+    let request = Request::post(format!("/api/links/{ALIAS}/touch"))
+        .body(Body::empty())
+        .unwrap();
+    let response = router.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    // Other users can't touch the link
+    let other_cookie = register(&router, "stranger").await;
+    let request = Request::post(format!("/api/links/{ALIAS}/touch"))
+        .header(COOKIE, &other_cookie)
+        .body(Body::empty())
+        .unwrap();
+    let response = router.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    // Owner touches the link
+    let request = Request::post(format!("/api/links/{ALIAS}/touch"))
+        .header(COOKIE, &cookie)
+        .body(Body::empty())
+        .unwrap();
+    let response = router.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT, "Touch failed");
+
+    let request = Request::get(format!("/r/{ALIAS}"))
+        .body(Body::empty())
+        .unwrap();
+    let response = router.oneshot(request).await.unwrap();
+    assert_eq!(
+        response.status(),
+        StatusCode::TEMPORARY_REDIRECT,
+        "Touched link should redirect instead of 410"
+    );
+}
+
+#[sqlx::test]
+async fn rotate_alias(pool: PgPool) {
+    const TEST_URL: &str = "https://example.com/";
+
+    let router = router(pool).await;
+    let cookie = register(&router, "rotator").await;
+
+    let request = Request::post("/api/shorten")
+        .header("content-type", "application/json")
+        .header(COOKIE, &cookie)
+        .body(Body::from(
+            serde_json::to_vec(&json!({ "url": TEST_URL })).unwrap(),
+        ))
+        .unwrap();
+    let response = router.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let api::handlers::ShortenResponse {
+        alias: old_alias, ..
+    } = json(response).await;
+
+    // Warm the cache with the old alias
+    let request = Request::get(format!("/r/{old_alias}"))
+        .body(Body::empty())
+        .unwrap();
+    let response = router.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::TEMPORARY_REDIRECT);
+
+    // Other users can't rotate the link
+    let other_cookie = register(&router, "stranger").await;
+    let request = Request::post(format!("/api/links/{old_alias}/rotate"))
+        .header(COOKIE, &other_cookie)
+        .body(Body::empty())
+        .unwrap();
+    let response = router.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    let request = Request::post(format!("/api/links/{old_alias}/rotate"))
+        .header(COOKIE, &cookie)
+        .body(Body::empty())
+        .unwrap();
+    let response = router.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK, "Rotate failed");
+    let body: serde_json::Value = json(response).await;
+    let new_alias = body["alias"].as_str().unwrap().to_string();
+    assert_ne!(new_alias, old_alias);
+
+    let request = Request::get(format!("/r/{old_alias}"))
+        .body(Body::empty())
+        .unwrap();
+    let response = router.clone().oneshot(request).await.unwrap();
+    assert_eq!(
+        response.status(),
+        StatusCode::GONE,
+        "Old alias should be retired"
+    );
+
+    let request = Request::get(format!("/r/{new_alias}"))
+        .body(Body::empty())
+        .unwrap();
+    let response = router.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::TEMPORARY_REDIRECT);
+    assert_eq!(response.headers().get(LOCATION).unwrap(), TEST_URL);
+}
+
+#[sqlx::test]
+async fn listing_is_compressed(pool: PgPool) {
+    let router = router(pool.clone()).await;
+    let cookie = register(&router, "collector").await;
+
+    sqlx::query!(
+        r#"
+        INSERT INTO links_main (alias, url, user_id)
+        SELECT 'link' || n, 'https://example.com/' || n, u.id
+        FROM generate_series(1, 100) AS n, users_main u
+        WHERE u.username = 'collector'
+        "#
+    )
+    .execute(&pool)
+    .await
+    .unwrap();
+
+    let request = Request::get("/api/user/list")
+        .header(COOKIE, &cookie)
+        .header(ACCEPT_ENCODING, "gzip")
+        .body(Body::empty())
+        .unwrap();
+    let response = router.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.headers().get(CONTENT_ENCODING).unwrap(),
+        "gzip",
+        "Listing should be gzip compressed"
+    );
+
+    // Redirects are left alone
+    let request = Request::get("/r/link1")
+        .header(ACCEPT_ENCODING, "gzip")
+        .body(Body::empty())
+        .unwrap();
+    let response = router.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::TEMPORARY_REDIRECT);
+    assert!(response.headers().get(CONTENT_ENCODING).is_none());
+}
+
+#[sqlx::test]
+async fn list_links_paginated(pool: PgPool) {
+    let router = router(pool.clone()).await;
+    let cookie = register(&router, "collector").await;
+
+    // Inserted together, so all share created_at and pages are ordered by id
+    sqlx::query(
+        r#"
+        INSERT INTO links_main (alias, url, user_id)
+        SELECT 'link' || n, 'https://example.com/' || n, u.id
+        FROM generate_series(1, 120) AS n, users_main u
+        WHERE u.username = 'collector'
+        "#,
+    )
+    .execute(&pool)
+    .await
+    .unwrap();
+
+    let list = |query: String| {
+        let request = Request::get(format!("/api/user/list{query}"))
+            .header(COOKIE, &cookie)
+            .body(Body::empty())
+            .unwrap();
+        router.clone().oneshot(request)
+    };
+
+    let mut aliases = Vec::new();
+    let mut query = String::new();
+    loop {
+        let response = list(query).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let page: serde_json::Value = json(response).await;
+        let links = page["links"].as_array().unwrap();
+        assert!(links.len() <= 50, "Default page size is 50");
+        aliases.extend(
+            links
+                .iter()
+                .map(|link| link["alias"].as_str().unwrap().to_string()),
+        );
+
+        match page["next_cursor"].as_str() {
+            Some(cursor) => query = format!("?cursor={cursor}"),
+            None => break,
+        }
+    }
+    let expected: Vec<String> = (1..=120).rev().map(|n| format!("link{n}")).collect();
+    assert_eq!(aliases, expected, "Every link once, newest first");
+
+    let response = list("?limit=1000".into()).await.unwrap();
+    let page: serde_json::Value = json(response).await;
+    assert_eq!(page["links"].as_array().unwrap().len(), 120);
+    assert!(page["next_cursor"].is_null());
+
+    let link = &page["links"][0];
+    assert!(link["created_at"].is_string());
+    let today = OffsetDateTime::now_utc().date().to_string();
+    assert_eq!(link["last_seen"], today.as_str());
+    assert_eq!(link["hit_count"], 0);
+
+    let response = list("?cursor=garbage".into()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[sqlx::test]
+async fn search_user_links(pool: PgPool) {
+    let router = router(pool.clone()).await;
+    let cookie = register(&router, "searcher").await;
+    register(&router, "stranger").await;
+
+    for (username, alias, url) in [
+        ("searcher", "docs", "https://example.com/guide"),
+        ("searcher", "guide", "https://example.com/start"),
+        ("searcher", "sale", "https://example.org/100%"),
+        ("searcher", "other", "https://example.net/x"),
+        ("stranger", "guides", "https://example.com/guide"),
+    ] {
+        sqlx::query(
+            "INSERT INTO links_main (alias, url, user_id) SELECT $1, $2, id FROM users_main WHERE username = $3",
+        )
+        .bind(alias)
+        .bind(url)
+        .bind(username)
+        .execute(&pool)
+        .await
+        .unwrap();
+    }
+
+    let search = |query: &str| {
+        let request = Request::get(format!("/api/links?{query}"))
+            .header(COOKIE, &cookie)
+            .body(Body::empty())
+            .unwrap();
+        let router = router.clone();
+        async move {
+            let response = router.oneshot(request).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            let page: serde_json::Value = json(response).await;
+            let mut aliases: Vec<String> = page["links"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|link| link["alias"].as_str().unwrap().to_string())
+                .collect();
+            aliases.sort();
+            (aliases, page["next_cursor"].as_str().map(str::to_string))
+        }
+    };
+
+    assert_eq!(search("q=GUI").await.0, ["docs", "guide"]);
+    assert_eq!(search("q=ide").await.0, ["docs"], "Aliases match by prefix");
+    assert_eq!(
+        search("q=%25").await.0,
+        ["sale"],
+        "Wildcards match literally"
+    );
+    assert!(search("q=nothing").await.0.is_empty());
+
+    let (first, cursor) = search("q=gui&limit=1").await;
+    let cursor = cursor.expect("A second page should follow");
+    let (second, cursor) = search(&format!("q=gui&limit=1&cursor={cursor}")).await;
+    assert!(cursor.is_none());
+    assert_ne!(first, second);
+}
+
+#[sqlx::test]
+async fn alias_filter_short_circuits_missing(pool: PgPool) {
+    const TEST_URL: &str = "https://example.com";
+    const ALIAS: &str = "existing";
+    const MISSING_ALIAS: &str = "missing";
+
+    sqlx::query!(
+        "INSERT INTO links_main (alias, url) VALUES ($1, $2)",
+        ALIAS,
+        TEST_URL
+    )
+    .execute(&pool)
+    .await
+    .unwrap();
+
+    let state = app::build_test_app_state(pool.clone()).unwrap();
+    let loaded = services::populate_alias_filter(&state.alias_filter, &pool)
+        .await
+        .unwrap();
+    assert_eq!(loaded, 1);
+
+    let router = api::build_router(state.clone());
+
+    let request = Request::get(format!("/r/{MISSING_ALIAS}"))
+        .body(Body::empty())
+        .unwrap();
+    let response = router.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    // Rejected by the filter before reaching the cache or DB
+    let (cache_hits, cache_misses, filter_rejects, _) = state.diag.snapshot();
+    assert_eq!((cache_hits, cache_misses, filter_rejects), (0, 0, 1));
+
+    let request = Request::get(format!("/r/{ALIAS}"))
+        .body(Body::empty())
+        .unwrap();
+    let response = router.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::TEMPORARY_REDIRECT);
+
+    // Links created after population pass the filter
+    let request_body = Body::from(serde_json::to_vec(&json!({ "url": TEST_URL })).unwrap());
+    let request = Request::post("/api/shorten")
+        .header("content-type", "application/json")
+        .body(request_body)
+        .unwrap();
+    let response = router.clone().oneshot(request).await.unwrap();
+    let api::handlers::ShortenResponse { alias, .. } = json(response).await;
+
+    let request = Request::get(format!("/r/{alias}"))
+        .body(Body::empty())
+        .unwrap();
+    let response = router.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::TEMPORARY_REDIRECT);
+    assert_eq!(state.diag.snapshot().2, 1);
+}
+
+#[sqlx::test]
+async fn sqids_high_water_warns(pool: PgPool) {
+    use std::sync::{Arc, Mutex};
+
+    // Collects formatted log lines
+    #[derive(Clone, Default)]
+    struct Capture(Arc<Mutex<Vec<u8>>>);
+
+    impl std::io::Write for Capture {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let capture = Capture::default();
+    let writer = capture.clone();
+    let subscriber = tracing_subscriber::fmt()
+        .with_writer(move || writer.clone())
+        .with_ansi(false)
+        .finish();
+    let _guard = tracing::subscriber::set_default(subscriber);
+
+    let config = AppConfig {
+        sqids_high_water: f64::MIN_POSITIVE,
+        ..AppConfig::default()
+    };
+    let state = app::build_test_app_state_with_config(pool, config).unwrap();
+    let router = api::build_router(state.clone());
+
+    for _ in 0..2 {
+        let request = Request::post("/api/shorten")
+            .header("content-type", "application/json")
+            .body(Body::from(
+                serde_json::to_vec(&json!({ "url": "https://example.com" })).unwrap(),
+            ))
+            .unwrap();
+        let response = router.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+    }
+
+    assert_eq!(state.diag.snapshot().3, 2, "Both links are past the mark");
+
+    let logs = String::from_utf8(capture.0.lock().unwrap().clone()).unwrap();
+    assert_eq!(
+        logs.matches("outgrow the minimum length").count(),
+        1,
+        "Expected a single warning, got: {logs}"
+    );
+}
+
+#[sqlx::test]
+async fn password_policies_are_independent(pool: PgPool) {
+    let config = AppConfig {
+        account_password_policy: AccountPasswordPolicy(PasswordPolicy {
+            min_length: 12,
+            max_length: 64,
+            require_letter: true,
+            require_digit: true,
+        }),
+        link_password_policy: LinkPasswordPolicy(PasswordPolicy {
+            min_length: 4,
+            max_length: 16,
+            require_letter: false,
+            require_digit: true,
+        }),
+        ..AppConfig::default()
+    };
+    let state = app::build_test_app_state_with_config(pool, config).unwrap();
+    let router = api::build_router(state);
+
+    let post = |path: &str, body: serde_json::Value| {
+        Request::post(path)
+            .header("content-type", "application/json")
+            .body(Body::from(serde_json::to_vec(&body).unwrap()))
+            .unwrap()
+    };
+
+    // Account policy: 12 characters with letters and digits
+    let cases = [
+        ("password123", StatusCode::BAD_REQUEST),
+        ("passwordpassword", StatusCode::BAD_REQUEST),
+        ("password1234", StatusCode::OK),
+    ];
+    for (password, status) in cases {
+        let request = post(
+            "/api/auth/register",
+            json!({ "username": "policyuser", "password": password }),
+        );
+        let response = router.clone().oneshot(request).await.unwrap();
+        assert_eq!(
+            response.status(),
+            status,
+            "Unexpected status registering with {password}"
+        );
+    }
+
+    // Link policy: 4 characters with a digit, shorter than the account minimum
+    let cases = [
+        ("abcd", StatusCode::BAD_REQUEST),
+        ("ab1", StatusCode::BAD_REQUEST),
+        ("1234", StatusCode::CREATED),
+    ];
+    for (password, status) in cases {
+        let request = post(
+            "/api/shorten",
+            json!({ "url": "https://example.com", "password": password }),
+        );
+        let response = router.clone().oneshot(request).await.unwrap();
+        assert_eq!(
+            response.status(),
+            status,
+            "Unexpected status shortening with {password}"
+        );
+    }
+}
+
+#[cfg(feature = "otel")]
+#[sqlx::test]
+async fn redirect_emits_otel_span(pool: PgPool) {
+    use opentelemetry::{KeyValue, trace::TracerProvider};
+    use opentelemetry_sdk::trace::{InMemorySpanExporter, SdkTracerProvider};
+    use tracing_subscriber::layer::SubscriberExt;
+
+    const ALIAS: &str = "traced";
+
+    let exporter = InMemorySpanExporter::default();
+    let provider = SdkTracerProvider::builder()
+        .with_simple_exporter(exporter.clone())
+        .build();
+    let subscriber = tracing_subscriber::registry()
+        .with(tracing_opentelemetry::layer().with_tracer(provider.tracer("test")));
+    let _guard = tracing::subscriber::set_default(subscriber);
+
+    sqlx::query!(
+        "INSERT INTO links_main (alias, url) VALUES ($1, $2)",
+        ALIAS,
+        "https://example.com"
+    )
+    .execute(&pool)
+    .await
+    .unwrap();
+
+    let router = router(pool).await;
+    let request = Request::get(format!("/r/{ALIAS}"))
+        .body(Body::empty())
+        .unwrap();
+    let response = router.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::TEMPORARY_REDIRECT);
+
+    provider.force_flush().unwrap();
+    let spans = exporter.get_finished_spans().unwrap();
+    let span = spans
+        .iter()
+        .find(|span| span.name == "services::query_url_by_alias")
+        .expect("No span emitted for services::query_url_by_alias");
+    assert!(
+        span.attributes.contains(&KeyValue::new("alias", ALIAS)),
+        "Span is missing the alias attribute"
+    );
+}
+
+#[sqlx::test]
+async fn private_link_hidden_from_recent(pool: PgPool) {
+    const PUBLIC_URL: &str = "https://example.com/public";
+    const PRIVATE_URL: &str = "https://example.com/private";
+
+    let router = router(pool).await;
+
+    let mut aliases = Vec::new();
+    for body in [
+        json!({ "url": PUBLIC_URL, "public": true }),
+        json!({ "url": PRIVATE_URL }),
+    ] {
+        let request = Request::post("/api/shorten")
+            .header("content-type", "application/json")
+            .body(Body::from(serde_json::to_vec(&body).unwrap()))
+            .unwrap();
+        let response = router.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let api::handlers::ShortenResponse { alias, .. } = json(response).await;
+        aliases.push(alias);
+    }
+
+    let request = Request::get("/api/recent").body(Body::empty()).unwrap();
+    let response = router.clone().oneshot(request).await.unwrap();
+    let links: Vec<serde_json::Value> = json(response).await;
+    assert_eq!(
+        links
+            .iter()
+            .map(|link| link["alias"].as_str().unwrap())
+            .collect::<Vec<_>>(),
+        vec![aliases[0].as_str()],
+        "Private link should be omitted"
+    );
+
+    // Private links still redirect
+    let request = Request::get(format!("/r/{}", aliases[1]))
+        .body(Body::empty())
+        .unwrap();
+    let response = router.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::TEMPORARY_REDIRECT);
+    assert_eq!(response.headers().get(LOCATION).unwrap(), PRIVATE_URL);
+}
+
+#[sqlx::test]
+async fn admin_list_users(pool: PgPool) {
+    let router = router(pool.clone()).await;
+
+    let user_cookie = register(&router, "alice1").await;
+    register(&router, "alice2").await;
+    register(&router, "alice3").await;
+    register(&router, "bob01").await;
+    let admin_cookie = register_admin(&router, &pool, "admin").await;
+
+    for url in ["https://example.com/1", "https://example.com/2"] {
+        let request = Request::post("/api/shorten")
+            .header("content-type", "application/json")
+            .header(COOKIE, &user_cookie)
+            .body(Body::from(
+                serde_json::to_vec(&json!({ "url": url })).unwrap(),
+            ))
+            .unwrap();
+        let response = router.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+    }
+
+    let list = |query: String, cookie: String| {
+        let router = router.clone();
+        async move {
+            let request = Request::get(format!("/api/admin/users{query}"))
+                .header(COOKIE, cookie)
+                .body(Body::empty())
+                .unwrap();
+            router.oneshot(request).await.unwrap()
+        }
+    };
+
+    // Regular users are forbidden
+    let response = list(String::new(), user_cookie.clone()).await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    let body: serde_json::Value = json(response).await;
+    assert_eq!(body["code"], "forbidden");
+
+    // Paginate through everyone, two at a time
+    let mut usernames = Vec::new();
+    let mut query = "?limit=2".to_string();
+    loop {
+        let response = list(query, admin_cookie.clone()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let page: serde_json::Value = json(response).await;
+
+        for user in page["users"].as_array().unwrap() {
+            assert!(user.get("password_hash").is_none());
+            usernames.push(user["username"].as_str().unwrap().to_string());
+            if user["username"] == "alice1" {
+                assert_eq!(user["link_count"], 2);
+            }
+        }
+
+        match page["next_cursor"].as_i64() {
+            Some(cursor) => query = format!("?limit=2&cursor={cursor}"),
+            None => break,
+        }
+    }
+    assert_eq!(usernames, ["alice1", "alice2", "alice3", "bob01", "admin"]);
+
+    // Filter by username prefix
+    let response = list("?prefix=alice".to_string(), admin_cookie.clone()).await;
+    let page: serde_json::Value = json(response).await;
+    let usernames: Vec<&str> = page["users"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|user| user["username"].as_str().unwrap())
+        .collect();
+    assert_eq!(usernames, ["alice1", "alice2", "alice3"]);
+    assert!(page["next_cursor"].is_null());
+
+    // Wildcards in the prefix are matched literally
+    let response = list("?prefix=%25".to_string(), admin_cookie).await;
+    let page: serde_json::Value = json(response).await;
+    assert!(page["users"].as_array().unwrap().is_empty());
+}
+
+#[sqlx::test]
+async fn admin_import_user(pool: PgPool) {
+    const PASSWORD: &str = "migrated123";
+
+    let router = router(pool.clone()).await;
+    let user_cookie = register(&router, "regular").await;
+    let admin_cookie = register_admin(&router, &pool, "admin").await;
+
+    let import = |body: serde_json::Value, cookie: String| {
+        let router = router.clone();
+        async move {
+            let request = Request::post("/api/admin/users/import")
+                .header("content-type", "application/json")
+                .header(COOKIE, cookie)
+                .body(Body::from(serde_json::to_vec(&body).unwrap()))
+                .unwrap();
+            router.oneshot(request).await.unwrap()
+        }
+    };
+
+    let password_hash = bcrypt::hash(PASSWORD, 4).unwrap();
+    let body = json!({ "username": "migrated", "password_hash": password_hash });
+
+    // Regular users are forbidden
+    let response = import(body.clone(), user_cookie).await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    // Unparseable hashes are rejected
+    let response = import(
+        json!({ "username": "plaintext", "password_hash": PASSWORD }),
+        admin_cookie.clone(),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let response = import(body.clone(), admin_cookie.clone()).await;
+    assert_eq!(response.status(), StatusCode::CREATED, "Import failed");
+
+    let response = import(body, admin_cookie).await;
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+
+    // Imported user logs in with the original password
+    let request = Request::post("/api/auth/login")
+        .header("content-type", "application/json")
+        .body(Body::from(
+            serde_json::to_vec(&json!({ "username": "migrated", "password": PASSWORD })).unwrap(),
+        ))
+        .unwrap();
+    let response = router.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK, "Login failed");
+
+    // The hash is upgraded to argon2 on login
+    let rec = sqlx::query!("SELECT password_hash FROM users_main WHERE username = 'migrated'")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert!(
+        rec.password_hash.starts_with("$argon2id$"),
+        "Hash was not upgraded: {}",
+        rec.password_hash
+    );
+
+    // Register never takes a hash in place of a password
+    let request = Request::post("/api/auth/register")
+        .header("content-type", "application/json")
+        .body(Body::from(
+            serde_json::to_vec(&json!({ "username": "sneaky", "password_hash": password_hash }))
+                .unwrap(),
+        ))
+        .unwrap();
+    let response = router.oneshot(request).await.unwrap();
+    assert!(response.status().is_client_error());
+}
+
+#[sqlx::test]
+async fn admin_usage_summary(pool: PgPool) {
+    let router = router(pool.clone()).await;
+    let cookie = register(&router, "regular").await;
+    let admin_cookie = register_admin(&router, &pool, "admin").await;
+
+    for _ in 0..2 {
+        let request = Request::post("/api/shorten")
+            .header("content-type", "application/json")
+            .body(Body::from(
+                serde_json::to_vec(&json!({ "url": "https://example.com" })).unwrap(),
+            ))
+            .unwrap();
+        let response = router.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+    }
+
+    let usage = |cookie: &str| {
+        let request = Request::get("/api/admin/usage")
+            .header(COOKIE, cookie)
+            .body(Body::empty())
+            .unwrap();
+        router.clone().oneshot(request)
+    };
+
+    let response = usage(&cookie).await.unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    let response = usage(&admin_cookie).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body: serde_json::Value = json(response).await;
+    let categories = body["categories"].as_array().unwrap();
+    let category = |name: &str| {
+        categories
+            .iter()
+            .find(|c| c["category"] == name)
+            .unwrap_or_else(|| panic!("Missing category {name}"))
+    };
+
+    let shorten = category("shorten");
+    assert_eq!(shorten["total"], 2);
+    assert!(shorten["peak_weekday"].is_string());
+    assert!(shorten["peak_hour"].as_u64().unwrap() < 24);
+
+    let redirect = category("redirect");
+    assert_eq!(redirect["total"], 0);
+    assert!(redirect["peak_hour"].is_null());
+}
+
+#[sqlx::test]
+async fn admin_sees_soft_deleted_link(pool: PgPool) {
+    const TEST_URL: &str = "https://example.com/";
+    const ALIAS: &str = "doomed";
+
+    let router = router(pool.clone()).await;
+    let cookie = register(&router, "owner").await;
+    let admin_cookie = register_admin(&router, &pool, "admin").await;
+
+    let request = Request::post("/api/shorten")
+        .header("content-type", "application/json")
+        .header(COOKIE, &cookie)
+        .body(Body::from(
+            serde_json::to_vec(&json!({ "url": TEST_URL, "name": ALIAS })).unwrap(),
+        ))
+        .unwrap();
+    let response = router.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+
+    let admin_get = |alias: &str| {
+        let router = router.clone();
+        let request = Request::get(format!("/api/admin/links/{alias}"))
+            .header(COOKIE, &admin_cookie)
+            .body(Body::empty())
+            .unwrap();
+        async move { router.oneshot(request).await.unwrap() }
+    };
+
+    let response = admin_get(ALIAS).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let link: serde_json::Value = json(response).await;
+    assert_eq!(link["url"], TEST_URL);
+    assert!(link["deleted_at"].is_null());
+
+    let request = Request::delete(format!("/api/user/link/{ALIAS}"))
+        .header(COOKIE, &cookie)
+        .body(Body::empty())
+        .unwrap();
+    let response = router.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+    // Soft-deleted link is gone for the public
+    let request = Request::get(format!("/r/{ALIAS}"))
+        .body(Body::empty())
+        .unwrap();
+    let response = router.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::GONE);
+
+    // but still visible to admins
+    let response = admin_get(ALIAS).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let link: serde_json::Value = json(response).await;
+    assert!(link["deleted_at"].is_string(), "deleted_at should be set");
+
+    // Aliases that never existed are 404
+    let response = admin_get("neverexisted").await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[sqlx::test]
+async fn removed_link_evicted_from_cache(pool: PgPool) {
+    const ALIAS: &str = "cached";
+
+    let router = router(pool).await;
+    let cookie = register(&router, "owner").await;
+
+    let request = Request::post("/api/shorten")
+        .header("content-type", "application/json")
+        .header(COOKIE, &cookie)
+        .body(Body::from(
+            json!({ "url": "https://example.com", "name": ALIAS }).to_string(),
+        ))
+        .unwrap();
+    let response = router.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+
+    let visit = || {
+        let request = Request::get(format!("/r/{ALIAS}"))
+            .body(Body::empty())
+            .unwrap();
+        router.clone().oneshot(request)
+    };
+
+    // The visit caches the link
+    let response = visit().await.unwrap();
+    assert_eq!(response.status(), StatusCode::TEMPORARY_REDIRECT);
+
+    let request = Request::delete(format!("/api/user/link/{ALIAS}"))
+        .header(COOKIE, &cookie)
+        .body(Body::empty())
+        .unwrap();
+    let response = router.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+    let response = visit().await.unwrap();
+    assert_eq!(
+        response.status(),
+        StatusCode::GONE,
+        "Deleted link must not be served from the cache"
+    );
+}
+
+#[sqlx::test]
+async fn retired_alias_not_reissued(pool: PgPool) {
+    const ALIAS: &str = "retired";
+
+    let expired_on = OffsetDateTime::now_utc()
+        .date()
+        .saturating_sub(Duration::days(idle_days() + 1));
+
+    sqlx::query!(
+        "INSERT INTO links_main (alias, url, last_seen) VALUES ($1, $2, $3)",
+        ALIAS,
+        "https://example.com/old",
+        expired_on
+    )
+    .execute(&pool)
+    .await
+    .unwrap();
+
+    tasks::link_cleanup::link_cleanup_task(pool.clone(), CleanupConfig::default(), None)
+        .await
+        .unwrap();
+
+    let router = router(pool.clone()).await;
+
+    let shorten = |url: &'static str| {
+        let router = router.clone();
+        let request = Request::post("/api/shorten")
+            .header("content-type", "application/json")
+            .body(Body::from(
+                serde_json::to_vec(&json!({ "url": url, "name": ALIAS })).unwrap(),
+            ))
+            .unwrap();
+        async move { router.oneshot(request).await.unwrap() }
+    };
+    let redirect = || {
+        let router = router.clone();
+        let request = Request::get(format!("/r/{ALIAS}"))
+            .body(Body::empty())
+            .unwrap();
+        async move { router.oneshot(request).await.unwrap() }
+    };
+
+    // Within the grace period the alias is gone and can't be claimed
+    assert_eq!(redirect().await.status(), StatusCode::GONE);
+    let response = shorten("https://example.com/new").await;
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+    assert_eq!(redirect().await.status(), StatusCode::GONE);
+
+    // Once the grace period is over the alias is free again
+    sqlx::query!(
+        "UPDATE retired_aliases SET retired_until = now() - interval '1 day' WHERE alias = $1",
+        ALIAS
+    )
+    .execute(&pool)
+    .await
+    .unwrap();
+
+    let response = shorten("https://example.com/new").await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+}
+
+#[sqlx::test]
+async fn auth_accepts_json_and_form(pool: PgPool) {
+    let router = router(pool).await;
+
+    let post = |path: &'static str, content_type: &'static str, body: String| {
+        let router = router.clone();
+        let request = Request::post(path)
+            .header("content-type", content_type)
+            .body(Body::from(body))
+            .unwrap();
+        async move { router.oneshot(request).await.unwrap() }
+    };
+
+    // Register with a form, log in with JSON
+    let response = post(
+        "/api/auth/register",
+        "application/x-www-form-urlencoded",
+        "username=formuser&password=password123".to_string(),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::OK, "Form register failed");
+
+    let response = post(
+        "/api/auth/login",
+        "application/json",
+        json!({ "username": "formuser", "password": "password123" }).to_string(),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::OK, "JSON login failed");
+
+    // Register with JSON, log in with a form
+    let response = post(
+        "/api/auth/register",
+        "application/json",
+        json!({ "username": "jsonuser", "password": "password123" }).to_string(),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::OK, "JSON register failed");
+
+    let response = post(
+        "/api/auth/login",
+        "application/x-www-form-urlencoded; charset=utf-8",
+        "username=jsonuser&password=password123".to_string(),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::OK, "Form login failed");
+    assert!(response.headers().get(SET_COOKIE).is_some());
+
+    // Validation is the same for both
+    let response = post(
+        "/api/auth/register",
+        "application/x-www-form-urlencoded",
+        "username=shortpw&password=short".to_string(),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    // Unreadable bodies are answered like any other error
+    for (content_type, body, status, code) in [
+        (
+            "application/json",
+            "{\"username\": ",
+            StatusCode::BAD_REQUEST,
+            "malformed_body",
+        ),
+        (
+            "application/json",
+            "{\"username\": \"nopassword\"}",
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "invalid_body",
+        ),
+        (
+            "application/x-www-form-urlencoded",
+            "username=nopassword",
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "invalid_body",
+        ),
+        (
+            "text/plain",
+            "username=nopassword",
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            "unsupported_media_type",
+        ),
+    ] {
+        let response = post("/api/auth/login", content_type, body.to_string()).await;
+        assert_eq!(response.status(), status, "{body}");
+        assert_eq!(response.headers()[CONTENT_TYPE], "application/json");
+        let body: serde_json::Value = json(response).await;
+        assert_eq!(body["code"], code);
+        assert!(body["message"].is_string());
+        assert!(body["request_id"].is_string());
+    }
+}
+
+#[sqlx::test]
+async fn failed_logins_counted(pool: PgPool) {
+    let router = router(pool.clone()).await;
+    register(&router, "target").await;
+    let admin_cookie = register_admin(&router, &pool, "admin").await;
+
+    let failed_logins = || {
+        let router = router.clone();
+        let request = Request::get("/api/admin/users?prefix=target")
+            .header(COOKIE, &admin_cookie)
+            .body(Body::empty())
+            .unwrap();
+        async move {
+            let response = router.oneshot(request).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            let body: serde_json::Value = json(response).await;
+            body["users"][0].clone()
+        }
+    };
+
+    for _ in 0..2 {
+        let request = Request::post("/api/auth/login")
+            .header("content-type", "application/json")
+            .body(Body::from(
+                json!({ "username": "target", "password": "wrongpassword" }).to_string(),
+            ))
+            .unwrap();
+        let response = router.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    let user = failed_logins().await;
+    assert_eq!(user["failed_logins"], 2);
+    assert!(user["last_failed_at"].is_string());
+
+    login(&router, "target").await;
+
+    let user = failed_logins().await;
+    assert_eq!(user["failed_logins"], 0, "Successful login should reset");
+}
+
+#[sqlx::test]
+async fn login_failure_is_uniform(pool: PgPool) {
+    let router = router(pool).await;
+    register(&router, "target").await;
+
+    let mut bodies = Vec::new();
+    for username in ["target", "nobody"] {
+        let request = Request::post("/api/auth/login")
+            .header("content-type", "application/json")
+            .body(Body::from(
+                json!({ "username": username, "password": "wrongpassword" }).to_string(),
+            ))
+            .unwrap();
+        let response = router.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let body: serde_json::Value = json(response).await;
+        bodies.push(body);
+    }
+    assert_eq!(bodies[0], bodies[1]);
+}
+
+#[sqlx::test]
+async fn auth_rejects_invalid_credentials(pool: PgPool) {
+    let router = router(pool).await;
+    register(&router, "target").await;
+
+    let overlong = "a".repeat(500);
+    let cases = [
+        ("/api/auth/login", "target", ""),
+        ("/api/auth/login", "target", overlong.as_str()),
+        ("/api/auth/login", "ab", "password123"),
+        ("/api/auth/register", "newuser", ""),
+        ("/api/auth/register", "newuser", overlong.as_str()),
+        ("/api/auth/register", "bad name!", "password123"),
+    ];
+
+    for (uri, username, password) in cases {
+        let request = Request::post(uri)
+            .header("content-type", "application/json")
+            .body(Body::from(
+                json!({ "username": username, "password": password }).to_string(),
+            ))
+            .unwrap();
+        let response = router.clone().oneshot(request).await.unwrap();
+        assert_eq!(
+            response.status(),
+            StatusCode::BAD_REQUEST,
+            "{uri} accepted {username:?} with a {}-char password",
+            password.len()
+        );
+        assert!(response.headers().get(SET_COOKIE).is_none());
+    }
+}
+
+#[sqlx::test]
+async fn update_link_target(pool: PgPool) {
+    const OLD_URL: &str = "https://example.com/old";
+    const NEW_URL: &str = "https://example.com/new";
+    const ALIAS: &str = "movable";
+
+    let router = router(pool).await;
+    let cookie = register(&router, "mover").await;
+
+    let request = Request::post("/api/shorten")
+        .header("content-type", "application/json")
+        .header(COOKIE, &cookie)
+        .body(Body::from(
+            json!({ "url": OLD_URL, "name": ALIAS }).to_string(),
+        ))
+        .unwrap();
+    let response = router.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+
+    let redirect = || {
+        let router = router.clone();
+        let request = Request::get(format!("/r/{ALIAS}"))
+            .body(Body::empty())
+            .unwrap();
+        async move { router.oneshot(request).await.unwrap() }
+    };
+    let update = |cookie: String, url: &'static str| {
+        let router = router.clone();
+        let request = Request::put(format!("/api/links/{ALIAS}"))
+            .header("content-type", "application/json")
+            .header(COOKIE, cookie)
+            .body(Body::from(json!({ "url": url }).to_string()))
+            .unwrap();
+        async move { router.oneshot(request).await.unwrap() }
+    };
+
+    // Cache the old destination
+    let response = redirect().await;
+    assert_eq!(response.headers().get(LOCATION).unwrap(), OLD_URL);
+
+    let other_cookie = register(&router, "stranger").await;
+    let response = update(other_cookie, NEW_URL).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    let response = update(cookie.clone(), "not a url").await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let response = update(cookie, NEW_URL).await;
+    assert_eq!(response.status(), StatusCode::OK, "Update failed");
+
+    let response = redirect().await;
+    assert_eq!(response.status(), StatusCode::TEMPORARY_REDIRECT);
+    assert_eq!(
+        response.headers().get(LOCATION).unwrap(),
+        NEW_URL,
+        "Redirect should use the new destination right away"
+    );
+}
+
+#[sqlx::test]
+async fn change_and_remove_link_password(pool: PgPool) {
+    const TEST_URL: &str = "https://example.com";
+    const ALIAS: &str = "lockable";
+
+    let router = router(pool).await;
+    let cookie = register(&router, "locksmith").await;
+
+    let request = Request::post("/api/shorten")
+        .header("content-type", "application/json")
+        .header(COOKIE, &cookie)
+        .body(Body::from(
+            json!({ "url": TEST_URL, "name": ALIAS, "password": "password123" }).to_string(),
+        ))
+        .unwrap();
+    let response = router.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+
+    let redirect = || {
+        let router = router.clone();
+        let request = Request::get(format!("/r/{ALIAS}"))
+            .body(Body::empty())
+            .unwrap();
+        async move { router.oneshot(request).await.unwrap() }
+    };
+    let unlock = |password: &'static str| {
+        let router = router.clone();
+        let request = Request::post(format!("/api/unlock/{ALIAS}"))
+            .header("content-type", "application/json")
+            .body(Body::from(json!({ "password": password }).to_string()))
+            .unwrap();
+        async move { router.oneshot(request).await.unwrap().status() }
+    };
+    let set_password = |cookie: String, password: serde_json::Value| {
+        let router = router.clone();
+        let request = Request::patch(format!("/api/links/{ALIAS}/password"))
+            .header("content-type", "application/json")
+            .header(COOKIE, cookie)
+            .body(Body::from(json!({ "password": password }).to_string()))
+            .unwrap();
+        async move { router.oneshot(request).await.unwrap().status() }
+    };
+
+    // Cache the protected link
+    let response = redirect().await;
+    assert_eq!(
+        response.headers().get(LOCATION).unwrap().to_str().unwrap(),
+        format!("/{UNLOCK_PATH}/{ALIAS}")
+    );
+
+    let other_cookie = register(&router, "burglar").await;
+    assert_eq!(
+        set_password(other_cookie, json!("hijacked123")).await,
+        StatusCode::NOT_FOUND
+    );
+    assert_eq!(
+        set_password(cookie.clone(), json!("line\nbreak")).await,
+        StatusCode::BAD_REQUEST
+    );
+
+    assert_eq!(
+        set_password(cookie.clone(), json!("changed123")).await,
+        StatusCode::NO_CONTENT
+    );
+    assert_eq!(unlock("password123").await, StatusCode::UNAUTHORIZED);
+    assert_eq!(unlock("changed123").await, StatusCode::OK);
+
+    assert_eq!(
+        set_password(cookie, serde_json::Value::Null).await,
+        StatusCode::NO_CONTENT
+    );
+    let response = redirect().await;
+    assert_eq!(
+        response.headers().get(LOCATION).unwrap(),
+        TEST_URL,
+        "Redirect should skip the unlock view right away"
+    );
+}
+
+#[sqlx::test]
+async fn per_link_expiry(pool: PgPool) {
+    let router = router(pool.clone()).await;
+
+    let shorten = |name: &'static str, expires_in_days: i64| {
+        let router = router.clone();
+        let request = Request::post("/api/shorten")
+            .header("content-type", "application/json")
+            .body(Body::from(
+                json!({
+                    "url": "https://example.com",
+                    "name": name,
+                    "expires_in_days": expires_in_days
+                })
+                .to_string(),
+            ))
+            .unwrap();
+        async move { router.oneshot(request).await.unwrap() }
+    };
+    let redirect = |alias: &'static str| {
+        let router = router.clone();
+        let request = Request::get(format!("/r/{alias}"))
+            .body(Body::empty())
+            .unwrap();
+        async move { router.oneshot(request).await.unwrap().status() }
+    };
+
+    assert_eq!(shorten("forever", 0).await.status(), StatusCode::CREATED);
+    assert_eq!(shorten("shortlived", 1).await.status(), StatusCode::CREATED);
+    assert_eq!(
+        shorten("negative", -1).await.status(),
+        StatusCode::BAD_REQUEST
+    );
+
+    // Long idle links never expire with 0
+    let idle_since = OffsetDateTime::now_utc()
+        .date()
+        .saturating_sub(Duration::days(idle_days() + 1));
+    sqlx::query!(
+        "UPDATE links_main SET last_seen = $1 WHERE alias = 'forever'",
+        idle_since
+    )
+    .execute(&pool)
+    .await
+    .unwrap();
+    assert_eq!(redirect("forever").await, StatusCode::TEMPORARY_REDIRECT);
+
+    // Explicit expiry applies even to links in use
+    sqlx::query!(
+        "UPDATE links_main SET expires_at = now() - interval '1 minute' WHERE alias = 'shortlived'"
+    )
+    .execute(&pool)
+    .await
+    .unwrap();
+    assert_eq!(redirect("shortlived").await, StatusCode::GONE);
+}
+
+#[sqlx::test]
+async fn link_stats(pool: PgPool) {
+    const ALIAS: &str = "counted";
+
+    let router = router(pool.clone()).await;
+    let cookie = register(&router, "owner").await;
+
+    let request = Request::post("/api/shorten")
+        .header("content-type", "application/json")
+        .header(COOKIE, &cookie)
+        .body(Body::from(
+            json!({ "url": "https://example.com", "name": ALIAS }).to_string(),
+        ))
+        .unwrap();
+    let response = router.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+
+    sqlx::query("CREATE TABLE daily_metrics_default PARTITION OF daily_metrics DEFAULT")
+        .execute(&pool)
+        .await
+        .unwrap();
+    sqlx::query!(
+        r#"
+        INSERT INTO daily_metrics (day, link_id, hits, last_access)
+        SELECT CURRENT_DATE - d, id, 10 + d, now() - make_interval(days => d)
+        FROM links_main, generate_series(0, 9) AS d
+        WHERE alias = $1
+          AND d <> 2
+        "#,
+        ALIAS
+    )
+    .execute(&pool)
+    .await
+    .unwrap();
+
+    let stats = |cookie: String| {
+        let router = router.clone();
+        let request = Request::get(format!("/api/links/{ALIAS}/stats"))
+            .header(COOKIE, cookie)
+            .body(Body::empty())
+            .unwrap();
+        async move { router.oneshot(request).await.unwrap() }
+    };
+
+    let other_cookie = register(&router, "stranger").await;
+    let response = stats(other_cookie).await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    let response = stats(cookie).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let body: serde_json::Value = json(response).await;
+
+    // 10 days of 10..=19 hits, except for the day with 12
+    assert_eq!(body["total_hits"], (10..=19).sum::<i64>() - 12);
+    assert!(body["last_access"].is_string());
+
+    let daily: Vec<i64> = body["daily_hits"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|day| day["hits"].as_i64().unwrap())
+        .collect();
+    assert_eq!(daily, vec![16, 15, 14, 13, 0, 11, 10], "Oldest day first");
+}
+
+#[sqlx::test]
+async fn link_stats_by_referrer(pool: PgPool) {
+    const ALIAS: &str = "shared";
+
+    tasks::link_metrics::create_partitions_task(pool.clone())
+        .await
+        .unwrap();
+
+    let router = router(pool.clone()).await;
+    let cookie = register(&router, "owner").await;
+    let request = Request::post("/api/shorten")
+        .header("content-type", "application/json")
+        .header(COOKIE, &cookie)
+        .body(Body::from(
+            json!({ "url": "https://example.com", "name": ALIAS }).to_string(),
+        ))
+        .unwrap();
+    let response = router.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+
+    let visits = [
+        (
+            Some("https://News.example.org/article?reader=42"),
+            Some("de-DE,de;q=0.9"),
+        ),
+        (Some("https://news.example.org/other"), Some("de")),
+        (Some("android-app://com.example"), Some("en-GB")),
+        (None, None),
+    ];
+    for collect_referrers in [false, true] {
+        let config = AppConfig {
+            collect_referrers,
+            ..AppConfig::default()
+        };
+        let state = app::build_test_app_state_with_config(pool.clone(), config).unwrap();
+        let redirects = api::build_redirect_router(state.clone());
+
+        for (referrer, language) in visits {
+            let mut request = Request::get(format!("/r/{ALIAS}"));
+            if let Some(referrer) = referrer {
+                request = request.header(REFERER, referrer);
+            }
+            if let Some(language) = language {
+                request = request.header(ACCEPT_LANGUAGE, language);
+            }
+            let response = redirects
+                .clone()
+                .oneshot(request.body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::TEMPORARY_REDIRECT);
+        }
+        state.metrics.drain_to_db(&pool).await.unwrap();
+    }
+
+    let request = Request::get(format!("/api/links/{ALIAS}/stats"))
+        .header(COOKIE, &cookie)
+        .body(Body::empty())
+        .unwrap();
+    let response = router.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body: serde_json::Value = json(response).await;
+
+    assert_eq!(body["total_hits"], 8);
+    assert_eq!(
+        body["referrers"],
+        json!([
+            { "value": "news.example.org", "hits": 2 },
+            { "value": "com.example", "hits": 1 },
+        ]),
+        "Only hosts are kept, and only while collection is enabled"
+    );
+    assert_eq!(
+        body["languages"],
+        json!([{ "value": "de", "hits": 2 }, { "value": "en", "hits": 1 }])
+    );
+}
+
+#[sqlx::test]
+async fn configured_url_schemes(pool: PgPool) {
+    let shorten = |allowed_schemes: &[&str]| {
+        let config = AppConfig {
+            url_policy: UrlPolicy {
+                allowed_schemes: allowed_schemes.iter().map(|s| s.to_string()).collect(),
+                ..UrlPolicy::default()
+            },
+            ..AppConfig::default()
+        };
+        let state = app::build_test_app_state_with_config(pool.clone(), config).unwrap();
+        let request = Request::post("/api/shorten")
+            .header("content-type", "application/json")
+            .body(Body::from(
+                json!({ "url": "mailto:someone@example.com" }).to_string(),
+            ))
+            .unwrap();
+        async move { api::build_router(state).oneshot(request).await.unwrap() }
+    };
+
+    let response = shorten(UrlPolicy::DEFAULT_SCHEMES).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let body: serde_json::Value = json(response).await;
+    assert_eq!(body["code"], "url_scheme_not_allowed");
+    assert_eq!(body["message"], "URL scheme must be one of: http, https");
+
+    let response = shorten(&["https", "mailto"]).await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+}
+
+#[sqlx::test]
+async fn normalized_url_stored(pool: PgPool) {
+    const TEST_URL: &str = "https://Example.com:443/page?b=2&a=1#top";
+
+    let target = |normalize: bool| {
+        let config = AppConfig {
+            url_policy: UrlPolicy {
+                normalize,
+                ..UrlPolicy::default()
+            },
+            ..AppConfig::default()
+        };
+        let state = app::build_test_app_state_with_config(pool.clone(), config).unwrap();
+        let router = api::build_router(state);
+        async move {
+            let request = Request::post("/api/shorten")
+                .header("content-type", "application/json")
+                .body(Body::from(json!({ "url": TEST_URL }).to_string()))
+                .unwrap();
+            let response = router.clone().oneshot(request).await.unwrap();
+            assert_eq!(response.status(), StatusCode::CREATED);
+            let body: serde_json::Value = json(response).await;
+
+            let request = Request::get(format!("/r/{}", body["alias"].as_str().unwrap()))
+                .body(Body::empty())
+                .unwrap();
+            let response = router.oneshot(request).await.unwrap();
+            response.headers()[LOCATION].to_str().unwrap().to_string()
+        }
+    };
+
+    assert_eq!(
+        target(false).await,
+        TEST_URL,
+        "URLs are kept as given by default"
+    );
+    assert_eq!(target(true).await, "https://example.com/page?a=1&b=2");
+}
+
+#[sqlx::test]
+async fn shorten_batch(pool: PgPool) {
+    let router = router(pool).await;
+
+    let urls = [
+        "https://example.com/1",
+        "not a url",
+        "https://example.com/3",
+        "https://example.com/1",
+    ];
+    let request = Request::post("/api/shorten/batch")
+        .header("content-type", "application/json")
+        .body(Body::from(json!({ "urls": urls }).to_string()))
+        .unwrap();
+    let response = router.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let items: Vec<serde_json::Value> = json(response).await;
+    assert_eq!(items.len(), urls.len());
+    for (item, url) in items.iter().zip(urls) {
+        assert_eq!(item["url"], url, "Input order must be preserved");
+    }
+    assert!(items[1]["error"].is_string(), "Bad URL should get an error");
+    assert!(items[1].get("alias").is_none());
+
+    // Good URLs redirect to their own destination
+    for idx in [0, 2, 3] {
+        let alias = items[idx]["alias"].as_str().unwrap();
+        let request = Request::get(format!("/r/{alias}"))
+            .body(Body::empty())
+            .unwrap();
+        let response = router.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::TEMPORARY_REDIRECT);
+        assert_eq!(response.headers().get(LOCATION).unwrap(), urls[idx]);
+    }
+    assert_ne!(items[0]["alias"], items[3]["alias"]);
+
+    // Anonymous links can be deleted like single ones
+    for idx in [0, 2, 3] {
+        assert!(items[idx]["deletion_token"].is_string());
+    }
+    let alias = items[2]["alias"].as_str().unwrap();
+    let request = Request::delete(format!("/api/links/{alias}"))
+        .header("content-type", "application/json")
+        .body(Body::from(
+            json!({ "token": items[2]["deletion_token"] }).to_string(),
+        ))
+        .unwrap();
+    let response = router.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+}
+
+#[sqlx::test]
+async fn import_links_for_user(pool: PgPool) {
+    let router = router(pool).await;
+    let cookie = register(&router, "migrant").await;
+
+    let rows = json!([
+        { "url": "https://example.com/1" },
+        { "url": "https://example.com/2", "alias": "imported" },
+        { "url": "https://example.com/3", "alias": "imported" },
+        { "url": "not a url" },
+        { "url": "https://example.com/5", "password": "secret" },
+    ]);
+    let import = |cookie: Option<&str>| {
+        let mut request = Request::post("/api/import").header("content-type", "application/json");
+        if let Some(cookie) = cookie {
+            request = request.header(COOKIE, cookie);
+        }
+        router
+            .clone()
+            .oneshot(request.body(Body::from(rows.to_string())).unwrap())
+    };
+
+    let response = import(None).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let body: serde_json::Value = json(response).await;
+    assert_eq!(body["code"], "unauthorized");
+
+    let response = import(Some(&cookie)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let items: Vec<serde_json::Value> = json(response).await;
+    assert_eq!(items.len(), 5);
+    assert!(items[0]["alias"].is_string());
+    assert_eq!(items[1]["alias"], "imported");
+    assert_eq!(items[2]["error"], "This alias already exists");
+    assert!(items[3]["error"].is_string());
+    assert!(items[4]["alias"].is_string());
+
+    // Protected rows keep their password
+    let alias = items[4]["alias"].as_str().unwrap();
+    let request = Request::get(format!("/r/{alias}"))
+        .body(Body::empty())
+        .unwrap();
+    let response = router.clone().oneshot(request).await.unwrap();
+    let location = response.headers().get(LOCATION).unwrap().to_str().unwrap();
+    assert!(location.starts_with(&format!("/{UNLOCK_PATH}/")));
+
+    let request = Request::get("/api/user/list")
+        .header(COOKIE, &cookie)
+        .body(Body::empty())
+        .unwrap();
+    let response = router.clone().oneshot(request).await.unwrap();
+    let page: serde_json::Value = json(response).await;
+    assert_eq!(page["links"].as_array().unwrap().len(), 3);
+}
+
+#[sqlx::test]
+async fn export_links_for_user(pool: PgPool) {
+    let router = router(pool.clone()).await;
+    let cookie = register(&router, "archivist").await;
+
+    // Enough links to span more than one page
+    sqlx::query(
+        r#"
+        INSERT INTO links_main (alias, url, user_id, deleted_at)
+        SELECT 'export' || n, 'https://example.com/' || n, u.id,
+               CASE WHEN n = 1 THEN now() END
+        FROM generate_series(1, 502) AS n, users_main u
+        WHERE u.username = 'archivist'
+        "#,
+    )
+    .execute(&pool)
+    .await
+    .unwrap();
+
+    let request = Request::get("/api/export")
+        .header(COOKIE, &cookie)
+        .body(Body::empty())
+        .unwrap();
+    let response = router.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let body: serde_json::Value = json(response).await;
+    let links = body["links"].as_array().unwrap();
+    assert_eq!(links.len(), 501, "Deleted links are not exported");
+    assert_eq!(links[0]["alias"], "export2");
+    assert_eq!(links[0]["short_url"], "http://localhost:3000/r/export2");
+    assert_eq!(links[500]["alias"], "export502");
+    assert!(links[0]["created_at"].is_string());
+    assert!(links[0]["last_seen"].is_string());
+}
+
+#[sqlx::test]
+async fn redirect_forwards_query_params(pool: PgPool) {
+    sqlx::query("INSERT INTO links_main (alias, url) VALUES ($1, $2)")
+        .bind("campaign")
+        .bind("https://example.com/page?ref=a%26b#top")
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    let visit = |forward_query_params: bool| {
+        let config = AppConfig {
+            forward_query_params,
+            ..AppConfig::default()
+        };
+        let state = app::build_test_app_state_with_config(pool.clone(), config).unwrap();
+        let request =
+            Request::get("/r/campaign?utm_source=news%20letter&password=x&utm_medium=email")
+                .body(Body::empty())
+                .unwrap();
+        async move {
+            let response = api::build_redirect_router(state)
+                .oneshot(request)
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::TEMPORARY_REDIRECT);
+            response.headers()[LOCATION].to_str().unwrap().to_string()
+        }
+    };
+
+    assert_eq!(visit(false).await, "https://example.com/page?ref=a%26b#top");
+    assert_eq!(
+        visit(true).await,
+        "https://example.com/page?ref=a%26b&utm_source=news+letter&utm_medium=email#top"
+    );
+}
+
+#[sqlx::test]
+async fn interstitial_preview_before_redirect(pool: PgPool) {
+    sqlx::query("INSERT INTO links_main (alias, url) VALUES ($1, $2)")
+        .bind("previewed")
+        .bind("https://example.com/page")
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    let config = AppConfig {
+        interstitial: true,
+        forward_query_params: true,
+        ..AppConfig::default()
+    };
+    let state = app::build_test_app_state_with_config(pool, config).unwrap();
+    let router = api::build_redirect_router(state.clone());
+
+    let visit = |path: &'static str, accept: &'static str| {
+        let request = Request::get(path)
+            .header("accept", accept)
+            .body(Body::empty())
+            .unwrap();
+        router.clone().oneshot(request)
+    };
+    let recorded_hits = || {
+        let map = state.metrics.swap_map();
+        map.iter().map(|entry| entry.value().hits()).sum::<i64>()
+    };
+
+    let response = visit("/r/previewed?utm_source=mail", "text/html")
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(response.headers().get(LOCATION).is_none());
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let page = String::from_utf8(bytes.to_vec()).unwrap();
+    assert!(page.contains("https://example.com/page?utm_source=mail"));
+    assert!(page.contains("/r/previewed?utm_source=mail&amp;go=1"));
+
+    let response = visit("/r/previewed", "application/json").await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body: serde_json::Value = json(response).await;
+    assert_eq!(body["target"], "https://example.com/page");
+
+    // Previews aren't visits
+    assert_eq!(recorded_hits(), 0);
+
+    let response = visit("/r/previewed?utm_source=mail&go=1", "text/html")
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::TEMPORARY_REDIRECT);
+    assert_eq!(
+        response.headers()[LOCATION],
+        "https://example.com/page?utm_source=mail"
+    );
+    assert_eq!(recorded_hits(), 1);
+}
+
+#[sqlx::test]
+async fn qr_code_for_alias(pool: PgPool) {
+    const BASE_URL: &str = "https://sho.rt";
+    let config = AppConfig {
+        base_url: BASE_URL.to_string(),
+        ..AppConfig::default()
+    };
+    let state = app::build_test_app_state_with_config(pool, config).unwrap();
+    let router = api::build_router(state);
+
+    let mut aliases = Vec::new();
+    for body in [
+        json!({ "url": "https://example.com" }),
+        json!({ "url": "https://example.com", "password": "password123" }),
+    ] {
+        let request = Request::post("/api/shorten")
+            .header("content-type", "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        let response = router.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let body: serde_json::Value = json(response).await;
+        aliases.push(body["alias"].as_str().unwrap().to_string());
+    }
+    let (open, protected) = (&aliases[0], &aliases[1]);
+
+    // SVG by default, encoding the full short URL
+    let expected = [
+        (open, format!("{BASE_URL}/r/{open}")),
+        (protected, format!("{BASE_URL}/{UNLOCK_PATH}/{protected}")),
+    ];
+    for (alias, short_url) in expected {
+        let request = Request::get(format!("/r/{alias}/qr?size=128"))
+            .body(Body::empty())
+            .unwrap();
+        let response = router.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["content-type"], "image/svg+xml");
+
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let svg = qrcode::QrCode::new(short_url.as_bytes())
+            .unwrap()
+            .render::<qrcode::render::svg::Color>()
+            .min_dimensions(128, 128)
+            .quiet_zone(true)
+            .build();
+        assert_eq!(bytes, svg.as_bytes(), "QR must encode {short_url}");
+    }
+
+    let request = Request::get(format!("/r/{open}/qr?format=png"))
+        .body(Body::empty())
+        .unwrap();
+    let response = router.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["content-type"], "image/png");
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    assert!(bytes.starts_with(b"\x89PNG\r\n\x1a\n"));
+
+    let request = Request::get(format!("/r/{open}/qr?format=gif"))
+        .body(Body::empty())
+        .unwrap();
+    let response = router.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let request = Request::get("/r/missing1/qr").body(Body::empty()).unwrap();
+    let response = router.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[sqlx::test]
+async fn max_hits_link_self_destructs(pool: PgPool) {
+    let state = app::build_test_app_state(pool.clone()).unwrap();
+    let router = api::build_router(state.clone());
+
+    let request = Request::post("/api/shorten")
+        .header("content-type", "application/json")
+        .body(Body::from(
+            json!({ "url": "https://example.com", "max_hits": 0 }).to_string(),
+        ))
+        .unwrap();
+    let response = router.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let request = Request::post("/api/shorten")
+        .header("content-type", "application/json")
+        .body(Body::from(
+            json!({ "url": "https://example.com", "max_hits": 3 }).to_string(),
+        ))
+        .unwrap();
+    let response = router.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let body: serde_json::Value = json(response).await;
+    let alias = body["alias"].as_str().unwrap().to_string();
+
+    let visit = |router: Router| {
+        let request = Request::get(format!("/r/{alias}"))
+            .body(Body::empty())
+            .unwrap();
+        async move { router.oneshot(request).await.unwrap().status() }
+    };
+
+    for _ in 0..2 {
+        assert_eq!(visit(router.clone()).await, StatusCode::TEMPORARY_REDIRECT);
+    }
+
+    // Flushed hits are persisted once and the reloaded link still knows its count
+    sqlx::query("CREATE TABLE daily_metrics_default PARTITION OF daily_metrics DEFAULT")
+        .execute(&pool)
+        .await
+        .unwrap();
+    tasks::link_metrics::process_batch_task(pool.clone(), state.metrics.clone())
+        .await
+        .unwrap();
+    let hit_count: i64 = sqlx::query_scalar("SELECT hit_count FROM links_main WHERE alias = $1")
+        .bind(&alias)
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(hit_count, 2);
+
+    // The cached link still holds the count from before the flush
+    assert_eq!(visit(router.clone()).await, StatusCode::TEMPORARY_REDIRECT);
+    assert_eq!(visit(router.clone()).await, StatusCode::GONE);
+
+    // Used up right at the flush boundary, the cached count doesn't revive the link
+    tasks::link_metrics::process_batch_task(pool.clone(), state.metrics.clone())
+        .await
+        .unwrap();
+    assert_eq!(visit(router.clone()).await, StatusCode::GONE);
+    assert_eq!(visit(router.clone()).await, StatusCode::GONE);
+}
+
+#[sqlx::test]
+async fn health_and_readiness(pool: PgPool) {
+    let state = app::build_test_app_state(pool.clone()).unwrap();
+
+    for router in [
+        api::build_router(state.clone()),
+        api::build_redirect_router(state.clone()),
+    ] {
+        for path in ["/health", "/ready"] {
+            let request = Request::get(path).body(Body::empty()).unwrap();
+            let response = router.clone().oneshot(request).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK, "{path}");
+        }
+    }
+
+    // Without a database only liveness holds
+    pool.close().await;
+    let router = api::build_router(state);
+
+    let request = Request::get("/health").body(Body::empty()).unwrap();
+    let response = router.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let request = Request::get("/ready").body(Body::empty()).unwrap();
+    let response = router.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    let body: serde_json::Value = json(response).await;
+    assert_eq!(body["code"], "database_unavailable");
+    assert_eq!(body["message"], "Database is unreachable");
+}
+
+#[sqlx::test]
+async fn request_id_in_error_response(pool: PgPool) {
+    let router = router(pool.clone()).await;
+
+    // Every response carries an id, generated when the client sent none
+    let request = Request::get("/health").body(Body::empty()).unwrap();
+    let response = router.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.headers()["x-request-id"].len(), 32);
+
+    // Failures report the id the client sent, to find the logs by
+    pool.close().await;
+    let request = Request::post("/api/shorten")
+        .header(CONTENT_TYPE, "application/json")
+        .header("x-request-id", "support-ticket-42")
+        .body(Body::from(
+            json!({ "url": "https://example.com" }).to_string(),
+        ))
+        .unwrap();
+    let response = router.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    assert_eq!(response.headers()["x-request-id"], "support-ticket-42");
+    let body: serde_json::Value = json(response).await;
+    assert_eq!(body["code"], "internal");
+    assert_eq!(body["request_id"], "support-ticket-42");
+}
+
+#[sqlx::test]
+async fn unreadable_json_body_rejected_with_code(pool: PgPool) {
+    let router = router(pool).await;
+
+    let request = Request::post("/api/shorten")
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from(json!({ "name": "nourl" }).to_string()))
+        .unwrap();
+    let response = router.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    let body: serde_json::Value = json(response).await;
+    assert_eq!(body["code"], "invalid_body");
+}
+
+#[sqlx::test]
+async fn drain_metrics_to_db(pool: PgPool) {
+    tasks::link_metrics::create_partitions_task(pool.clone())
+        .await
+        .unwrap();
+    let link_id: i64 = sqlx::query_scalar(
+        "INSERT INTO links_main (url) VALUES ('https://example.com') RETURNING id",
+    )
+    .fetch_one(&pool)
+    .await
+    .unwrap();
+
+    let metrics = tasks::link_metrics::LinkMetrics::new();
+    for _ in 0..3 {
+        metrics.record_hit(link_id);
+    }
+    metrics.drain_to_db(&pool).await.unwrap();
+
+    let hits: i64 = sqlx::query_scalar(
+        "SELECT hits FROM daily_metrics WHERE link_id = $1 AND day = CURRENT_DATE",
+    )
+    .bind(link_id)
+    .fetch_one(&pool)
+    .await
+    .unwrap();
+    assert_eq!(hits, 3);
+
+    // Drained hits are not persisted twice
+    metrics.drain_to_db(&pool).await.unwrap();
+    let hits: i64 = sqlx::query_scalar("SELECT SUM(hits)::bigint FROM daily_metrics")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(hits, 3);
+}
+
+/// Bus shared by test instances, standing in for a Redis pub/sub channel
+struct SharedBus(broadcast::Sender<Invalidation>);
+
+impl InvalidationBus for SharedBus {
+    fn publish(&self, invalidation: Invalidation) {
+        let _ = self.0.send(invalidation);
+    }
+
+    fn subscribe(&self) -> Option<broadcast::Receiver<Invalidation>> {
+        Some(self.0.subscribe())
+    }
+}
+
+/// Redirect location of the alias
+async fn location(router: &Router, alias: &str) -> String {
+    let request = Request::get(format!("/r/{alias}"))
+        .body(Body::empty())
+        .unwrap();
+    let response = router.clone().oneshot(request).await.unwrap();
+    response.headers()[LOCATION].to_str().unwrap().to_string()
+}
+
+/// Poll until the instance redirects the alias to `url`, listeners apply invalidations asynchronously
+async fn wait_for_location(router: &Router, alias: &str, url: &str) {
+    for _ in 0..50 {
+        if location(router, alias).await == url {
+            return;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+    panic!("Instance kept serving a stale destination for {alias}");
+}
+
+/// Update a link on the first instance after the second cached it
+async fn update_reaches_other_instance(first: &Router, second: &Router) {
+    const ALIAS: &str = "shared";
+
+    let cookie = register(first, "owner").await;
+    let request = Request::post("/api/shorten")
+        .header("content-type", "application/json")
+        .header(COOKIE, &cookie)
+        .body(Body::from(
+            json!({ "url": "https://example.com/old", "name": ALIAS }).to_string(),
+        ))
+        .unwrap();
+    let response = first.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+
+    // Cache the old destination on the second instance
+    assert_eq!(location(second, ALIAS).await, "https://example.com/old");
+
+    let request = Request::put(format!("/api/links/{ALIAS}"))
+        .header("content-type", "application/json")
+        .header(COOKIE, &cookie)
+        .body(Body::from(
+            json!({ "url": "https://example.com/new" }).to_string(),
+        ))
+        .unwrap();
+    let response = first.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    wait_for_location(second, ALIAS, "https://example.com/new").await;
+}
+
+#[sqlx::test]
+async fn cache_invalidation_reaches_other_instances(pool: PgPool) {
+    let bus = std::sync::Arc::new(SharedBus(broadcast::channel(16).0));
+    let mut replicas = Vec::new();
+    for _ in 0..2 {
+        let mut state = app::build_test_app_state(pool.clone()).unwrap();
+        state.cache = LinkCache::new(bus.clone());
+        tokio::spawn(state.cache.clone().listen());
+        replicas.push(api::build_router(state));
+    }
+
+    update_reaches_other_instance(&replicas[0], &replicas[1]).await;
+}
+
+#[sqlx::test]
+async fn postgres_cache_bus_reaches_other_instances(pool: PgPool) {
+    const ALIAS: &str = "bulk";
+
+    let config = AppConfig {
+        cache_bus: CacheBusKind::Postgres,
+        ..AppConfig::default()
+    };
+    let mut states = Vec::new();
+    for _ in 0..2 {
+        let state = app::build_test_app_state_with_config(pool.clone(), config.clone()).unwrap();
+        tokio::spawn(state.cache.clone().listen());
+        states.push(state);
+    }
+    let first = api::build_router(states[0].clone());
+    let second = api::build_router(states[1].clone());
+    // Give the listeners time to connect, notifications before that are not delivered
+    tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+
+    update_reaches_other_instance(&first, &second).await;
+
+    // Bulk changes clear every instance
+    sqlx::query("INSERT INTO links_main (alias, url) VALUES ($1, 'https://example.com/before')")
+        .bind(ALIAS)
+        .execute(&pool)
+        .await
+        .unwrap();
+    assert_eq!(location(&second, ALIAS).await, "https://example.com/before");
+    sqlx::query("UPDATE links_main SET url = 'https://example.com/after' WHERE alias = $1")
+        .bind(ALIAS)
+        .execute(&pool)
+        .await
+        .unwrap();
+    states[0].cache.invalidate_all();
+
+    wait_for_location(&second, ALIAS, "https://example.com/after").await;
+}
+
+#[sqlx::test]
+async fn postgres_sessions_survive_restart(pool: PgPool) {
+    let instance = || {
+        let config = AppConfig {
+            session_store: SessionStoreKind::Postgres,
+            ..AppConfig::default()
+        };
+        let state = app::build_test_app_state_with_config(pool.clone(), config).unwrap();
+        api::build_router(state)
+    };
+
+    let cookie = register(&instance(), "persistent").await;
+
+    // Only a digest of the session id is stored
+    let (_, session_id) = cookie.split_once('=').unwrap();
+    let stored: Vec<String> = sqlx::query_scalar("SELECT id_hash FROM sessions")
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+    assert_eq!(stored.len(), 1);
+    assert_ne!(stored[0], session_id);
+
+    let me = |router: Router| {
+        let request = Request::get("/api/auth/me")
+            .header(COOKIE, &cookie)
+            .body(Body::empty())
+            .unwrap();
+        async move { router.oneshot(request).await.unwrap().status() }
+    };
+
+    // A fresh instance knows the session
+    let restarted = instance();
+    assert_eq!(me(restarted.clone()).await, StatusCode::OK);
+
+    let request = Request::post("/api/user/logout")
+        .header(COOKIE, &cookie)
+        .body(Body::empty())
+        .unwrap();
+    let response = instance().oneshot(request).await.unwrap();
+    assert!(response.status().is_success());
+
+    assert_eq!(me(restarted).await, StatusCode::UNAUTHORIZED);
+}
+
+#[sqlx::test]
+async fn logout_all_revokes_every_session(pool: PgPool) {
+    for session_store in [SessionStoreKind::Memory, SessionStoreKind::Postgres] {
+        sqlx::query("DELETE FROM sessions")
+            .execute(&pool)
+            .await
+            .unwrap();
+        sqlx::query("DELETE FROM users_main")
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let config = AppConfig {
+            session_store,
+            ..AppConfig::default()
+        };
+        let state = app::build_test_app_state_with_config(pool.clone(), config).unwrap();
+        let router = api::build_router(state);
+
+        let first = register(&router, "victim").await;
+        let second = login(&router, "victim").await;
+        let other = register(&router, "bystander").await;
+
+        let request = Request::post("/api/logout-all")
+            .header(COOKIE, &first)
+            .body(Body::empty())
+            .unwrap();
+        let response = router.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let set_cookie = response
+            .headers()
+            .get(SET_COOKIE)
+            .unwrap()
+            .to_str()
+            .unwrap();
+        assert!(set_cookie.contains("Max-Age=0"));
+        let body: serde_json::Value = json(response).await;
+        assert_eq!(body["revoked"], 2);
+
+        for (cookie, expected) in [
+            (&first, StatusCode::UNAUTHORIZED),
+            (&second, StatusCode::UNAUTHORIZED),
+            (&other, StatusCode::OK),
+        ] {
+            let request = Request::get("/api/auth/me")
+                .header(COOKIE, cookie)
+                .body(Body::empty())
+                .unwrap();
+            let response = router.clone().oneshot(request).await.unwrap();
+            assert_eq!(response.status(), expected);
+        }
+    }
+}
+
+#[sqlx::test]
+async fn secure_cookies(pool: PgPool) {
+    let config = AppConfig {
+        secure_cookies: true,
+        ..AppConfig::default()
+    };
+    let state = app::build_test_app_state_with_config(pool, config).unwrap();
+    let router = api::build_router(state);
+
+    let request = Request::post("/api/auth/register")
+        .header("content-type", "application/json")
+        .body(Body::from(
+            json!({ "username": "secure", "password": "password123" }).to_string(),
+        ))
+        .unwrap();
+    let response = router.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let set_cookie = response
+        .headers()
+        .get(SET_COOKIE)
+        .unwrap()
+        .to_str()
+        .unwrap();
+    assert!(set_cookie.contains("Secure"));
+    let cookie = set_cookie.split(';').next().unwrap().to_string();
+
+    let request = Request::post("/api/user/logout")
+        .header(COOKIE, &cookie)
+        .body(Body::empty())
+        .unwrap();
+    let response = router.oneshot(request).await.unwrap();
+    let set_cookie = response
+        .headers()
+        .get(SET_COOKIE)
+        .unwrap()
+        .to_str()
+        .unwrap();
+    assert!(set_cookie.contains("Max-Age=0"));
+    assert!(set_cookie.contains("Secure"));
+}
+
+#[sqlx::test]
+async fn expired_link_purged_after_visit(pool: PgPool) {
+    const ALIAS: &str = "deadlink";
+
+    let expired_on = OffsetDateTime::now_utc()
+        .date()
+        .saturating_sub(Duration::days(idle_days() + 1));
+    sqlx::query("INSERT INTO links_main (alias, url, last_seen) VALUES ($1, $2, $3)")
+        .bind(ALIAS)
+        .bind("https://example.com/")
+        .bind(expired_on)
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    let state = app::build_test_app_state(pool.clone()).unwrap();
+    let router = api::build_router(state.clone());
+
+    let request = Request::get(format!("/r/{ALIAS}"))
+        .body(Body::empty())
+        .unwrap();
+    let response = router.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::GONE);
+
+    state
+        .maintenance
+        .run(MaintenanceTask::ExpiredPurge, &state)
+        .await
+        .unwrap();
+
+    let remaining: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM links_main WHERE alias = $1")
+        .bind(ALIAS)
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(remaining, 0, "Visited expired link should be purged");
+
+    // The purge drops cached lookups, the alias is retired like after the daily cleanup
+    let alias = Alias::try_from(ALIAS.to_string()).unwrap();
+    assert!(state.cache.get(&alias).await.is_none());
+    let request = Request::get(format!("/r/{ALIAS}"))
+        .body(Body::empty())
+        .unwrap();
+    let response = router.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::GONE);
+}
+
+#[sqlx::test]
+async fn anonymous_link_deleted_with_token(pool: PgPool) {
+    let router = router(pool).await;
+
+    let shorten = |cookie: Option<String>| {
+        let mut request = Request::post("/api/shorten").header("content-type", "application/json");
+        if let Some(cookie) = cookie {
+            request = request.header(COOKIE, cookie);
+        }
+        let request = request
+            .body(Body::from(
+                json!({ "url": "https://example.com" }).to_string(),
+            ))
+            .unwrap();
+        let router = router.clone();
+        async move {
+            let response = router.oneshot(request).await.unwrap();
+            assert_eq!(response.status(), StatusCode::CREATED);
+            let body: api::handlers::ShortenResponse = json(response).await;
+            body
+        }
+    };
+    let remove = |alias: String, token: &str| {
+        let request = Request::delete(format!("/api/links/{alias}"))
+            .header("content-type", "application/json")
+            .body(Body::from(json!({ "token": token }).to_string()))
+            .unwrap();
+        let router = router.clone();
+        async move { router.oneshot(request).await.unwrap().status() }
+    };
+
+    let cookie = register(&router, "owner").await;
+    let owned = shorten(Some(cookie)).await;
+    assert!(
+        owned.deletion_token.is_none(),
+        "Users delete through their account"
+    );
+
+    let anonymous = shorten(None).await;
+    let token = anonymous.deletion_token.unwrap();
+    let alias = anonymous.alias;
+
+    // Missing links and wrong tokens look the same
+    assert_eq!(
+        remove(alias.clone(), "wrong").await,
+        StatusCode::UNAUTHORIZED
+    );
+    assert_eq!(
+        remove("missing1".into(), &token).await,
+        StatusCode::UNAUTHORIZED
+    );
+    assert_eq!(remove(owned.alias, &token).await, StatusCode::UNAUTHORIZED);
+
+    assert_eq!(remove(alias.clone(), &token).await, StatusCode::NO_CONTENT);
+
+    let request = Request::get(format!("/r/{alias}"))
+        .body(Body::empty())
+        .unwrap();
+    let response = router.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::GONE);
+
+    assert_eq!(remove(alias, &token).await, StatusCode::UNAUTHORIZED);
+}
+
+#[sqlx::test]
+async fn shorten_rate_limited_per_ip(pool: PgPool) {
+    let config = AppConfig {
+        rate_limit_per_minute: 1,
+        rate_limit_burst: 2,
+        trust_proxy: true,
+        ..AppConfig::default()
+    };
+    let state = app::build_test_app_state_with_config(pool, config).unwrap();
+    let router = api::build_router(state);
+
+    let shorten = |ip: &str| {
+        let request = Request::post("/api/shorten")
+            .header("content-type", "application/json")
+            .header("x-forwarded-for", ip)
+            .body(Body::from(
+                json!({ "url": "https://example.com" }).to_string(),
+            ))
+            .unwrap();
+        router.clone().oneshot(request)
+    };
+
+    for _ in 0..2 {
+        let response = shorten("203.0.113.1").await.unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+    }
+
+    let response = shorten("203.0.113.1").await.unwrap();
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    let retry_after: u64 = response.headers()["retry-after"]
+        .to_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!((1..=60).contains(&retry_after));
+
+    let response = shorten("203.0.113.2").await.unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+
+    // Reads aren't limited
+    let request = Request::get("/api/recent")
+        .header("x-forwarded-for", "203.0.113.1")
+        .body(Body::empty())
+        .unwrap();
+    let response = router.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[sqlx::test]
+async fn prometheus_metrics(pool: PgPool) {
+    let scrape = |router: Router| async move {
+        let request = Request::get("/metrics").body(Body::empty()).unwrap();
+        router.oneshot(request).await.unwrap()
+    };
+
+    // Not routed unless enabled
+    let response = scrape(router(pool.clone()).await).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    let config = AppConfig {
+        metrics_enabled: true,
+        ..AppConfig::default()
+    };
+    let state = app::build_test_app_state_with_config(pool, config).unwrap();
+    let router = api::build_router(state);
+
+    let request = Request::post("/api/shorten")
+        .header("content-type", "application/json")
+        .body(Body::from(
+            json!({ "url": "https://example.com" }).to_string(),
+        ))
+        .unwrap();
+    let response = router.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let api::handlers::ShortenResponse { alias, .. } = json(response).await;
+
+    for _ in 0..2 {
+        let request = Request::get(format!("/r/{alias}"))
+            .body(Body::empty())
+            .unwrap();
+        let response = router.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::TEMPORARY_REDIRECT);
+    }
+
+    let response = scrape(router).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(
+        response.headers()[CONTENT_TYPE]
+            .to_str()
+            .unwrap()
+            .starts_with("text/plain; version=0.0.4")
+    );
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let body = String::from_utf8(bytes.to_vec()).unwrap();
+
+    for line in [
+        "# TYPE url_shorten_requests_total counter",
+        "url_shorten_requests_total{category=\"shorten\"} 1",
+        "url_shorten_requests_total{category=\"redirect\"} 2",
+        "url_shorten_cache_misses_total 1",
+        "url_shorten_cache_hits_total 1",
+        "url_shorten_cache_hit_ratio 0.5",
+        "url_shorten_hit_flush_batches_total 0",
+    ] {
+        assert!(
+            body.lines().any(|l| l == line),
+            "Missing {line:?} in\n{body}"
+        );
+    }
+    assert!(body.contains("url_shorten_db_pool_connections{state=\"idle\"}"));
+}
+
+#[sqlx::test]
+async fn first_user_admin(pool: PgPool) {
+    let list_users = |router: Router, cookie: String| async move {
+        let request = Request::get("/api/admin/users")
+            .header(COOKIE, cookie)
+            .body(Body::empty())
+            .unwrap();
+        router.oneshot(request).await.unwrap().status()
+    };
+
+    let config = AppConfig {
+        first_user_admin: true,
+        ..AppConfig::default()
+    };
+    let state = app::build_test_app_state_with_config(pool.clone(), config).unwrap();
+    let promoting = api::build_router(state);
+
+    let first = register(&promoting, "founder").await;
+    let second = register(&promoting, "latecomer").await;
+    assert_eq!(list_users(promoting.clone(), first).await, StatusCode::OK);
+    assert_eq!(list_users(promoting, second).await, StatusCode::FORBIDDEN);
+
+    // Without the flag nobody is promoted, even on an empty table
+    sqlx::query("DELETE FROM users_main")
+        .execute(&pool)
+        .await
+        .unwrap();
+    let router = router(pool).await;
+    let first = register(&router, "founder").await;
+    assert_eq!(list_users(router, first).await, StatusCode::FORBIDDEN);
+}
+
+#[sqlx::test]
+async fn admin_runs_maintenance(pool: PgPool) {
+    let expired_on = OffsetDateTime::now_utc()
+        .date()
+        .saturating_sub(Duration::days(idle_days() + 1));
+    for alias in ["stale1", "stale2"] {
+        sqlx::query("INSERT INTO links_main (alias, url, last_seen) VALUES ($1, $2, $3)")
+            .bind(alias)
+            .bind("https://example.com/old")
+            .bind(expired_on)
+            .execute(&pool)
+            .await
+            .unwrap();
+    }
+
+    let router = router(pool.clone()).await;
+    let cookie = register(&router, "regular").await;
+    let admin_cookie = register_admin(&router, &pool, "admin").await;
+
+    let run = |task: &str, cookie: &str| {
+        let request = Request::post(format!("/api/admin/maintenance/{task}"))
+            .header(COOKIE, cookie)
+            .body(Body::empty())
+            .unwrap();
+        router.clone().oneshot(request)
+    };
+
+    let response = run("link_cleanup", &cookie).await.unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    let response = run("no_such_task", &admin_cookie).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    let response = run("link_cleanup", &admin_cookie).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let summary: serde_json::Value = json(response).await;
+    assert_eq!(summary["task"], "link_cleanup");
+    assert_eq!(summary["rows"], 2);
+    assert!(summary["elapsed_ms"].is_u64());
+
+    let remaining: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM links_main")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(remaining, 0);
+
+    let response = run("session_sweep", &admin_cookie).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[sqlx::test]
+async fn configured_link_idle_days(pool: PgPool) {
+    let last_seen = OffsetDateTime::now_utc()
+        .date()
+        .saturating_sub(Duration::days(3));
+    sqlx::query("INSERT INTO links_main (alias, url, last_seen) VALUES ($1, $2, $3)")
+        .bind("quiet")
+        .bind("https://example.com/")
+        .bind(last_seen)
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    let visit = |router: Router| async move {
+        let request = Request::get("/r/quiet").body(Body::empty()).unwrap();
+        router.oneshot(request).await.unwrap().status()
+    };
+
+    assert_eq!(
+        visit(router(pool.clone()).await).await,
+        StatusCode::TEMPORARY_REDIRECT
+    );
+
+    let config = AppConfig {
+        link_idle_days: 2,
+        ..AppConfig::default()
+    };
+    let state = app::build_test_app_state_with_config(pool, config).unwrap();
+    assert_eq!(visit(api::build_router(state)).await, StatusCode::GONE);
+}
@@ -18,6 +18,7 @@ export async function postEmpty<ResponseType = void>(
     try {
       const err = await res.json();
       if (typeof err === "string") reason = err;
+      else if (typeof err?.message === "string") reason = err.message;
     } catch {
       // ignore
     }
@@ -48,6 +49,7 @@ export async function postReq<RequestType, ResponseType = void>(
     try {
       const err = await res.json();
       if (typeof err === "string") reason = err;
+      else if (typeof err?.message === "string") reason = err.message;
     } catch {
       // ignore
     }
@@ -79,6 +81,7 @@ export async function getReq<ResponseType>(
     try {
       const err = await res.json();
       if (typeof err === "string") reason = err;
+      else if (typeof err?.message === "string") reason = err.message;
     } catch {
       // ignore
     }
@@ -102,6 +105,7 @@ export async function deleteReq(path: string, signal?: AbortSignal): Promise<voi
     try {
       const err = await res.json();
       if (typeof err === "string") reason = err;
+      else if (typeof err?.message === "string") reason = err.message;
     } catch {
       // ignore
     }
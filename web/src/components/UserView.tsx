@@ -152,28 +152,42 @@ export function UserView() {
 
 type LinkItem = {
   alias: string;
-  url: string
+  url: string;
+  created_at: string;
+  last_seen: string;
+  hit_count: number;
+};
+
+type LinkPage = {
+  links: LinkItem[];
+  next_cursor: string | null;
 };
 
 function LinksTable() {
   const [links, setLinks] = React.useState<LinkItem[]>([]);
+  const [nextCursor, setNextCursor] = React.useState<string | null>(null);
   const [loading, setLoading] = React.useState(true);
 
   const [removingLink, setRemovingLink] = React.useState(false);
 
   const { notifyOk, notifyErr, notifyShort } = useNotify();
 
+  const loadPage = async (cursor: string | null) => {
+    setLoading(true);
+    try {
+      const query = cursor ? `?cursor=${encodeURIComponent(cursor)}` : "";
+      const page = await getReq<LinkPage>(`/api/user/list${query}`);
+      setLinks((xs) => (cursor ? [...xs, ...page.links] : page.links));
+      setNextCursor(page.next_cursor);
+    } catch (err) {
+      console.error(err);
+    } finally {
+      setLoading(false);
+    }
+  };
+
   React.useEffect(() => {
-    (async () => {
-      try {
-        const data = await getReq<LinkItem[]>("/api/user/list");
-        setLinks(data);
-      } catch (err) {
-        console.error(err);
-      } finally {
-        setLoading(false);
-      }
-    })();
+    loadPage(null);
   }, []);
 
   const copyLink = async (link: LinkItem) => {
@@ -208,7 +222,7 @@ function LinksTable() {
         </Table.Header>
 
         <Table.Body>
-          {loading ? (
+          {loading && links.length === 0 ? (
             <Table.Row>
               <Table.Cell>Loading…</Table.Cell>
             </Table.Row>
@@ -239,6 +253,14 @@ function LinksTable() {
           )}
         </Table.Body>
       </Table.Root>
+
+      {nextCursor && (
+        <Flex justify="center" mt="3">
+          <Button variant="soft" disabled={loading} onClick={() => loadPage(nextCursor)}>
+            Load more
+          </Button>
+        </Flex>
+      )}
     </Inset>
   );
 }
\ No newline at end of file